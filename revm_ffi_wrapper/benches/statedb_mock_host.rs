@@ -0,0 +1,69 @@
+//! Cost of a committed StateDB call served by the in-memory host, with and
+//! without a per-callback latency standing in for the Go boundary.
+//!
+//! Run with `cargo bench --features test-utils --bench statedb_mock_host`.
+//! `GoDatabase` prints every commit to stdout; filter out its `[Rust]` lines.
+
+use std::ffi::CString;
+use std::time::{Duration, Instant};
+
+use revm::bytecode::Bytecode;
+use revm::primitives::{Address, Bytes, U256};
+use revm::state::AccountInfo;
+use revm_ffi::*;
+
+/// `SSTORE(0, SLOAD(0) + 1); STOP`
+const COUNTER: [u8; 8] = [0x5f, 0x54, 0x60, 0x01, 0x01, 0x5f, 0x55, 0x00];
+
+const CALLER: Address = Address::repeat_byte(0xaa);
+const CONTRACT: Address = Address::repeat_byte(0xcc);
+
+fn run(label: &str, latency: Duration, calls: u64) {
+    let host = MockHost::new();
+    host.insert_account(CALLER, AccountInfo::from_balance(U256::from(10u64.pow(18))));
+    host.insert_account(
+        CONTRACT,
+        AccountInfo::from_bytecode(Bytecode::new_raw(Bytes::from_static(&COUNTER))),
+    );
+    host.set_latency(latency);
+    let from = CString::new(format!("{CALLER:#x}")).unwrap();
+    let to = CString::new(format!("{CONTRACT:#x}")).unwrap();
+
+    let start = Instant::now();
+    unsafe {
+        let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+        for _ in 0..calls {
+            let result = revm_call_contract_statedb_commit(
+                instance,
+                from.as_ptr(),
+                to.as_ptr(),
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+                100_000,
+            );
+            assert!(!result.is_null() && (*result).success == 1);
+            revm_free_execution_result(result);
+        }
+        revm_free_statedb_instance(instance);
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(host.storage(CONTRACT, U256::ZERO), U256::from(calls));
+
+    let reads = [HostCall::Basic, HostCall::Storage, HostCall::Code]
+        .map(|call| host.calls(call))
+        .iter()
+        .sum::<usize>();
+    let writes = host.calls(HostCall::SetBasic) + host.calls(HostCall::SetStorage);
+    println!("{label}:");
+    println!("   Calls: {calls}");
+    println!("   Duration: {:.2}ms", elapsed.as_secs_f64() * 1e3);
+    println!("   Calls/sec: {:.2}", calls as f64 / elapsed.as_secs_f64());
+    println!("   Host reads/call: {:.1}", reads as f64 / calls as f64);
+    println!("   Host writes/call: {:.1}", writes as f64 / calls as f64);
+}
+
+fn main() {
+    run("No host latency", Duration::ZERO, 10_000);
+    run("50us host latency", Duration::from_micros(50), 500);
+}
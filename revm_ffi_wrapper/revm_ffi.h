@@ -22,6 +22,16 @@ typedef struct {
     uint32_t max_code_size;             // Maximum contract code size (0 for default 24KB limit)
 } RevmConfigFFI;
 
+// Options for EIP-3155 tracing; zero-initialised captures everything
+typedef struct {
+    bool disable_memory;                // Omit the "memory" field
+    bool disable_stack;                 // Report "stack" as null
+    bool disable_storage;               // Omit the "storage" field (geth never prints it)
+} TraceOptionsFFI;
+
+// Receives one EIP-3155 trace line (JSON without trailing newline)
+typedef void (*TraceCallback)(void* user_data, const char* line);
+
 // Predefined chain configurations
 typedef enum {
     ETHEREUM_MAINNET = 0,               // Ethereum mainnet (chain ID 1)
@@ -84,6 +94,155 @@ DeploymentResultFFI* revm_deploy_contract(
     uint64_t gas_limit
 );
 
+// Checkpoints (in-memory instances only)
+// revm_snapshot returns a checkpoint id (0 on error). revm_revert_to restores
+// it and drops that checkpoint and all newer ones. Returns 0 on success.
+uint64_t revm_snapshot(RevmInstance* instance);
+int revm_revert_to(RevmInstance* instance, uint64_t snapshot_id);
+
+// Copy-on-write fork of an in-memory instance. The fork shares the parent's
+// current state and keeps its own writes. Free with revm_free.
+RevmInstance* revm_fork_instance(RevmInstance* parent);
+
+// Merkle-Patricia roots (in-memory instances only). Return 0x-prefixed hex
+// strings; free with revm_free_string. Recomputation is incremental.
+char* revm_state_root(RevmInstance* instance);
+char* revm_storage_root(RevmInstance* instance, const char* address);
+
+// eth_getProof-style proof for an account and `slots_len` storage keys (hex
+// strings). Returns a JSON string (free with revm_free_string) or NULL.
+char* revm_get_proof(
+    RevmInstance* instance,
+    const char* address,
+    const char* const* slots,
+    unsigned int slots_len
+);
+
+// Paged state enumeration in hashed-key order (debug_accountRange /
+// debug_storageRangeAt style). `start` is a hashed-key cursor or NULL for the
+// first page; max_results 0 means 256, the cap for account pages. Return
+// JSON strings; free with revm_free_string.
+char* revm_account_range(
+    RevmInstance* instance,
+    const char* start,
+    unsigned int max_results,
+    bool include_code,
+    bool include_storage
+);
+char* revm_storage_range(
+    RevmInstance* instance,
+    const char* address,
+    const char* start,
+    unsigned int max_results
+);
+
+// Shadow execution: run the pending transaction (see revm_set_tx) and compare
+// it with the receipt and state diff the Go EVM produced, given as JSON.
+// Returns a JSON report {"matches": bool, "mismatches": [...]} (free with
+// revm_free_string) or NULL if execution failed. Commits when `commit`.
+char* revm_execute_shadow(
+    RevmInstance* instance,
+    const char* expected_json,
+    bool commit
+);
+
+// Execution witnesses. revm_execute_with_witness runs the pending transaction
+// and returns {"success","gasUsed","witness"} as JSON, where the witness lists
+// every account, storage slot, bytecode and block hash read with its value
+// (free with revm_free_string). revm_execute_stateless runs the pending
+// transaction from such a witness alone; reads outside it fail the call.
+char* revm_execute_with_witness(RevmInstance* instance, bool commit);
+ExecutionResultFFI* revm_execute_stateless(RevmInstance* instance, const char* witness_json);
+
+// Block witnesses. Between revm_begin_block_witness and
+// revm_take_block_witness, the witness of every execution committed through
+// revm_execute_with_witness is merged into one witness of the pre-block state,
+// returned as JSON by revm_take_block_witness (free with revm_free_string).
+// Executions committed through any other call are not collected, so run
+// every transaction of the block through revm_execute_with_witness.
+int revm_begin_block_witness(RevmInstance* instance);
+char* revm_take_block_witness(RevmInstance* instance);
+
+// Preimage recording (geth's --cache.preimages). revm_execute_with_preimages
+// runs the pending transaction and returns {"success","gasUsed","preimages"}
+// as JSON, mapping the hash of every KECCAK256 computed and every address and
+// storage slot touched to its preimage (free with revm_free_string).
+// revm_execute_stream_preimages passes each new preimage to the callback
+// instead; hash and data are only valid during the call.
+typedef void (*PreimageCallback)(void* user_data, const unsigned char* hash,
+                                 const unsigned char* data, size_t len);
+char* revm_execute_with_preimages(RevmInstance* instance, bool commit);
+ExecutionResultFFI* revm_execute_stream_preimages(
+    RevmInstance* instance,
+    bool commit,
+    PreimageCallback callback,
+    void* user_data
+);
+
+// Block hash history for BLOCKHASH. revm_set_block_hash registers one hash;
+// revm_advance_block records the hash of the current block, moves to the next
+// one and keeps a rolling window of the last 256 hashes. Under Prague the
+// hashes within the 8191-block serve window are also written to the EIP-2935
+// history contract once deployed (revm_deploy_history_contract deploys it).
+int revm_set_block_hash(RevmInstance* instance, uint64_t number, const char* hash);
+void revm_set_block(RevmInstance* instance, uint64_t number, uint64_t timestamp);
+int revm_advance_block(RevmInstance* instance, const char* hash, uint64_t timestamp);
+void revm_deploy_history_contract(RevmInstance* instance);
+
+// EIP-3155 tracing in the format of `geth evm --json`. Once set, every
+// execution (revm_execute, revm_call_contract, ...) writes one JSON line per
+// opcode and a summary line per transaction to the file (truncated) or the
+// callback. options may be NULL.
+int revm_set_tracer(RevmInstance* instance, const char* path, const TraceOptionsFFI* options);
+int revm_set_tracer_callback(RevmInstance* instance, TraceCallback callback, void* user_data,
+                             const TraceOptionsFFI* options);
+void revm_clear_tracer(RevmInstance* instance);
+
+// Gas profiling. Once started, every execution is aggregated by opcode, by
+// contract and by call stack until revm_stop_profiling. revm_profile_report
+// returns {"transactions","gas","timeNs","opcodes":[...],"contracts":[...]}
+// with rows sorted by gas; revm_profile_folded returns folded stacks
+// ("frame;frame;OPCODE weight" lines) for flamegraph tools, weighted by gas
+// or by nanoseconds. Both return NULL when profiling is off; free with
+// revm_free_string. Intrinsic gas and refunds are not attributed.
+void revm_start_profiling(RevmInstance* instance);
+char* revm_profile_report(RevmInstance* instance);
+char* revm_profile_folded(RevmInstance* instance, bool by_time);
+void revm_stop_profiling(RevmInstance* instance);
+
+// geth's callTracer. Runs the pending transaction and returns the top call
+// frame as geth's JSON (free with revm_free_string). config_json is the
+// tracerConfig object, e.g. {"withLog":true,"onlyTopCall":false}, or NULL.
+char* revm_execute_call_trace(RevmInstance* instance, const char* config_json, bool commit);
+
+// geth's prestateTracer. Returns the pre-state of every account the pending
+// transaction touched (balance, nonce, code, storage read) as JSON, or with
+// {"diffMode":true} a {"post","pre"} pair of what changed (free with
+// revm_free_string). Also accepts disableCode and disableStorage.
+char* revm_execute_prestate_trace(RevmInstance* instance, const char* config_json, bool commit);
+
+// Parity/OpenEthereum-style traces. revm_trace_replay_transaction returns
+// {"output","stateDiff","trace","vmTrace"} for the requested trace types, e.g.
+// ["trace","vmTrace","stateDiff"] (NULL means ["trace"]).
+// revm_trace_transaction returns the flat trace localized with the block
+// hash, transaction hash and position; committing each transaction of a block
+// in order and concatenating the arrays gives a reward-free trace_block.
+char* revm_trace_replay_transaction(RevmInstance* instance, const char* trace_types_json,
+                                    bool commit);
+char* revm_trace_transaction(RevmInstance* instance, const char* block_hash, const char* tx_hash,
+                             uint64_t tx_position, bool commit);
+
+// Runs the pending transaction under a geth tracer chosen by name and returns
+// its debug_traceTransaction JSON (free with revm_free_string): "callTracer",
+// "prestateTracer", "4byteTracer" (selector-calldata size counts),
+// "noopTracer" ({}) or "muxTracer", whose config maps tracer names to their
+// configs and whose result maps them to their outputs. tracer_config_json may
+// be NULL. Built with the js-tracer feature, any other tracer_name is run as a
+// geth JavaScript tracer (an object with result, fault and optionally step,
+// enter, exit and setup), with tracer_config_json passed to setup.
+char* revm_trace(RevmInstance* instance, const char* tracer_name, const char* tracer_config_json,
+                 bool commit);
+
 // Transaction execution
 ExecutionResultFFI* revm_call_contract(
     RevmInstance* instance,
@@ -148,6 +307,60 @@ RevmInstanceStateDB* revm_new_with_statedb(size_t handle, const RevmConfigFFI* c
 // Destroy a StateDB-backed instance created with `revm_new_with_statedb`.
 void revm_free_statedb_instance(RevmInstanceStateDB* instance);
 
+// Rebind an instance to another StateDB handle (new block or historical
+// state). Configuration and cached bytecode are kept. Returns 0 on success.
+int revm_statedb_set_handle(RevmInstanceStateDB* instance, size_t handle);
+
+// Record every re_state_* request/response, write, config and tx env of the
+// instance to `path` (JSON lines) for replay without Go. Returns 0 on success.
+// Entries are buffered; stopping flushes them and returns -1 (see
+// revm_get_last_error) if any write failed.
+int revm_statedb_start_recording(RevmInstanceStateDB* instance, const char* path);
+int revm_statedb_stop_recording(RevmInstanceStateDB* instance);
+
+// Pending transaction of a StateDB-backed instance, run by the
+// revm_statedb_execute_* functions; see revm_set_tx.
+int revm_statedb_set_tx(
+    RevmInstanceStateDB* instance,
+    const char* caller,
+    const char* to,
+    const char* value,
+    const unsigned char* data,
+    unsigned int data_len,
+    unsigned int gas_limit,
+    const char* gas_price,
+    unsigned int nonce
+);
+
+// revm_execute_shadow against the StateDB; commits go through re_state_set_*.
+char* revm_statedb_execute_shadow(
+    RevmInstanceStateDB* instance,
+    const char* expected_json,
+    bool commit
+);
+
+// Witnesses of StateDB executions; see revm_execute_with_witness and
+// revm_begin_block_witness.
+char* revm_statedb_execute_with_witness(RevmInstanceStateDB* instance, bool commit);
+int revm_statedb_begin_block_witness(RevmInstanceStateDB* instance);
+char* revm_statedb_take_block_witness(RevmInstanceStateDB* instance);
+
+// Preimage recording of StateDB executions; see revm_execute_with_preimages.
+char* revm_statedb_execute_with_preimages(RevmInstanceStateDB* instance, bool commit);
+ExecutionResultFFI* revm_statedb_execute_stream_preimages(
+    RevmInstanceStateDB* instance,
+    bool commit,
+    PreimageCallback callback,
+    void* user_data
+);
+
+// EIP-3155 tracing of StateDB executions; see revm_set_tracer.
+int revm_statedb_set_tracer(RevmInstanceStateDB* instance, const char* path,
+                            const TraceOptionsFFI* options);
+int revm_statedb_set_tracer_callback(RevmInstanceStateDB* instance, TraceCallback callback,
+                                     void* user_data, const TraceOptionsFFI* options);
+void revm_statedb_clear_tracer(RevmInstanceStateDB* instance);
+
 // Execute a CALL against a contract using a StateDB-backed instance
 ExecutionResultFFI* revm_call_contract_statedb(
     RevmInstanceStateDB* instance,
@@ -191,8 +404,136 @@ typedef struct {
 int re_state_set_basic(size_t handle, FFIAddress addr, FFIAccountInfo info);
 int re_state_set_storage(size_t handle, FFIAddress addr, FFIHash slot, FFIU256 value);
 
+// ---------------- host inspector callbacks ----------------
+typedef enum {
+    FRAME_KIND_CALL = 0,
+    FRAME_KIND_STATIC_CALL = 1,
+    FRAME_KIND_DELEGATE_CALL = 2,
+    FRAME_KIND_CALL_CODE = 3,
+    FRAME_KIND_CREATE = 4,
+    FRAME_KIND_CREATE2 = 5
+} FrameKindFFI;
+
+typedef struct {
+    unsigned int depth;                 // 1 for the transaction's top frame
+    FrameKindFFI kind;
+    FFIAddress caller;
+    FFIAddress target;                  // Address to be created for creates
+    FFIAddress code_address;            // Differs from target for DELEGATECALL/CALLCODE
+    FFIU256 value;
+    uint64_t gas_limit;
+    const unsigned char* input;         // Calldata, or init code for creates
+    unsigned int input_len;
+} FrameEnterFFI;
+
+typedef struct {
+    unsigned int depth;
+    bool success;
+    bool reverted;                      // REVERT rather than an exceptional halt
+    uint64_t gas_used;
+    const unsigned char* output;
+    unsigned int output_len;
+    FFIAddress created_address;         // Zero unless a create succeeded
+    const char* error;                  // geth's error message, NULL on success
+} FrameExitFFI;
+
+typedef struct {
+    unsigned int depth;
+    FFIAddress address;
+    const FFIHash* topics;
+    unsigned int topics_count;
+    const unsigned char* data;
+    unsigned int data_len;
+} LogEventFFI;
+
+typedef struct {
+    unsigned int depth;
+    FFIAddress contract;
+    FFIAddress beneficiary;
+    FFIU256 value;
+} SelfDestructFFI;
+
+typedef struct {
+    unsigned int depth;
+    uint64_t pc;
+    uint8_t opcode;
+    uint64_t gas_remaining;             // Gas left before the opcode
+    FFIAddress address;
+    const FFIU256* stack;               // Bottom first
+    unsigned int stack_len;
+    const unsigned char* memory;
+    unsigned int memory_len;
+} StepFFI;
+
+typedef void (*FrameEnterCallback)(void* user_data, const FrameEnterFFI* frame);
+typedef void (*FrameExitCallback)(void* user_data, const FrameExitFFI* frame);
+typedef void (*LogCallback)(void* user_data, const LogEventFFI* log);
+typedef void (*SelfDestructCallback)(void* user_data, const SelfDestructFFI* selfdestruct);
+typedef void (*StepCallback)(void* user_data, const StepFFI* step);
+
+// Any callback may be NULL; on_step runs before every opcode, so leave it
+// unset unless needed.
+typedef struct {
+    FrameEnterCallback on_enter;
+    FrameExitCallback on_exit;
+    LogCallback on_log;
+    SelfDestructCallback on_selfdestruct;
+    StepCallback on_step;
+} InspectorCallbacksFFI;
+
+// Invoke callbacks with user_data during every following execution. The
+// records passed are only valid during the callback. Returns 0 on success.
+int revm_set_inspector_callbacks(
+    RevmInstance* instance,
+    const InspectorCallbacksFFI* callbacks,
+    void* user_data);
+void revm_clear_inspector_callbacks(RevmInstance* instance);
+int revm_statedb_set_inspector_callbacks(
+    RevmInstanceStateDB* instance,
+    const InspectorCallbacksFFI* callbacks,
+    void* user_data);
+void revm_statedb_clear_inspector_callbacks(RevmInstanceStateDB* instance);
+
+// ---------------- streamed logs ----------------
+typedef struct {
+    unsigned int index;                 // Position among the transaction's logs
+    unsigned int depth;                 // 1 for the transaction's top frame
+    bool reverted;                      // Set when delivered again after an enclosing frame failed
+    FFIAddress address;
+    const FFIHash* topics;
+    unsigned int topics_count;
+    const unsigned char* data;
+    unsigned int data_len;
+} StreamedLogFFI;
+
+typedef void (*StreamedLogCallback)(void* user_data, const StreamedLogFFI* log);
+
+// Deliver each log to callback as it is emitted. Logs of frames that later
+// revert or halt are delivered a second time, same index, with reverted set.
+// The record is only valid during the callback. Returns 0 on success.
+int revm_set_log_callback(RevmInstance* instance, StreamedLogCallback callback, void* user_data);
+void revm_clear_log_callback(RevmInstance* instance);
+int revm_statedb_set_log_callback(
+    RevmInstanceStateDB* instance,
+    StreamedLogCallback callback,
+    void* user_data);
+void revm_statedb_clear_log_callback(RevmInstanceStateDB* instance);
+
+// ---------------- revert diagnostics ----------------
+// Record where each following execution reverts or halts: address, depth,
+// pc, opcode, remaining gas, top 8 stack items and the first 256 bytes of
+// memory of every failing frame. Much cheaper than an EIP-3155 trace.
+void revm_enable_revert_diagnostics(RevmInstance* instance);
+// JSON array of the last execution's failing instructions, innermost first;
+// [] if it succeeded. Free with revm_free_string. NULL if not enabled.
+char* revm_revert_points(RevmInstance* instance);
+void revm_disable_revert_diagnostics(RevmInstance* instance);
+void revm_statedb_enable_revert_diagnostics(RevmInstanceStateDB* instance);
+char* revm_statedb_revert_points(RevmInstanceStateDB* instance);
+void revm_statedb_disable_revert_diagnostics(RevmInstanceStateDB* instance);
+
 #ifdef __cplusplus
 }
 #endif
 
-#endif // REVM_FFI_H 
\ No newline at end of file
+#endif // REVM_FFI_H
@@ -0,0 +1,149 @@
+//! Replay a range of stored blocks through REVM and check them against the
+//! chain: `revm-replay --datadir <chaindata> --from N --to M`.
+//!
+//! By default execution starts from the state persisted for block `N - 1`.
+//! With `--genesis`, state is instead built in memory from the genesis alloc
+//! and blocks `1..N` are replayed first, which also enables the state root
+//! check.
+
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use revm::primitives::hardfork::SpecId;
+use revm_ffi::{
+    genesis_instance, replay_range, BlockReport, ChainData, DiskBackend, ReplayBackend,
+    ReplayConfig,
+};
+
+const USAGE: &str = "usage: revm-replay --datadir <chaindata> --from <N> --to <M> \
+                     [--genesis <genesis.json>] [--chain-id <id>] [--spec <hardfork>] \
+                     [--no-parlia]";
+
+struct Args {
+    datadir: String,
+    from: u64,
+    to: u64,
+    genesis: Option<String>,
+    config: ReplayConfig,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut datadir = None;
+    let mut from = None;
+    let mut to = None;
+    let mut genesis = None;
+    let mut config = ReplayConfig {
+        chain_id: 56,
+        spec: SpecId::CANCUN,
+        parlia: true,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{flag} needs a value"));
+        match flag.as_str() {
+            "--datadir" => datadir = Some(value()?),
+            "--from" => from = Some(value()?.parse()?),
+            "--to" => to = Some(value()?.parse()?),
+            "--genesis" => genesis = Some(value()?),
+            "--chain-id" => config.chain_id = value()?.parse()?,
+            "--no-parlia" => config.parlia = false,
+            "--spec" => {
+                let name = value()?;
+                config.spec = name
+                    .parse()
+                    .map_err(|_| anyhow!("unknown hardfork {name}"))?;
+            }
+            _ => bail!("unknown argument {flag}"),
+        }
+    }
+
+    let (Some(datadir), Some(from), Some(to)) = (datadir, from, to) else {
+        bail!("--datadir, --from and --to are required");
+    };
+    if from == 0 || from > to {
+        bail!("expected 0 < from <= to");
+    }
+    Ok(Args {
+        datadir,
+        from,
+        to,
+        genesis,
+        config,
+    })
+}
+
+fn print_report(report: &BlockReport) {
+    if report.is_ok() {
+        println!(
+            "block {} {} ok ({} txs, {} gas)",
+            report.number, report.hash, report.transactions, report.gas_used
+        );
+        return;
+    }
+    println!("block {} {} DIVERGED", report.number, report.hash);
+    for m in &report.mismatches {
+        println!("  {}: expected {}, got {}", m.field, m.expected, m.actual);
+    }
+    if let Some(tx) = &report.divergent_tx {
+        println!("  first divergent transaction #{} {}", tx.index, tx.hash);
+        println!("    from {}", tx.sender);
+        match tx.to {
+            Some(to) => println!("    to   {to}"),
+            None => println!("    to   (contract creation)"),
+        }
+        println!("    revm {}", tx.outcome);
+        for m in &tx.mismatches {
+            println!("    {}: expected {}, got {}", m.field, m.expected, m.actual);
+        }
+    }
+}
+
+fn run<B: ReplayBackend>(chain: &ChainData, backend: &mut B, args: &Args) -> Result<bool> {
+    let last = replay_range(
+        chain,
+        backend,
+        args.from,
+        args.to,
+        args.config,
+        print_report,
+    )?;
+    Ok(last.is_some_and(|report| report.is_ok()))
+}
+
+fn main() -> ExitCode {
+    let result = parse_args().and_then(|args| {
+        let chain = Arc::new(ChainData::open(&args.datadir)?);
+        match &args.genesis {
+            Some(genesis) => {
+                let mut instance = genesis_instance(genesis, args.config)?;
+                if args.from > 1 {
+                    let warmup =
+                        replay_range(&chain, &mut instance, 1, args.from - 1, args.config, |_| {})?;
+                    if let Some(report) = warmup.filter(|r| !r.is_ok()) {
+                        print_report(&report);
+                        return Ok(false);
+                    }
+                }
+                run(&chain, &mut instance, &args)
+            }
+            None => {
+                let parent = chain
+                    .header(args.from - 1)?
+                    .with_context(|| format!("header {} not found", args.from - 1))?;
+                let mut backend = DiskBackend::new(chain.clone(), parent.state_root, args.config)?;
+                run(&chain, &mut backend, &args)
+            }
+        }
+    });
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("error: {err:#}\n{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Block hash history for in-memory instances.
+//!
+//! BLOCKHASH reads hashes from the database, and an in-memory instance has
+//! no chain behind it, so hashes are registered explicitly: one at a time
+//! with [`RevmInstance::set_block_hash`], or by closing blocks with
+//! [`RevmInstance::advance_block`], which keeps a rolling window of the last
+//! 256.  A block that was never registered hashes to zero.  From Prague on,
+//! registered hashes still inside the EIP-2935 serve window are also written
+//! to the history contract once it is deployed, as the pre-block system call
+//! does;
+//! [`RevmInstance::deploy_history_contract`] deploys it on instances that do
+//! not start from a chain state.
+
+use alloy_eips::eip2935::{HISTORY_SERVE_WINDOW, HISTORY_STORAGE_ADDRESS, HISTORY_STORAGE_CODE};
+use revm::{
+    bytecode::Bytecode,
+    database_interface::DatabaseRef,
+    primitives::{hardfork::SpecId, keccak256, B256, U256},
+    state::AccountInfo,
+};
+
+use crate::types::RevmInstance;
+
+/// Number of most recent blocks whose hashes BLOCKHASH can return.
+pub const BLOCK_HASH_WINDOW: u64 = 256;
+
+impl RevmInstance {
+    /// Register `hash` as the hash of block `number`.  The history contract
+    /// only gets it if `number` is at most `HISTORY_SERVE_WINDOW` blocks
+    /// behind the current block.
+    pub fn set_block_hash(&mut self, number: u64, hash: B256) {
+        self.evm
+            .ctx
+            .journaled_state
+            .database
+            .cache
+            .block_hashes
+            .insert(U256::from(number), hash);
+        let served = self
+            .evm
+            .ctx
+            .block
+            .number
+            .checked_sub(number)
+            .is_some_and(|age| age <= HISTORY_SERVE_WINDOW as u64);
+        if served
+            && self.evm.ctx.cfg.spec.is_enabled_in(SpecId::PRAGUE)
+            && self.has_history_contract()
+        {
+            self.store_history_hash(number, hash);
+        }
+    }
+
+    /// Close the current block under `hash` and move the block env to the
+    /// next one with `timestamp`.  Hashes that fall out of the BLOCKHASH
+    /// window are dropped from the write overlay; those in frozen fork layers
+    /// go when the layers are merged, and BLOCKHASH never reads them anyway.
+    pub fn advance_block(&mut self, hash: B256, timestamp: u64) {
+        let number = self.evm.ctx.block.number;
+        self.set_block_hash(number, hash);
+
+        let block = &mut self.evm.ctx.block;
+        block.number = number + 1;
+        block.timestamp = timestamp;
+
+        let oldest = U256::from(block.number.saturating_sub(BLOCK_HASH_WINDOW));
+        self.evm
+            .ctx
+            .journaled_state
+            .database
+            .cache
+            .block_hashes
+            .retain(|number, _| *number >= oldest);
+    }
+
+    /// Deploy the EIP-2935 history contract if it is not there yet.
+    pub fn deploy_history_contract(&mut self) {
+        if self.has_history_contract() {
+            return;
+        }
+        let db = &mut self.evm.ctx.journaled_state.database;
+        let Ok(existing) = db.basic_ref(HISTORY_STORAGE_ADDRESS);
+        let existing = existing.unwrap_or_default();
+        db.insert_account_info(
+            HISTORY_STORAGE_ADDRESS,
+            AccountInfo {
+                nonce: existing.nonce.max(1),
+                code_hash: keccak256(&HISTORY_STORAGE_CODE),
+                code: Some(Bytecode::new_raw(HISTORY_STORAGE_CODE.clone())),
+                ..existing
+            },
+        );
+        self.touch_account(HISTORY_STORAGE_ADDRESS);
+    }
+
+    fn has_history_contract(&self) -> bool {
+        let db = &self.evm.ctx.journaled_state.database;
+        let Ok(account) = db.basic_ref(HISTORY_STORAGE_ADDRESS);
+        account.is_some_and(|account| account.code_hash == keccak256(&HISTORY_STORAGE_CODE))
+    }
+
+    /// Mirror the EIP-2935 system call: the history contract keeps the hash
+    /// of block `number` in slot `number % HISTORY_SERVE_WINDOW`.
+    fn store_history_hash(&mut self, number: u64, hash: B256) {
+        let slot = U256::from(number % HISTORY_SERVE_WINDOW as u64);
+        let value = U256::from_be_bytes(hash.0);
+        let db = &mut self.evm.ctx.journaled_state.database;
+        let Ok(()) = db.insert_account_storage(HISTORY_STORAGE_ADDRESS, slot, value);
+        self.touch_slot(HISTORY_STORAGE_ADDRESS, slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use alloy_eips::eip2935::{HISTORY_SERVE_WINDOW, HISTORY_STORAGE_ADDRESS};
+    use revm::bytecode::Bytecode;
+    use revm::database_interface::DatabaseRef;
+    use revm::handler::ExecuteEvm;
+    use revm::primitives::{keccak256, Address, Bytes, TxKind, B256, U256};
+    use revm::state::AccountInfo;
+    use std::ffi::CString;
+
+    /// `RETURN(BLOCKHASH(CALLDATALOAD(0)))`
+    const RUNTIME: [u8; 9] = [0x5f, 0x35, 0x40, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xf3];
+
+    fn hash_of(number: u64) -> B256 {
+        keccak256(number.to_be_bytes())
+    }
+
+    unsafe fn lookup(instance: &mut RevmInstance, target: Address, number: u64) -> B256 {
+        let ctx = &mut instance.evm.ctx;
+        ctx.tx.caller = Address::repeat_byte(0xaa);
+        ctx.tx.kind = TxKind::Call(target);
+        ctx.tx.data = Bytes::from(U256::from(number).to_be_bytes_vec());
+        ctx.tx.gas_limit = 100_000;
+        ctx.tx.nonce = 0;
+        let outcome = instance.evm.replay().unwrap();
+        assert!(outcome.result.is_success());
+        B256::from_slice(outcome.result.output().unwrap())
+    }
+
+    #[test]
+    fn serves_rolling_window_and_history_contract() {
+        let contract = Address::repeat_byte(0xcc);
+
+        unsafe {
+            let instance = revm_new();
+            let inst = &mut *instance;
+            let db = &mut inst.evm.ctx.journaled_state.database;
+            db.insert_account_info(
+                contract,
+                AccountInfo::from_bytecode(Bytecode::new_raw(Bytes::from_static(&RUNTIME))),
+            );
+
+            revm_set_block(instance, 100, 1_000);
+            let hash = CString::new(hash_of(99).to_string()).unwrap();
+            assert_eq!(revm_set_block_hash(instance, 99, hash.as_ptr()), 0);
+            assert_eq!(lookup(inst, contract, 99), hash_of(99));
+            // Inside the window but never registered.
+            assert_eq!(lookup(inst, contract, 50), B256::ZERO);
+            // Without the history contract nothing is written to state.
+            let Ok(history) = inst
+                .evm
+                .ctx
+                .journaled_state
+                .database
+                .basic_ref(HISTORY_STORAGE_ADDRESS);
+            assert!(history.is_none());
+
+            revm_deploy_history_contract(instance);
+            for number in 100..400 {
+                let hash = CString::new(hash_of(number).to_string()).unwrap();
+                assert_eq!(
+                    revm_advance_block(instance, hash.as_ptr(), 1_000 + number),
+                    0
+                );
+            }
+            assert_eq!(inst.evm.ctx.block.number, 400);
+            assert_eq!(inst.evm.ctx.block.timestamp, 1_399);
+            assert_eq!(lookup(inst, contract, 399), hash_of(399));
+            assert_eq!(lookup(inst, contract, 144), hash_of(144));
+            assert_eq!(lookup(inst, contract, 143), B256::ZERO);
+            let block_hashes = &inst.evm.ctx.journaled_state.database.cache.block_hashes;
+            assert_eq!(block_hashes.len(), BLOCK_HASH_WINDOW as usize);
+
+            // EIP-2935 serves hashes beyond the BLOCKHASH window from state.
+            assert_eq!(lookup(inst, HISTORY_STORAGE_ADDRESS, 120), hash_of(120));
+            assert_eq!(lookup(inst, HISTORY_STORAGE_ADDRESS, 399), hash_of(399));
+
+            // Hashes outside the serve window never reach the contract.
+            revm_set_block(instance, 10_000, 11_000);
+            for number in [1_000, 20_000] {
+                let hash = CString::new(hash_of(number).to_string()).unwrap();
+                assert_eq!(revm_set_block_hash(instance, number, hash.as_ptr()), 0);
+                let slot = U256::from(number % HISTORY_SERVE_WINDOW as u64);
+                let db = &inst.evm.ctx.journaled_state.database;
+                let Ok(value) = db.storage_ref(HISTORY_STORAGE_ADDRESS, slot);
+                assert_eq!(value, U256::ZERO);
+            }
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn forked_window_stays_bounded() {
+        let contract = Address::repeat_byte(0xcc);
+
+        unsafe {
+            let instance = revm_new();
+            let inst = &mut *instance;
+            let db = &mut inst.evm.ctx.journaled_state.database;
+            db.insert_account_info(
+                contract,
+                AccountInfo::from_bytecode(Bytecode::new_raw(Bytes::from_static(&RUNTIME))),
+            );
+
+            // Fork every ten blocks, so most hashes are frozen before they
+            // leave the window.
+            for number in 0..2_000 {
+                inst.advance_block(hash_of(number), number);
+                if number % 10 == 9 {
+                    drop(inst.fork());
+                }
+            }
+            let db = &inst.evm.ctx.journaled_state.database;
+            let held: usize = fork::layers(db).iter().map(|cache| cache.block_hashes.len()).sum();
+            assert!(held <= BLOCK_HASH_WINDOW as usize + 10 * fork::MAX_FORK_DEPTH);
+
+            assert_eq!(lookup(inst, contract, 1_999), hash_of(1_999));
+            assert_eq!(lookup(inst, contract, 1_744), hash_of(1_744));
+            assert_eq!(lookup(inst, contract, 1_743), B256::ZERO);
+            revm_free(instance);
+        }
+    }
+}
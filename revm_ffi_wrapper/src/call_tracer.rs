@@ -0,0 +1,400 @@
+//! geth's `callTracer`.
+//!
+//! [`CallTracer`] rebuilds the call tree of a transaction and renders it
+//! exactly like geth's `debug_traceTransaction` with `"tracer":
+//! "callTracer"`: nested frames with type, from, to, value, gas, gasUsed,
+//! input and output, the error and decoded revert reason of failed frames
+//! and, with `withLog`, the logs each frame emitted.  `onlyTopCall` keeps
+//! the outermost frame only.
+
+use anyhow::Result;
+use revm::{
+    context_interface::{result::ExecutionResult, ContextTr, Transaction},
+    inspector::Inspector,
+    interpreter::{
+        CallInputs, CallOutcome, CallScheme, CreateInputs, CreateOutcome, CreateScheme,
+        InstructionResult, Interpreter, InterpreterResult,
+    },
+    primitives::{alloy_primitives::U64, Address, Bytes, Log, B256, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::eip3155::geth_error;
+use crate::types::RevmInstance;
+
+/// `callTracer` options, as passed in geth's `tracerConfig`.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CallTracerConfig {
+    pub only_top_call: bool,
+    pub with_log: bool,
+}
+
+/// A log emitted by a frame.  `position` is the number of sub-calls the
+/// frame had made when the log was emitted.
+#[derive(Clone, Debug, Serialize)]
+pub struct CallLog {
+    pub address: Address,
+    pub topics: Vec<B256>,
+    pub data: Bytes,
+    pub position: U64,
+}
+
+/// One call frame, in geth's field order.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    pub from: Address,
+    pub gas: U64,
+    pub gas_used: U64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Address>,
+    pub input: Bytes,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<Bytes>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<CallLog>,
+    /// Absent for STATICCALL, as in geth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<U256>,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+impl CallFrame {
+    fn new(kind: &'static str, from: Address, to: Option<Address>, gas: u64) -> Self {
+        Self {
+            from,
+            gas: U64::from(gas),
+            gas_used: U64::ZERO,
+            to,
+            input: Bytes::new(),
+            output: None,
+            error: None,
+            revert_reason: None,
+            calls: Vec::new(),
+            logs: Vec::new(),
+            value: None,
+            kind,
+        }
+    }
+
+    /// Record how the frame ended, following geth's `processOutput`.
+    fn finish(&mut self, result: &InterpreterResult) {
+        self.gas_used = U64::from(result.gas.spent());
+        let Some(error) = geth_error(result.result) else {
+            self.output = (!result.output.is_empty()).then(|| result.output.clone());
+            return;
+        };
+        self.error = Some(error.to_string());
+        if matches!(self.kind, "CREATE" | "CREATE2") {
+            self.to = None;
+        }
+        if result.result == InstructionResult::Revert && !result.output.is_empty() {
+            self.output = Some(result.output.clone());
+            self.revert_reason = revert_reason(&result.output);
+        }
+    }
+
+    /// Drop the logs of failed frames and everything below them.
+    fn clear_failed_logs(&mut self, parent_failed: bool) {
+        let failed = parent_failed || self.error.is_some();
+        if failed {
+            self.logs.clear();
+        }
+        for call in &mut self.calls {
+            call.clear_failed_logs(failed);
+        }
+    }
+}
+
+/// Decode `Error(string)` and `Panic(uint256)` revert data like geth's
+/// `abi.UnpackRevert`.
+pub(crate) fn revert_reason(output: &[u8]) -> Option<String> {
+    const ERROR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+    const PANIC: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+    let (selector, data) = output.split_at_checked(4)?;
+    let word = |at: usize| -> Option<U256> {
+        Some(U256::from_be_slice(data.get(at..at.checked_add(32)?)?))
+    };
+    if selector == ERROR {
+        let offset: usize = word(0)?.try_into().ok()?;
+        let len: usize = word(offset)?.try_into().ok()?;
+        let start = offset.checked_add(32)?;
+        let reason = data.get(start..start.checked_add(len)?)?;
+        return Some(String::from_utf8_lossy(reason).into_owned());
+    }
+    if selector == PANIC {
+        let code = word(0)?;
+        let reason = match code.try_into().unwrap_or(u64::MAX) {
+            0x00 => "generic panic",
+            0x01 => "assert(false)",
+            0x11 => "arithmetic underflow or overflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "enum overflow",
+            0x22 => "invalid encoded storage byte array accessed",
+            0x31 => "out-of-bounds array access; popping on an empty array",
+            0x32 => "out-of-bounds access of an array or bytesN",
+            0x41 => "out of memory",
+            0x51 => "uninitialized function",
+            _ => return Some(format!("unknown panic code: {code:#x}")),
+        };
+        return Some(reason.to_string());
+    }
+    None
+}
+
+/// Inspector building the `callTracer` frame tree.
+pub struct CallTracer {
+    config: CallTracerConfig,
+    /// Open frames, outermost first.
+    stack: Vec<CallFrame>,
+    /// Frames entered and not yet exited, including those skipped by
+    /// `onlyTopCall`.
+    depth: usize,
+}
+
+impl CallTracer {
+    pub fn new(config: CallTracerConfig) -> Self {
+        Self {
+            config,
+            stack: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    /// The top frame of the transaction, with gasUsed taken from the
+    /// receipt (refunds included) as geth reports it.
+    pub fn into_frame(mut self, result: &ExecutionResult) -> Option<CallFrame> {
+        let mut frame = self.stack.drain(..).next()?;
+        frame.gas_used = U64::from(result.gas_used());
+        if self.config.with_log {
+            frame.clear_failed_logs(false);
+        }
+        Some(frame)
+    }
+
+    fn enter<CTX: ContextTr>(&mut self, context: &mut CTX, mut frame: CallFrame) {
+        self.depth += 1;
+        if self.depth == 1 {
+            // The top frame reports the whole transaction's gas.
+            frame.gas = U64::from(context.tx().gas_limit());
+        } else if self.config.only_top_call {
+            return;
+        }
+        self.stack.push(frame);
+    }
+
+    fn exit(&mut self, result: &InterpreterResult, to: Option<Address>) {
+        self.depth -= 1;
+        if self.depth > 0 && self.config.only_top_call {
+            return;
+        }
+        let Some(mut frame) = self.stack.pop() else {
+            return;
+        };
+        if to.is_some() {
+            frame.to = to;
+        }
+        frame.finish(result);
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.stack.push(frame),
+        }
+    }
+}
+
+/// geth's type of the call frame for `inputs` and its sender.
+pub(crate) fn call_kind(inputs: &CallInputs) -> (&'static str, Address) {
+    match inputs.scheme {
+        CallScheme::Call | CallScheme::ExtCall => ("CALL", inputs.caller),
+        CallScheme::StaticCall | CallScheme::ExtStaticCall => ("STATICCALL", inputs.caller),
+        // The frame runs as the calling contract, which geth reports as the
+        // sender.
+        CallScheme::DelegateCall | CallScheme::ExtDelegateCall => {
+            ("DELEGATECALL", inputs.target_address)
+        }
+        CallScheme::CallCode => ("CALLCODE", inputs.target_address),
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for CallTracer {
+    fn log(&mut self, _interp: &mut Interpreter, _context: &mut CTX, log: Log) {
+        if !self.config.with_log || (self.config.only_top_call && self.depth > 1) {
+            return;
+        }
+        if let Some(frame) = self.stack.last_mut() {
+            frame.logs.push(CallLog {
+                address: log.address,
+                topics: log.data.topics().to_vec(),
+                data: log.data.data,
+                position: U64::from(frame.calls.len()),
+            });
+        }
+    }
+
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        let (kind, from) = call_kind(inputs);
+        let mut frame = CallFrame::new(kind, from, Some(inputs.bytecode_address), inputs.gas_limit);
+        frame.input = inputs.input.bytes(context);
+        frame.value = (kind != "STATICCALL").then(|| inputs.value.get());
+        self.enter(context, frame);
+        None
+    }
+
+    fn call_end(&mut self, _context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.exit(&outcome.result, None);
+    }
+
+    fn create(&mut self, context: &mut CTX, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        let kind = match inputs.scheme {
+            CreateScheme::Create2 { .. } => "CREATE2",
+            _ => "CREATE",
+        };
+        let mut frame = CallFrame::new(kind, inputs.caller, None, inputs.gas_limit);
+        frame.input = inputs.init_code.clone();
+        frame.value = Some(inputs.value);
+        self.enter(context, frame);
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.exit(&outcome.result, outcome.address);
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+        if self.config.only_top_call {
+            return;
+        }
+        if let Some(parent) = self.stack.last_mut() {
+            let mut frame = CallFrame::new("SELFDESTRUCT", contract, Some(target), 0);
+            frame.value = Some(value);
+            parent.calls.push(frame);
+        }
+    }
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction under a [`CallTracer`] and return its
+    /// result with the top call frame, committing when `commit` is true.
+    pub fn execute_call_trace(
+        &mut self,
+        config: CallTracerConfig,
+        commit: bool,
+    ) -> Result<(ExecutionResult, CallFrame)> {
+        let (outcome, tracer) = self.inspect_pending(CallTracer::new(config))?;
+        let frame = tracer
+            .into_frame(&outcome.result)
+            .ok_or_else(|| anyhow::anyhow!("transaction produced no call frame"))?;
+        if commit {
+            self.commit_state(outcome.state);
+        }
+        Ok((outcome.result, frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::{Address, U256};
+    use std::ffi::CString;
+
+    /// `LOG0(0, 0); STATICCALL(gas, 0xbb.., 0, 0, 0, 0); POP; STOP`
+    const OUTER: [u8; 32] = [
+        0x5f, 0x5f, 0xa0, 0x5f, 0x5f, 0x5f, 0x5f, 0x73, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+        0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0x5a, 0xfa,
+        0x50, 0x00,
+    ];
+
+    /// `revert Error("no")`: the ABI encoding is stored in memory word by
+    /// word and returned with REVERT.
+    fn reverting() -> Vec<u8> {
+        let mut payload = vec![0x08, 0xc3, 0x79, 0xa0];
+        payload.extend(U256::from(0x20).to_be_bytes::<32>());
+        payload.extend(U256::from(2).to_be_bytes::<32>());
+        let mut reason = [0u8; 32];
+        reason[..2].copy_from_slice(b"no");
+        payload.extend(reason);
+        let mut code = Vec::new();
+        for (i, chunk) in payload.chunks(32).enumerate() {
+            let mut word = [0u8; 32];
+            word[..chunk.len()].copy_from_slice(chunk);
+            code.push(0x7f); // PUSH32
+            code.extend(word);
+            code.extend([0x60, (i * 32) as u8, 0x52]); // MSTORE
+        }
+        code.extend([0x60, payload.len() as u8, 0x5f, 0xfd]); // REVERT(0, len)
+        code
+    }
+
+    fn trace(instance: *mut RevmInstance, config: &str) -> serde_json::Value {
+        let config = CString::new(config).unwrap();
+        unsafe { take_json(revm_execute_call_trace(instance, config.as_ptr(), false)) }
+    }
+
+    #[test]
+    fn traces_calls_in_geth_format() {
+        let (caller, outer) = (CALLER, CONTRACT);
+        let inner = Address::repeat_byte(0xbb);
+
+        unsafe {
+            let instance = instance_with_contracts(&[(outer, &OUTER), (inner, &reverting())]);
+            set_call(instance, outer, &[0x12, 0x34], 0);
+
+            let top = trace(instance, r#"{"withLog":true}"#);
+            assert_eq!(top["type"], "CALL");
+            assert_eq!(top["from"], format!("{caller:#x}"));
+            assert_eq!(top["to"], format!("{outer:#x}"));
+            assert_eq!(top["gas"], "0x186a0");
+            assert_eq!(top["input"], "0x1234");
+            assert_eq!(top["value"], "0x0");
+            assert!(top.get("error").is_none());
+            assert_eq!(top["logs"][0]["address"], format!("{outer:#x}"));
+            assert_eq!(top["logs"][0]["data"], "0x");
+            assert_eq!(top["logs"][0]["position"], "0x0");
+
+            let call = &top["calls"][0];
+            assert_eq!(call["type"], "STATICCALL");
+            assert_eq!(call["from"], format!("{outer:#x}"));
+            assert_eq!(call["to"], format!("{inner:#x}"));
+            assert!(call.get("value").is_none());
+            assert_eq!(call["error"], "execution reverted");
+            assert_eq!(call["revertReason"], "no");
+            assert_eq!(call["output"].as_str().unwrap().len(), 2 + 2 * 100);
+
+            let only_top = trace(instance, r#"{"onlyTopCall":true}"#);
+            assert!(only_top.get("calls").is_none());
+            assert!(only_top.get("logs").is_none());
+            assert_eq!(only_top["gasUsed"], top["gasUsed"]);
+
+            // Nothing was committed.
+            let nonce = revm_get_nonce(instance, c_address(caller).as_ptr());
+            assert_eq!(nonce, 0);
+            revm_free(instance);
+        }
+
+        let panic = [
+            &[0x4e, 0x48, 0x7b, 0x71][..],
+            &U256::from(0x11).to_be_bytes::<32>(),
+        ]
+        .concat();
+        assert_eq!(
+            call_tracer::revert_reason(&panic).as_deref(),
+            Some("arithmetic underflow or overflow")
+        );
+        assert_eq!(call_tracer::revert_reason(&[0xde, 0xad]), None);
+    }
+}
@@ -0,0 +1,232 @@
+//! Canonical blocks and receipts, read from the freezer for old blocks and
+//! from the key-value store for recent ones.
+
+use alloy_consensus::{Block, BlockBody, Eip658Value, Header, Receipt, TxEnvelope};
+use alloy_rlp::Decodable;
+use revm::primitives::{Log, B256};
+
+use super::freezer::{BODIES_TABLE, HASHES_TABLE, HEADERS_TABLE, RECEIPTS_TABLE};
+use super::{ChainData, ChainDataError};
+
+const HEADER_PREFIX: &[u8] = b"h";
+const CANONICAL_SUFFIX: &[u8] = b"n";
+const BODY_PREFIX: &[u8] = b"b";
+const RECEIPTS_PREFIX: &[u8] = b"r";
+
+/// A receipt in geth's storage encoding: `[status, cumulativeGasUsed, logs]`.
+/// Bloom, transaction type and derived fields are not stored.
+pub type StoredReceipt = Receipt<Log>;
+
+impl ChainData {
+    /// Hash of the canonical block `number`.
+    pub fn canonical_hash(&self, number: u64) -> Result<Option<B256>, ChainDataError> {
+        let hash = match self.frozen_item(HASHES_TABLE, number)? {
+            Some(hash) => Some(hash),
+            None => self.get(&block_key(HEADER_PREFIX, number, CANONICAL_SUFFIX))?,
+        };
+        match hash {
+            Some(hash) if hash.len() == 32 => Ok(Some(B256::from_slice(&hash))),
+            Some(_) => Err(ChainDataError(format!("malformed hash of block {number}"))),
+            None => Ok(None),
+        }
+    }
+
+    /// Header of the canonical block `number`.
+    pub fn header(&self, number: u64) -> Result<Option<Header>, ChainDataError> {
+        self.canonical_item(HEADERS_TABLE, HEADER_PREFIX, number)
+    }
+
+    /// Transactions, ommers and withdrawals of the canonical block `number`.
+    pub fn body(&self, number: u64) -> Result<Option<BlockBody<TxEnvelope>>, ChainDataError> {
+        self.canonical_item(BODIES_TABLE, BODY_PREFIX, number)
+    }
+
+    /// Header and body of the canonical block `number`.
+    pub fn block(&self, number: u64) -> Result<Option<Block<TxEnvelope>>, ChainDataError> {
+        let (Some(header), Some(body)) = (self.header(number)?, self.body(number)?) else {
+            return Ok(None);
+        };
+        Ok(Some(Block { header, body }))
+    }
+
+    /// Receipts of the canonical block `number`, in transaction order.
+    pub fn receipts(&self, number: u64) -> Result<Option<Vec<StoredReceipt>>, ChainDataError> {
+        let receipts: Option<Vec<StoredReceiptRlp>> =
+            self.canonical_item(RECEIPTS_TABLE, RECEIPTS_PREFIX, number)?;
+        Ok(receipts.map(|receipts| receipts.into_iter().map(|r| r.0).collect()))
+    }
+
+    fn frozen_item(&self, table: &str, number: u64) -> Result<Option<Vec<u8>>, ChainDataError> {
+        match &self.freezer {
+            Some(freezer) if number < freezer.frozen() => freezer.get(table, number),
+            _ => Ok(None),
+        }
+    }
+
+    /// Decode item `number` of a freezer table, falling back to the
+    /// `prefix + number + hash` key of the canonical block.
+    fn canonical_item<T: Decodable>(
+        &self,
+        table: &str,
+        prefix: &[u8],
+        number: u64,
+    ) -> Result<Option<T>, ChainDataError> {
+        let raw = match self.frozen_item(table, number)? {
+            Some(raw) => Some(raw),
+            None => match self.canonical_hash(number)? {
+                Some(hash) => self.get(&block_key(prefix, number, &hash[..]))?,
+                None => None,
+            },
+        };
+        raw.map(|raw| {
+            T::decode(&mut raw.as_slice())
+                .map_err(|e| ChainDataError(format!("{table} of block {number}: {e}")))
+        })
+        .transpose()
+    }
+}
+
+/// RLP wrapper for [`StoredReceipt`].
+struct StoredReceiptRlp(StoredReceipt);
+
+impl Decodable for StoredReceiptRlp {
+    fn decode(buf: &mut &[u8]) -> alloy_rlp::Result<Self> {
+        let header = alloy_rlp::Header::decode(buf)?;
+        if !header.list {
+            return Err(alloy_rlp::Error::UnexpectedString);
+        }
+        let (mut payload, rest) = buf.split_at(header.payload_length.min(buf.len()));
+        *buf = rest;
+        Ok(Self(Receipt {
+            status: Eip658Value::decode(&mut payload)?,
+            cumulative_gas_used: u64::decode(&mut payload)?,
+            logs: Vec::<Log>::decode(&mut payload)?,
+        }))
+    }
+}
+
+fn block_key(prefix: &[u8], number: u64, suffix: &[u8]) -> Vec<u8> {
+    [prefix, &number.to_be_bytes()[..], suffix].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_db::{create, encode_receipts, write_freezer_table};
+    use super::*;
+    use alloy_consensus::{SignableTransaction, TxLegacy};
+    use revm::database_interface::DatabaseRef;
+    use revm::primitives::alloy_primitives::Signature;
+    use revm::primitives::{Address, Bytes, LogData, TxKind, U256};
+
+    fn block(number: u64) -> (Header, BlockBody<TxEnvelope>, Vec<StoredReceipt>) {
+        let header = Header {
+            number,
+            gas_used: 21_000 * number,
+            ..Default::default()
+        };
+        let tx = TxLegacy {
+            nonce: number,
+            gas_limit: 21_000,
+            to: TxKind::Call(Address::with_last_byte(9)),
+            value: U256::from(number),
+            ..Default::default()
+        };
+        let body = BlockBody {
+            transactions: vec![tx.into_signed(Signature::test_signature()).into()],
+            ..Default::default()
+        };
+        let log = Log {
+            address: Address::with_last_byte(9),
+            data: LogData::new_unchecked(vec![B256::repeat_byte(number as u8)], Bytes::new()),
+        };
+        let receipt = Receipt {
+            status: Eip658Value::Eip658(true),
+            cumulative_gas_used: 21_000,
+            logs: vec![log],
+        };
+        (header, body, vec![receipt])
+    }
+
+    #[test]
+    fn reads_frozen_and_recent_blocks() {
+        let blocks: Vec<_> = (0..3).map(block).collect();
+        let hashes: Vec<B256> = blocks.iter().map(|(h, _, _)| h.hash_slow()).collect();
+
+        // Block 2 is only in the key-value store.
+        let (header, body, receipts) = &blocks[2];
+        let dir = create(
+            "blocks",
+            &[vec![
+                (
+                    1,
+                    block_key(HEADER_PREFIX, 2, CANONICAL_SUFFIX),
+                    hashes[2].to_vec(),
+                ),
+                (
+                    1,
+                    block_key(HEADER_PREFIX, 2, &hashes[2][..]),
+                    alloy_rlp::encode(header),
+                ),
+                (
+                    1,
+                    block_key(BODY_PREFIX, 2, &hashes[2][..]),
+                    alloy_rlp::encode(body),
+                ),
+                (
+                    1,
+                    block_key(RECEIPTS_PREFIX, 2, &hashes[2][..]),
+                    encode_receipts(receipts),
+                ),
+                (1, b"SnapshotRoot".to_vec(), B256::repeat_byte(1).to_vec()),
+            ]],
+        );
+
+        let ancient = dir.join("ancient").join("chain");
+        let frozen = &blocks[..2];
+        let table =
+            |name, items: Vec<Vec<u8>>| write_freezer_table(&ancient, name, true, 0, &items);
+        table(
+            HEADERS_TABLE,
+            frozen
+                .iter()
+                .map(|(h, _, _)| alloy_rlp::encode(h))
+                .collect(),
+        );
+        table(
+            BODIES_TABLE,
+            frozen
+                .iter()
+                .map(|(_, b, _)| alloy_rlp::encode(b))
+                .collect(),
+        );
+        table(
+            RECEIPTS_TABLE,
+            frozen.iter().map(|(_, _, r)| encode_receipts(r)).collect(),
+        );
+        write_freezer_table(
+            &ancient,
+            HASHES_TABLE,
+            false,
+            0,
+            &[hashes[0].to_vec(), hashes[1].to_vec()],
+        );
+
+        let db = ChainData::open(&dir).unwrap();
+        for (number, (header, body, receipts)) in blocks.iter().enumerate() {
+            let number = number as u64;
+            assert_eq!(
+                db.canonical_hash(number).unwrap(),
+                Some(hashes[number as usize])
+            );
+            assert_eq!(db.header(number).unwrap().as_ref(), Some(header));
+            assert_eq!(db.body(number).unwrap().as_ref(), Some(body));
+            assert_eq!(db.receipts(number).unwrap().as_ref(), Some(receipts));
+        }
+        assert!(db.block(3).unwrap().is_none());
+
+        let state = crate::ChainDataDB::new(std::sync::Arc::new(db), None).unwrap();
+        assert_eq!(state.block_hash_ref(1).unwrap(), hashes[1]);
+        assert_eq!(state.block_hash_ref(2).unwrap(), hashes[2]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,255 @@
+//! Reader for geth's freezer ("ancient store") tables.
+//!
+//! A table is an index file of 6-byte entries (`u16` data file number, `u32`
+//! end offset, both big-endian) plus numbered data files.  The first index
+//! entry is special: it holds the first data file number and the count of
+//! items deleted from the tail.  `.cidx`/`.cdat` tables store every item
+//! snappy-compressed, `.ridx`/`.rdat` tables store them raw.  A `.meta` file
+//! records the version and the virtual tail below which items are hidden.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use alloy_rlp::Decodable;
+use revm::primitives::HashMap;
+
+use super::{read_exact_at, ChainDataError};
+
+const INDEX_ENTRY_SIZE: u64 = 6;
+
+/// Chain freezer table names.
+pub(super) const HEADERS_TABLE: &str = "headers";
+pub(super) const HASHES_TABLE: &str = "hashes";
+pub(super) const BODIES_TABLE: &str = "bodies";
+pub(super) const RECEIPTS_TABLE: &str = "receipts";
+
+#[derive(Clone, Copy, Debug)]
+struct IndexEntry {
+    file: u32,
+    offset: u32,
+}
+
+impl IndexEntry {
+    fn decode(buf: &[u8; INDEX_ENTRY_SIZE as usize]) -> Self {
+        Self {
+            file: u32::from(u16::from_be_bytes([buf[0], buf[1]])),
+            offset: u32::from_be_bytes([buf[2], buf[3], buf[4], buf[5]]),
+        }
+    }
+}
+
+/// Virtual tail from a `.meta` file: `[version, virtualTail, ...]`.
+fn decode_virtual_tail(mut meta: &[u8]) -> alloy_rlp::Result<u64> {
+    let header = alloy_rlp::Header::decode(&mut meta)?;
+    if !header.list {
+        return Err(alloy_rlp::Error::UnexpectedString);
+    }
+    u16::decode(&mut meta)?;
+    u64::decode(&mut meta)
+}
+
+/// One read-only freezer table.
+#[derive(Debug)]
+pub struct FreezerTable {
+    dir: PathBuf,
+    name: String,
+    compressed: bool,
+    index: File,
+    /// Items deleted from the tail; the first entry in the index describes
+    /// item `offset`.
+    offset: u64,
+    /// Items below this number are hidden even if still on disk.
+    tail: u64,
+    items: u64,
+    data_files: Mutex<HashMap<u32, Arc<File>>>,
+}
+
+impl FreezerTable {
+    /// Open table `name` in `dir`, detecting whether it is compressed.
+    pub fn open(dir: impl AsRef<Path>, name: &str) -> Result<Self, ChainDataError> {
+        let dir = dir.as_ref().to_path_buf();
+        let (compressed, index_path) = match dir.join(format!("{name}.cidx")) {
+            path if path.exists() => (true, path),
+            _ => (false, dir.join(format!("{name}.ridx"))),
+        };
+        let index = File::open(&index_path)?;
+        let entries = index.metadata()?.len() / INDEX_ENTRY_SIZE;
+        if entries == 0 {
+            return Err(ChainDataError::corrupt(&index_path, "empty index"));
+        }
+
+        let mut first = [0u8; INDEX_ENTRY_SIZE as usize];
+        read_exact_at(&index, &mut first, 0)?;
+        let offset = u64::from(IndexEntry::decode(&first).offset);
+
+        let meta_path = dir.join(format!("{name}.meta"));
+        let tail = match std::fs::read(&meta_path) {
+            Ok(meta) => decode_virtual_tail(&meta)
+                .map_err(|e| ChainDataError::corrupt(&meta_path, &e.to_string()))?
+                .max(offset),
+            Err(_) => offset,
+        };
+
+        Ok(Self {
+            dir,
+            name: name.to_string(),
+            compressed,
+            index,
+            offset,
+            tail,
+            items: offset + entries - 1,
+            data_files: Mutex::new(HashMap::default()),
+        })
+    }
+
+    /// Number of the first readable item.
+    pub fn tail(&self) -> u64 {
+        self.tail
+    }
+
+    /// One past the number of the last stored item.
+    pub fn items(&self) -> u64 {
+        self.items
+    }
+
+    /// Item `number`, decompressed, or `None` outside `tail..items`.
+    pub fn get(&self, number: u64) -> Result<Option<Vec<u8>>, ChainDataError> {
+        if number < self.tail || number >= self.items {
+            return Ok(None);
+        }
+        let mut buf = [0u8; 2 * INDEX_ENTRY_SIZE as usize];
+        let relative = number - self.offset;
+        read_exact_at(&self.index, &mut buf, relative * INDEX_ENTRY_SIZE)?;
+        let start = IndexEntry::decode(buf[..6].try_into().unwrap());
+        let end = IndexEntry::decode(buf[6..].try_into().unwrap());
+
+        // An item never spans files, so one starting a new file begins at 0.
+        // So does the first item: the first entry holds the deletion count.
+        let from = if relative > 0 && start.file == end.file {
+            start.offset
+        } else {
+            0
+        };
+        if from > end.offset {
+            return Err(ChainDataError(format!(
+                "{}: item {number} has a negative size",
+                self.name
+            )));
+        }
+        let mut data = vec![0u8; (end.offset - from) as usize];
+        let file = self.data_file(end.file)?;
+        read_exact_at(&file, &mut data, u64::from(from))?;
+
+        if !self.compressed {
+            return Ok(Some(data));
+        }
+        snap::raw::Decoder::new()
+            .decompress_vec(&data)
+            .map(Some)
+            .map_err(|e| ChainDataError(format!("{}: item {number}: snappy: {e}", self.name)))
+    }
+
+    fn data_file(&self, number: u32) -> Result<Arc<File>, ChainDataError> {
+        let mut files = self.data_files.lock().unwrap();
+        if let Some(file) = files.get(&number) {
+            return Ok(file.clone());
+        }
+        let ext = if self.compressed { "cdat" } else { "rdat" };
+        let path = self.dir.join(format!("{}.{number:04}.{ext}", self.name));
+        let file = Arc::new(
+            File::open(&path).map_err(|e| ChainDataError::corrupt(&path, &e.to_string()))?,
+        );
+        files.insert(number, file.clone());
+        Ok(file)
+    }
+}
+
+/// The chain freezer: headers, canonical hashes, bodies and receipts of old
+/// blocks, all indexed by block number.
+#[derive(Debug)]
+pub struct ChainFreezer {
+    headers: FreezerTable,
+    hashes: FreezerTable,
+    bodies: FreezerTable,
+    receipts: FreezerTable,
+}
+
+impl ChainFreezer {
+    /// Open the tables in `dir` (normally `chaindata/ancient/chain`).
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, ChainDataError> {
+        let dir = dir.as_ref();
+        Ok(Self {
+            headers: FreezerTable::open(dir, HEADERS_TABLE)?,
+            hashes: FreezerTable::open(dir, HASHES_TABLE)?,
+            bodies: FreezerTable::open(dir, BODIES_TABLE)?,
+            receipts: FreezerTable::open(dir, RECEIPTS_TABLE)?,
+        })
+    }
+
+    /// Number of blocks frozen; every block below it lives in the freezer.
+    pub fn frozen(&self) -> u64 {
+        self.headers.items()
+    }
+
+    /// Raw item `number` of the table called `name`.
+    pub fn get(&self, name: &str, number: u64) -> Result<Option<Vec<u8>>, ChainDataError> {
+        let table = match name {
+            HEADERS_TABLE => &self.headers,
+            HASHES_TABLE => &self.hashes,
+            BODIES_TABLE => &self.bodies,
+            RECEIPTS_TABLE => &self.receipts,
+            _ => return Err(ChainDataError(format!("unknown freezer table {name}"))),
+        };
+        table.get(number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_db::write_freezer_table;
+    use super::*;
+
+    const STATE_FREEZER: &str =
+        "../perf_comparision/seed_peer/seed_peer_data/geth/chaindata/ancient/state";
+
+    #[test]
+    #[ignore = "needs the seed peer database under ../perf_comparision"]
+    fn reads_seed_peer_state_history() {
+        // Each history meta item is `version || parentRoot || root || block`,
+        // and consecutive items chain root to parent root.
+        let meta = FreezerTable::open(STATE_FREEZER, "history.meta").unwrap();
+        assert_eq!((meta.tail(), meta.items()), (0, 734));
+        let first = meta.get(0).unwrap().unwrap();
+        let second = meta.get(1).unwrap().unwrap();
+        assert_eq!((first.len(), second.len()), (73, 73));
+        assert_eq!(first[33..65], second[1..33]);
+        assert!(meta.get(734).unwrap().is_none());
+
+        let index = FreezerTable::open(STATE_FREEZER, "account.index").unwrap();
+        assert!(index.compressed);
+        assert!(!index.get(733).unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn skips_deleted_and_hidden_items() {
+        let dir = std::env::temp_dir().join(format!("revm-freezer-{}", std::process::id()));
+        let items: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 10 + i as usize]).collect();
+        write_freezer_table(&dir, "raw", false, 5, &items);
+        write_freezer_table(&dir, "packed", true, 5, &items);
+        // `[version, virtualTail, flushOffset]` hiding item 5.
+        std::fs::write(dir.join("packed.meta"), [0xc3, 0x02, 0x06, 0x80]).unwrap();
+
+        let raw = FreezerTable::open(&dir, "raw").unwrap();
+        assert_eq!((raw.tail(), raw.items()), (5, 9));
+        assert!(raw.get(4).unwrap().is_none());
+        assert_eq!(raw.get(5).unwrap(), Some(items[0].clone()));
+        assert_eq!(raw.get(8).unwrap(), Some(items[3].clone()));
+
+        let packed = FreezerTable::open(&dir, "packed").unwrap();
+        assert_eq!(packed.tail(), 6);
+        assert!(packed.get(5).unwrap().is_none());
+        assert_eq!(packed.get(7).unwrap(), Some(items[2].clone()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
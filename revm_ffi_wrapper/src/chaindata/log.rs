@@ -0,0 +1,145 @@
+//! Reader for the leveldb/pebble log format shared by `MANIFEST-*` and the
+//! write-ahead `*.log` files.
+//!
+//! A log is a sequence of 32 KiB blocks.  Each block holds records with a
+//! 7-byte header (checksum, length, type) or, for pebble's recyclable WAL
+//! segments, an 11-byte header that also carries the log number.  Logical
+//! records may be split across blocks as FIRST/MIDDLE/LAST fragments.
+
+use super::table::{mask_crc, CRC32C};
+use super::ChainDataError;
+
+const BLOCK_SIZE: usize = 32 * 1024;
+const HEADER_SIZE: usize = 7;
+const RECYCLABLE_HEADER_SIZE: usize = 11;
+
+const FULL: u8 = 1;
+const FIRST: u8 = 2;
+const MIDDLE: u8 = 3;
+const LAST: u8 = 4;
+/// Recyclable record types are the legacy ones shifted by this amount.
+const RECYCLABLE_OFFSET: u8 = 4;
+
+/// Split `data` into logical records.
+///
+/// `log_number` is the file number of the log; recyclable records written for
+/// an older log (left behind when a file is reused) end the stream.  A torn
+/// or corrupt record at the tail, as left by an unclean shutdown, also ends
+/// the stream rather than failing; records are checked against their masked
+/// CRC32c checksum.
+pub(super) fn read_records(data: &[u8], log_number: u64) -> Result<Vec<Vec<u8>>, ChainDataError> {
+    let mut records = Vec::new();
+    let mut pending: Option<Vec<u8>> = None;
+    let mut block_start = 0;
+
+    while block_start < data.len() {
+        let block = &data[block_start..data.len().min(block_start + BLOCK_SIZE)];
+        let mut pos = 0;
+
+        while block.len() - pos >= HEADER_SIZE {
+            let header = &block[pos..];
+            let length = u16::from_le_bytes([header[4], header[5]]) as usize;
+            let mut kind = header[6];
+            if kind == 0 && length == 0 {
+                // Zero padding or preallocated space.
+                break;
+            }
+
+            let mut header_len = HEADER_SIZE;
+            if kind > RECYCLABLE_OFFSET {
+                if block.len() - pos < RECYCLABLE_HEADER_SIZE {
+                    return Ok(records);
+                }
+                let number = u32::from_le_bytes(header[7..11].try_into().unwrap());
+                if u64::from(number) != log_number & u64::from(u32::MAX) {
+                    return Ok(records);
+                }
+                header_len = RECYCLABLE_HEADER_SIZE;
+                kind -= RECYCLABLE_OFFSET;
+            }
+
+            let start = pos + header_len;
+            let end = start + length;
+            if end > block.len() {
+                return Ok(records);
+            }
+            // The checksum covers the type byte, the log number of recyclable
+            // records and the payload.
+            let stored = u32::from_le_bytes(header[..4].try_into().unwrap());
+            if mask_crc(CRC32C.checksum(&block[pos + 6..end])) != stored {
+                return Ok(records);
+            }
+            let payload = &block[start..end];
+            pos = end;
+
+            match kind {
+                FULL => {
+                    pending = None;
+                    records.push(payload.to_vec());
+                }
+                FIRST => pending = Some(payload.to_vec()),
+                MIDDLE => {
+                    if let Some(buf) = pending.as_mut() {
+                        buf.extend_from_slice(payload);
+                    }
+                }
+                LAST => {
+                    if let Some(mut buf) = pending.take() {
+                        buf.extend_from_slice(payload);
+                        records.push(buf);
+                    }
+                }
+                other => {
+                    return Err(ChainDataError(format!("unknown log record type {other}")));
+                }
+            }
+        }
+
+        block_start += BLOCK_SIZE;
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(kind: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![0u8; 4];
+        out.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        out.push(kind);
+        out.extend_from_slice(payload);
+        let crc = mask_crc(CRC32C.checksum(&out[6..]));
+        out[..4].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn reassembles_fragments_across_blocks() {
+        let big = vec![7u8; BLOCK_SIZE];
+        let first_len = BLOCK_SIZE - 2 * HEADER_SIZE - 3;
+
+        let mut data = record(FULL, b"abc");
+        data.extend(record(FIRST, &big[..first_len]));
+        assert_eq!(data.len(), BLOCK_SIZE);
+        data.extend(record(LAST, &big[first_len..]));
+        // A torn record at the tail is ignored.
+        data.extend(&record(FULL, b"torn")[..8]);
+
+        let records = read_records(&data, 1).unwrap();
+        assert_eq!(records, vec![b"abc".to_vec(), big]);
+    }
+
+    #[test]
+    fn checksum_mismatch_ends_stream() {
+        let mut data = record(FULL, b"abc");
+        let mut corrupt = record(FULL, b"def");
+        corrupt[HEADER_SIZE] ^= 1;
+        data.extend(corrupt);
+        data.extend(record(FULL, b"ghi"));
+
+        let records = read_records(&data, 1).unwrap();
+        assert_eq!(records, vec![b"abc".to_vec()]);
+    }
+}
@@ -0,0 +1,277 @@
+//! Read-only access to a geth/BSC `chaindata` directory.
+//!
+//! [`ChainData`] is a minimal pebble reader: it replays the `MANIFEST` to find
+//! the live tables, loads unflushed writes from the write-ahead log and serves
+//! point lookups.  [`ChainDataDB`] builds REVM's `DatabaseRef` on top of it,
+//! resolving accounts and storage at a fixed state root from either the flat
+//! snapshot (`a`/`o` keys) or the path-based trie (`A`/`O` keys), so blocks
+//! can be executed without a Go process attached.
+//!
+//! Blocks older than the freezer's head are read from the ancient store
+//! ([`ChainFreezer`]); newer ones from the key-value store.
+
+mod blocks;
+mod freezer;
+mod log;
+mod state;
+mod store;
+mod table;
+
+use std::fs::File;
+use std::path::Path;
+use std::{error::Error, fmt, io};
+
+use revm::database_interface::DBErrorMarker;
+
+pub use blocks::StoredReceipt;
+pub use freezer::{ChainFreezer, FreezerTable};
+pub use state::{ChainDataDB, StateLayout};
+pub use store::ChainData;
+
+/// Failure while reading the database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainDataError(pub String);
+
+impl ChainDataError {
+    fn corrupt(path: &Path, msg: &str) -> Self {
+        Self(format!("{}: {msg}", path.display()))
+    }
+}
+
+impl fmt::Display for ChainDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ChainDataError {}
+
+impl DBErrorMarker for ChainDataError {}
+
+impl From<io::Error> for ChainDataError {
+    fn from(err: io::Error) -> Self {
+        Self(err.to_string())
+    }
+}
+
+/// Internal key kinds that carry a live value.
+const KIND_SET: u8 = 1;
+const KIND_MERGE: u8 = 2;
+const KIND_SET_WITH_DELETE: u8 = 18;
+
+/// A table or memtable key: the user key followed by an 8-byte little-endian
+/// trailer of `seq << 8 | kind`.
+struct InternalKey<'a> {
+    user_key: &'a [u8],
+    seq: u64,
+    kind: u8,
+}
+
+impl<'a> InternalKey<'a> {
+    fn parse(key: &'a [u8]) -> Result<Self, ChainDataError> {
+        let split = key
+            .len()
+            .checked_sub(8)
+            .ok_or_else(|| ChainDataError("internal key too short".into()))?;
+        let trailer = u64::from_le_bytes(key[split..].try_into().unwrap());
+        Ok(Self {
+            user_key: &key[..split],
+            seq: trailer >> 8,
+            kind: trailer as u8,
+        })
+    }
+
+    fn user_key_of(key: &[u8]) -> &[u8] {
+        &key[..key.len().saturating_sub(8)]
+    }
+}
+
+/// Keys in `[start, end)` written before `seq` are deleted.
+#[derive(Clone, Debug)]
+struct RangeTombstone {
+    start: Vec<u8>,
+    end: Vec<u8>,
+    seq: u64,
+}
+
+impl RangeTombstone {
+    fn covers(&self, key: &[u8]) -> bool {
+        self.start.as_slice() <= key && key < self.end.as_slice()
+    }
+}
+
+fn uvarint(buf: &mut &[u8]) -> Result<u64, ChainDataError> {
+    let mut value = 0u64;
+    for (i, byte) in buf.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *buf = &buf[i + 1..];
+            return Ok(value);
+        }
+    }
+    Err(ChainDataError("malformed varint".into()))
+}
+
+fn read_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], ChainDataError> {
+    let len = uvarint(buf)? as usize;
+    if buf.len() < len {
+        return Err(ChainDataError("truncated byte string".into()));
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+/// Fill `buf` from `file` at `offset` without moving the shared file cursor,
+/// so tables and freezer files can be read from several threads.
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+/// Fill `buf` from `file` at `offset`.  Windows positional reads do move the
+/// cursor, but every read here passes its own offset.
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut std::mem::take(&mut buf)[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Builds throwaway database directories made of a manifest without tables
+/// and a single WAL.
+#[cfg(test)]
+pub(crate) mod test_db {
+    use std::path::PathBuf;
+
+    use alloy_rlp::Encodable;
+
+    use super::table::{mask_crc, CRC32C};
+    use super::StoredReceipt;
+
+    /// `(kind, key, value)` batch entry.
+    pub(crate) type Op = (u8, Vec<u8>, Vec<u8>);
+
+    fn log_record(payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![0u8; 4];
+        out.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        out.push(1);
+        out.extend_from_slice(payload);
+        let crc = mask_crc(CRC32C.checksum(&out[6..]));
+        out[..4].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+        let mut len = bytes.len() as u64;
+        while len >= 0x80 {
+            out.push(len as u8 | 0x80);
+            len >>= 7;
+        }
+        out.push(len as u8);
+        out.extend_from_slice(bytes);
+    }
+
+    /// Write a freezer table whose first item is `offset`.
+    pub(crate) fn write_freezer_table(
+        dir: &std::path::Path,
+        name: &str,
+        compressed: bool,
+        offset: u32,
+        items: &[Vec<u8>],
+    ) {
+        let (index_ext, data_ext) = if compressed {
+            ("cidx", "cdat")
+        } else {
+            ("ridx", "rdat")
+        };
+        let mut index = [0u8, 0].to_vec();
+        index.extend_from_slice(&offset.to_be_bytes());
+        let mut data = Vec::new();
+        for item in items {
+            if compressed {
+                data.extend(snap::raw::Encoder::new().compress_vec(item).unwrap());
+            } else {
+                data.extend_from_slice(item);
+            }
+            index.extend_from_slice(&[0, 0]);
+            index.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        }
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(format!("{name}.{index_ext}")), index).unwrap();
+        std::fs::write(dir.join(format!("{name}.0000.{data_ext}")), data).unwrap();
+    }
+
+    /// Write `batches` to a fresh directory, numbering sequences from 1.
+    pub(crate) fn create(name: &str, batches: &[Vec<Op>]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("revm-chaindata-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut edit = vec![1];
+        put_bytes(&mut edit, b"leveldb.BytewiseComparator");
+        edit.extend_from_slice(&[2, 2]);
+        std::fs::write(dir.join("MANIFEST-000001"), log_record(&edit)).unwrap();
+        std::fs::write(dir.join("CURRENT"), "MANIFEST-000001\n").unwrap();
+
+        let mut wal = Vec::new();
+        let mut seq = 1u64;
+        for batch in batches {
+            let mut record = seq.to_le_bytes().to_vec();
+            record.extend_from_slice(&(batch.len() as u32).to_le_bytes());
+            for (kind, key, value) in batch {
+                record.push(*kind);
+                put_bytes(&mut record, key);
+                if *kind != 0 {
+                    put_bytes(&mut record, value);
+                }
+            }
+            seq += batch.len() as u64;
+            wal.extend(log_record(&record));
+        }
+        std::fs::write(dir.join("000002.log"), wal).unwrap();
+        dir
+    }
+
+    /// Receipts in geth's storage encoding.
+    pub(crate) fn encode_receipts(receipts: &[StoredReceipt]) -> Vec<u8> {
+        let encoded: Vec<Vec<u8>> = receipts
+            .iter()
+            .map(|r| {
+                let mut fields = Vec::new();
+                r.status.encode(&mut fields);
+                r.cumulative_gas_used.encode(&mut fields);
+                r.logs.encode(&mut fields);
+                let mut out = Vec::new();
+                alloy_rlp::Header {
+                    list: true,
+                    payload_length: fields.len(),
+                }
+                .encode(&mut out);
+                out.extend(fields);
+                out
+            })
+            .collect();
+        let mut out = Vec::new();
+        let payload_length = encoded.iter().map(Vec::len).sum();
+        alloy_rlp::Header {
+            list: true,
+            payload_length,
+        }
+        .encode(&mut out);
+        encoded.iter().for_each(|r| out.extend_from_slice(r));
+        out
+    }
+}
@@ -0,0 +1,528 @@
+//! REVM `DatabaseRef` over the state stored in a chaindata directory.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use alloy_rlp::{Decodable, Header};
+use revm::bytecode::Bytecode;
+use revm::database_interface::{Database, DatabaseRef};
+use revm::primitives::{keccak256, Address, StorageKey, StorageValue, B256, KECCAK_EMPTY, U256};
+use revm::state::AccountInfo;
+
+use super::{ChainData, ChainDataError};
+use crate::trie::EMPTY_ROOT_HASH;
+
+const SNAPSHOT_ROOT_KEY: &[u8] = b"SnapshotRoot";
+const SNAPSHOT_GENERATOR_KEY: &[u8] = b"SnapshotGenerator";
+const SNAPSHOT_ACCOUNT_PREFIX: &[u8] = b"a";
+const SNAPSHOT_STORAGE_PREFIX: &[u8] = b"o";
+const TRIE_ACCOUNT_PREFIX: &[u8] = b"A";
+const TRIE_STORAGE_PREFIX: &[u8] = b"O";
+const CODE_PREFIX: &[u8] = b"c";
+
+/// Where account and storage values are read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateLayout {
+    /// The flat snapshot: `a` + account hash and `o` + account hash + slot hash.
+    Snapshot,
+    /// Path-scheme trie nodes: `A` + path and `O` + account hash + path.
+    PathTrie,
+}
+
+/// Read-only state at a single root of a [`ChainData`] database.
+///
+/// Only the layer persisted to disk can be served; roots that exist solely in
+/// geth's in-memory diff layers (or its journal) are rejected on open.
+#[derive(Clone, Debug)]
+pub struct ChainDataDB {
+    data: Arc<ChainData>,
+    root: B256,
+    layout: StateLayout,
+}
+
+impl ChainDataDB {
+    /// Open the database in `dir` at `root`, or at the persisted state root
+    /// when `root` is `None`.
+    pub fn open(dir: impl AsRef<Path>, root: Option<B256>) -> Result<Self, ChainDataError> {
+        Self::new(Arc::new(ChainData::open(dir)?), root)
+    }
+
+    /// Serve `root` from an already opened database.  The snapshot is used
+    /// when it is complete and matches `root`; otherwise the path-based trie
+    /// must be at `root`.
+    pub fn new(data: Arc<ChainData>, root: Option<B256>) -> Result<Self, ChainDataError> {
+        let trie_root = trie_root(&data)?;
+        let snapshot_root = snapshot_root(&data)?;
+        let root = match root.or(trie_root).or(snapshot_root) {
+            Some(root) => root,
+            None => return Err(ChainDataError("no persisted state found".into())),
+        };
+
+        let layout = if snapshot_root == Some(root) {
+            StateLayout::Snapshot
+        } else if trie_root == Some(root) {
+            StateLayout::PathTrie
+        } else {
+            return Err(ChainDataError(format!(
+                "state root {root} is not persisted"
+            )));
+        };
+        Ok(Self { data, root, layout })
+    }
+
+    /// The state root being served.
+    pub fn root(&self) -> B256 {
+        self.root
+    }
+
+    /// The layout lookups are served from.
+    pub fn layout(&self) -> StateLayout {
+        self.layout
+    }
+
+    /// The underlying key-value store.
+    pub fn chain_data(&self) -> &Arc<ChainData> {
+        &self.data
+    }
+
+    /// Account info and storage root of `address`.
+    fn account(&self, address: Address) -> Result<Option<(AccountInfo, B256)>, ChainDataError> {
+        let hash = keccak256(address);
+        let value = match self.layout {
+            StateLayout::Snapshot => self
+                .data
+                .get(&[SNAPSHOT_ACCOUNT_PREFIX, &hash[..]].concat())?,
+            StateLayout::PathTrie => self.trie_get(None, self.root, hash)?,
+        };
+        value.map(|value| decode_account(&value)).transpose()
+    }
+
+    /// Walk the path-based trie of `owner` (the account trie when `None`)
+    /// from `root` and return the leaf value stored under `key`.
+    fn trie_get(
+        &self,
+        owner: Option<B256>,
+        root: B256,
+        key: B256,
+    ) -> Result<Option<Vec<u8>>, ChainDataError> {
+        if root == EMPTY_ROOT_HASH {
+            return Ok(None);
+        }
+        let target: Vec<u8> = key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+        let mut path = Vec::new();
+        let mut node = self.trie_node(owner, &path, root)?;
+
+        loop {
+            let items = rlp_list(&node)?;
+            let child = match items.len() {
+                17 => {
+                    let Some(&nibble) = target.get(path.len()) else {
+                        return Ok(None);
+                    };
+                    path.push(nibble);
+                    items[nibble as usize]
+                }
+                2 => {
+                    let (segment, leaf) = decode_compact(rlp_bytes(items[0])?);
+                    let rest = &target[path.len()..];
+                    if leaf {
+                        if rest != segment.as_slice() {
+                            return Ok(None);
+                        }
+                        return Ok(Some(rlp_bytes(items[1])?.to_vec()));
+                    }
+                    if !rest.starts_with(&segment) {
+                        return Ok(None);
+                    }
+                    path.extend_from_slice(&segment);
+                    items[1]
+                }
+                n => return Err(ChainDataError(format!("trie node with {n} items"))),
+            };
+
+            let next = if Header::decode(&mut &child[..]).map_err(rlp_error)?.list {
+                child.to_vec()
+            } else {
+                match rlp_bytes(child)? {
+                    [] => return Ok(None),
+                    hash if hash.len() == 32 => {
+                        self.trie_node(owner, &path, B256::from_slice(hash))?
+                    }
+                    _ => return Err(ChainDataError("malformed trie child reference".into())),
+                }
+            };
+            node = next;
+        }
+    }
+
+    /// Load the node at `path` and check it is the one `hash` refers to.
+    fn trie_node(
+        &self,
+        owner: Option<B256>,
+        path: &[u8],
+        hash: B256,
+    ) -> Result<Vec<u8>, ChainDataError> {
+        let key = match owner {
+            Some(owner) => [TRIE_STORAGE_PREFIX, &owner[..], path].concat(),
+            None => [TRIE_ACCOUNT_PREFIX, path].concat(),
+        };
+        match self.data.get(&key)? {
+            Some(node) if keccak256(&node) == hash => Ok(node),
+            _ => Err(ChainDataError(format!(
+                "trie node {hash} missing at path {}",
+                hex::encode(path)
+            ))),
+        }
+    }
+}
+
+impl DatabaseRef for ChainDataDB {
+    type Error = ChainDataError;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        Ok(self.account(address)?.map(|(info, _)| info))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        if code_hash == KECCAK_EMPTY {
+            return Ok(Bytecode::default());
+        }
+        // Databases older than the prefixed scheme keyed code by bare hash.
+        let code = match self.data.get(&[CODE_PREFIX, &code_hash[..]].concat())? {
+            Some(code) => Some(code),
+            None => self.data.get(&code_hash[..])?,
+        };
+        code.map(|code| Bytecode::new_raw(code.into()))
+            .ok_or_else(|| ChainDataError(format!("code {code_hash} not found")))
+    }
+
+    fn storage_ref(
+        &self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        let account_hash = keccak256(address);
+        let slot_hash = keccak256(index.to_be_bytes::<32>());
+        let value = match self.layout {
+            StateLayout::Snapshot => self
+                .data
+                .get(&[SNAPSHOT_STORAGE_PREFIX, &account_hash[..], &slot_hash[..]].concat())?,
+            StateLayout::PathTrie => match self.account(address)? {
+                Some((_, storage_root)) => {
+                    self.trie_get(Some(account_hash), storage_root, slot_hash)?
+                }
+                None => None,
+            },
+        };
+        match value {
+            Some(value) => Ok(U256::from_be_slice(rlp_bytes(&value)?)),
+            None => Ok(U256::ZERO),
+        }
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        self.data
+            .canonical_hash(number)?
+            .ok_or_else(|| ChainDataError(format!("canonical hash of block {number} not found")))
+    }
+}
+
+impl Database for ChainDataDB {
+    type Error = ChainDataError;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.basic_ref(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.code_by_hash_ref(code_hash)
+    }
+
+    fn storage(
+        &mut self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        self.storage_ref(address, index)
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<B256, Self::Error> {
+        self.block_hash_ref(number)
+    }
+}
+
+/// Hash of the persisted account trie root node, if the path scheme is used.
+fn trie_root(data: &ChainData) -> Result<Option<B256>, ChainDataError> {
+    Ok(data.get(TRIE_ACCOUNT_PREFIX)?.map(keccak256))
+}
+
+/// Root of the snapshot, if one exists and has finished generating.
+fn snapshot_root(data: &ChainData) -> Result<Option<B256>, ChainDataError> {
+    let Some(root) = data.get(SNAPSHOT_ROOT_KEY)? else {
+        return Ok(None);
+    };
+    if root.len() != 32 {
+        return Ok(None);
+    }
+    // The generator progress is `[wiping, done, marker, ...]`; a missing entry
+    // predates progress tracking and means the snapshot is complete.
+    if let Some(generator) = data.get(SNAPSHOT_GENERATOR_KEY)? {
+        let items = rlp_list(&generator)?;
+        let done = items.get(1).map(|item| rlp_bytes(item)).transpose()?;
+        if done != Some(&[1u8][..]) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(B256::from_slice(&root)))
+}
+
+/// Decode a full (`[nonce, balance, root, codeHash]`) or slim snapshot account,
+/// where the latter leaves an empty root and code hash as empty strings.
+fn decode_account(data: &[u8]) -> Result<(AccountInfo, B256), ChainDataError> {
+    let items = rlp_list(data)?;
+    if items.len() < 4 {
+        return Err(ChainDataError("malformed account".into()));
+    }
+    let nonce = u64::decode(&mut &items[0][..]).map_err(rlp_error)?;
+    let balance = U256::decode(&mut &items[1][..]).map_err(rlp_error)?;
+    let hash_or = |item: &[u8], empty: B256| -> Result<B256, ChainDataError> {
+        match rlp_bytes(item)? {
+            [] => Ok(empty),
+            hash if hash.len() == 32 => Ok(B256::from_slice(hash)),
+            _ => Err(ChainDataError("malformed account hash".into())),
+        }
+    };
+    let storage_root = hash_or(items[2], EMPTY_ROOT_HASH)?;
+    let code_hash = hash_or(items[3], KECCAK_EMPTY)?;
+    Ok((
+        AccountInfo::new(balance, nonce, code_hash, Bytecode::default()),
+        storage_root,
+    ))
+}
+
+/// Raw encodings of the items of an RLP list.
+fn rlp_list(data: &[u8]) -> Result<Vec<&[u8]>, ChainDataError> {
+    let mut buf = data;
+    let header = Header::decode(&mut buf).map_err(rlp_error)?;
+    if !header.list || buf.len() < header.payload_length {
+        return Err(ChainDataError("expected RLP list".into()));
+    }
+    let mut payload = &buf[..header.payload_length];
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let mut rest = payload;
+        let item = Header::decode(&mut rest).map_err(rlp_error)?;
+        let len = payload.len() - rest.len() + item.payload_length;
+        if len > payload.len() {
+            return Err(ChainDataError("truncated RLP item".into()));
+        }
+        items.push(&payload[..len]);
+        payload = &payload[len..];
+    }
+    Ok(items)
+}
+
+/// Payload of an RLP string.
+fn rlp_bytes(item: &[u8]) -> Result<&[u8], ChainDataError> {
+    let mut buf = item;
+    let header = Header::decode(&mut buf).map_err(rlp_error)?;
+    if header.list || buf.len() < header.payload_length {
+        return Err(ChainDataError("expected RLP string".into()));
+    }
+    Ok(&buf[..header.payload_length])
+}
+
+fn rlp_error(err: alloy_rlp::Error) -> ChainDataError {
+    ChainDataError(format!("rlp: {err}"))
+}
+
+/// Split a hex-prefix encoded path into nibbles and its leaf flag.
+fn decode_compact(encoded: &[u8]) -> (Vec<u8>, bool) {
+    let Some(&first) = encoded.first() else {
+        return (Vec::new(), false);
+    };
+    let flag = first >> 4;
+    let mut nibbles = Vec::with_capacity(encoded.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    }
+    nibbles.extend(encoded[1..].iter().flat_map(|b| [b >> 4, b & 0x0f]));
+    (nibbles, flag & 2 == 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_db::create;
+    use super::*;
+    use crate::state_root::{encode_account, encode_storage_value, storage_key};
+    use crate::trie::Trie;
+    use alloy_rlp::Encodable;
+    use revm::primitives::Bytes;
+
+    const SET: u8 = 1;
+
+    fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+        let payload_length = items.iter().map(Vec::len).sum();
+        let mut out = Vec::new();
+        Header {
+            list: true,
+            payload_length,
+        }
+        .encode(&mut out);
+        items.iter().for_each(|item| out.extend_from_slice(item));
+        out
+    }
+
+    fn encode_string(bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        bytes.encode(&mut out);
+        out
+    }
+
+    fn nibbles(key: B256) -> Vec<u8> {
+        key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+    }
+
+    fn leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut compact = if path.len() % 2 == 1 {
+            vec![0x30 | path[0]]
+        } else {
+            vec![0x20]
+        };
+        let even = &path[path.len() % 2..];
+        compact.extend(even.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+        encode_list(&[encode_string(&compact), encode_string(value)])
+    }
+
+    fn code_key(hash: B256) -> Vec<u8> {
+        [CODE_PREFIX, &hash[..]].concat()
+    }
+
+    fn canonical_key(number: u64) -> Vec<u8> {
+        [&b"h"[..], &number.to_be_bytes()[..], b"n"].concat()
+    }
+
+    #[test]
+    fn serves_path_based_trie() {
+        let alice = Address::with_last_byte(1);
+        let alice_hash = keccak256(alice);
+        let bob = (2..=u8::MAX)
+            .map(Address::with_last_byte)
+            .find(|a| keccak256(a)[0] >> 4 != alice_hash[0] >> 4)
+            .unwrap();
+        let bob_hash = keccak256(bob);
+
+        let code = Bytes::from_static(&[0x60, 0x00, 0x56]);
+        let code_hash = keccak256(&code);
+        let storage_leaf = leaf(
+            &nibbles(storage_key(U256::from(1))),
+            &encode_storage_value(U256::from(42)),
+        );
+        let storage_root = keccak256(&storage_leaf);
+
+        let alice_info = AccountInfo::new(U256::from(1000), 3, code_hash, Bytecode::default());
+        let bob_info = AccountInfo::new(U256::from(7), 0, KECCAK_EMPTY, Bytecode::default());
+        let alice_rlp = encode_account(&alice_info, storage_root);
+        let bob_rlp = encode_account(&bob_info, EMPTY_ROOT_HASH);
+
+        let alice_leaf = leaf(&nibbles(alice_hash)[1..], &alice_rlp);
+        let bob_leaf = leaf(&nibbles(bob_hash)[1..], &bob_rlp);
+        let mut children = vec![encode_string(&[]); 17];
+        children[(alice_hash[0] >> 4) as usize] = encode_string(&keccak256(&alice_leaf)[..]);
+        children[(bob_hash[0] >> 4) as usize] = encode_string(&keccak256(&bob_leaf)[..]);
+        let branch = encode_list(&children);
+        let root = keccak256(&branch);
+
+        let mut reference = Trie::new();
+        reference.insert(alice_hash.as_slice(), alice_rlp);
+        reference.insert(bob_hash.as_slice(), bob_rlp);
+        assert_eq!(reference.root_hash(), root);
+
+        let dir = create(
+            "path-trie",
+            &[vec![
+                (SET, b"A".to_vec(), branch),
+                (SET, [&b"A"[..], &[alice_hash[0] >> 4]].concat(), alice_leaf),
+                (SET, [&b"A"[..], &[bob_hash[0] >> 4]].concat(), bob_leaf),
+                (SET, [&b"O"[..], &alice_hash[..]].concat(), storage_leaf),
+                (SET, code_key(code_hash), code.to_vec()),
+                (SET, canonical_key(9), root.to_vec()),
+            ]],
+        );
+
+        let db = ChainDataDB::open(&dir, None).unwrap();
+        assert_eq!(db.layout(), StateLayout::PathTrie);
+        assert_eq!(db.root(), root);
+
+        let info = db.basic_ref(alice).unwrap().unwrap();
+        assert_eq!(
+            (info.balance, info.nonce, info.code_hash),
+            (U256::from(1000), 3, code_hash)
+        );
+        assert_eq!(db.basic_ref(bob).unwrap().unwrap().balance, U256::from(7));
+        assert!(db.basic_ref(Address::ZERO).unwrap().is_none());
+
+        assert_eq!(
+            db.storage_ref(alice, U256::from(1)).unwrap(),
+            U256::from(42)
+        );
+        assert_eq!(db.storage_ref(alice, U256::from(2)).unwrap(), U256::ZERO);
+        assert_eq!(db.storage_ref(bob, U256::from(1)).unwrap(), U256::ZERO);
+
+        assert_eq!(
+            db.code_by_hash_ref(code_hash).unwrap().original_bytes(),
+            code
+        );
+        assert_eq!(db.block_hash_ref(9).unwrap(), root);
+        assert!(db.block_hash_ref(10).is_err());
+
+        assert!(ChainDataDB::open(&dir, Some(B256::repeat_byte(1))).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn serves_snapshot() {
+        let account = Address::with_last_byte(5);
+        let hash = keccak256(account);
+        let root = B256::repeat_byte(0xaa);
+        // Slim encoding: empty storage root and code hash are left empty.
+        let slim = encode_list(&[
+            encode_string(&[1]),
+            encode_string(&[0x03, 0xe8]),
+            encode_string(&[]),
+            encode_string(&[]),
+        ]);
+
+        let dir = create(
+            "snapshot",
+            &[vec![
+                (SET, SNAPSHOT_ROOT_KEY.to_vec(), root.to_vec()),
+                (SET, [SNAPSHOT_ACCOUNT_PREFIX, &hash[..]].concat(), slim),
+                (
+                    SET,
+                    [
+                        SNAPSHOT_STORAGE_PREFIX,
+                        &hash[..],
+                        &storage_key(U256::from(3))[..],
+                    ]
+                    .concat(),
+                    encode_storage_value(U256::from(0x1234)),
+                ),
+            ]],
+        );
+
+        let db = ChainDataDB::open(&dir, Some(root)).unwrap();
+        assert_eq!(db.layout(), StateLayout::Snapshot);
+        let info = db.basic_ref(account).unwrap().unwrap();
+        assert_eq!(
+            (info.nonce, info.balance, info.code_hash),
+            (1, U256::from(1000), KECCAK_EMPTY)
+        );
+        assert_eq!(
+            db.storage_ref(account, U256::from(3)).unwrap(),
+            U256::from(0x1234)
+        );
+        assert!(db.code_by_hash_ref(KECCAK_EMPTY).unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,381 @@
+//! Key-value view of a pebble database directory.
+
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use revm::primitives::HashMap;
+
+use super::freezer::ChainFreezer;
+use super::log::read_records;
+use super::table::Table;
+use super::{
+    read_bytes, uvarint, ChainDataError, InternalKey, RangeTombstone, KIND_MERGE, KIND_SET,
+    KIND_SET_WITH_DELETE,
+};
+
+const NUM_LEVELS: usize = 7;
+
+// Version edit tags (leveldb, RocksDB and pebble).
+const TAG_COMPARATOR: u64 = 1;
+const TAG_LOG_NUMBER: u64 = 2;
+const TAG_NEXT_FILE_NUMBER: u64 = 3;
+const TAG_LAST_SEQUENCE: u64 = 4;
+const TAG_COMPACT_POINTER: u64 = 5;
+const TAG_DELETED_FILE: u64 = 6;
+const TAG_NEW_FILE: u64 = 7;
+const TAG_PREV_LOG_NUMBER: u64 = 9;
+const TAG_NEW_FILE2: u64 = 100;
+const TAG_NEW_FILE3: u64 = 102;
+const TAG_NEW_FILE4: u64 = 103;
+const TAG_MAX_COLUMN_FAMILY: u64 = 203;
+
+const CUSTOM_TAG_TERMINATE: u64 = 1;
+const CUSTOM_TAG_NON_SAFE_IGNORE_MASK: u64 = 1 << 6;
+
+// Batch record kinds that matter when replaying the WAL.
+const KIND_DELETE: u8 = 0;
+const KIND_LOG_DATA: u8 = 3;
+const KIND_SINGLE_DELETE: u8 = 7;
+const KIND_RANGE_DELETE: u8 = 15;
+const KIND_INGEST_SST: u8 = 22;
+
+/// A live table as recorded in the manifest, opened on first use.
+#[derive(Debug)]
+struct TableFile {
+    number: u64,
+    smallest: Vec<u8>,
+    largest: Vec<u8>,
+    largest_seq: u64,
+    table: Mutex<Option<Arc<Table>>>,
+}
+
+impl TableFile {
+    fn contains(&self, key: &[u8]) -> bool {
+        self.smallest.as_slice() <= key && key <= self.largest.as_slice()
+    }
+}
+
+/// A read-only pebble database.
+///
+/// The directory is read once on open; later writes by another process are
+/// not picked up.
+#[derive(Debug)]
+pub struct ChainData {
+    dir: PathBuf,
+    /// Level 0 newest first, then levels 1-6 ordered by key.
+    levels: Vec<Vec<TableFile>>,
+    /// Writes from the WAL that were not flushed to a table yet.
+    memtable: HashMap<Vec<u8>, (u64, u8, Vec<u8>)>,
+    mem_tombstones: Vec<RangeTombstone>,
+    /// `ancient/chain`, when present.
+    pub(super) freezer: Option<ChainFreezer>,
+}
+
+impl ChainData {
+    /// Open the database in `dir`, together with its chain freezer under
+    /// `ancient/chain` if there is one.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, ChainDataError> {
+        let dir = dir.as_ref().to_path_buf();
+        let current = fs::read_to_string(dir.join("CURRENT"))?;
+        let manifest_path = dir.join(current.trim());
+        let manifest_number = file_number(current.trim(), "MANIFEST-")
+            .ok_or_else(|| ChainDataError::corrupt(&manifest_path, "bad manifest name"))?;
+
+        let mut files: Vec<HashMap<u64, TableFile>> =
+            (0..NUM_LEVELS).map(|_| HashMap::default()).collect();
+        let mut log_number = 0;
+        for record in read_records(&fs::read(&manifest_path)?, manifest_number)? {
+            apply_version_edit(&record, &mut files, &mut log_number)
+                .map_err(|e| ChainDataError::corrupt(&manifest_path, &e.0))?;
+        }
+
+        let mut levels: Vec<Vec<TableFile>> = files
+            .into_iter()
+            .map(|level| level.into_values().collect())
+            .collect();
+        levels[0].sort_by_key(|f| Reverse((f.largest_seq, f.number)));
+        for level in &mut levels[1..] {
+            level.sort_by(|a, b| a.smallest.cmp(&b.smallest));
+        }
+
+        let mut db = Self {
+            dir,
+            levels,
+            memtable: HashMap::default(),
+            mem_tombstones: Vec::new(),
+            freezer: None,
+        };
+        let ancient = db.dir.join("ancient").join("chain");
+        if ancient.is_dir() {
+            db.freezer = Some(ChainFreezer::open(&ancient)?);
+        }
+        db.replay_wal(log_number)?;
+        Ok(db)
+    }
+
+    /// The directory this database was opened from.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The current value stored under `key`, if any.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, ChainDataError> {
+        // Range deletions only shadow older writes, which always live in the
+        // same or a later (older) source than the tombstone itself.  Walking
+        // sources newest first and stopping at the first point entry therefore
+        // only needs the tombstones seen on the way.
+        let mut deleted_below = newest_covering(&self.mem_tombstones, key);
+        if let Some((seq, kind, value)) = self.memtable.get(key) {
+            return Ok(live_value(*seq, *kind, value, deleted_below));
+        }
+
+        for (level, files) in self.levels.iter().enumerate() {
+            let candidates: &[TableFile] = if level == 0 {
+                files
+            } else {
+                let idx = files.partition_point(|f| f.largest.as_slice() < key);
+                &files[idx..files.len().min(idx + 1)]
+            };
+            for file in candidates.iter().filter(|f| f.contains(key)) {
+                let table = self.table(file)?;
+                deleted_below = deleted_below.max(newest_covering(&table.tombstones, key));
+                if let Some((seq, kind, value)) = table.get(key)? {
+                    return Ok(live_value(seq, kind, &value, deleted_below));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn table(&self, file: &TableFile) -> Result<Arc<Table>, ChainDataError> {
+        let mut slot = file.table.lock().unwrap();
+        if let Some(table) = slot.as_ref() {
+            return Ok(table.clone());
+        }
+        let path = self.dir.join(format!("{:06}.sst", file.number));
+        let table = Arc::new(Table::open(&path).map_err(|e| ChainDataError::corrupt(&path, &e.0))?);
+        *slot = Some(table.clone());
+        Ok(table)
+    }
+
+    /// Load every WAL at or after the manifest's log number into the memtable.
+    fn replay_wal(&mut self, min_log: u64) -> Result<(), ChainDataError> {
+        let mut logs = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if let Some(number) = name
+                .strip_suffix(".log")
+                .and_then(|n| n.parse::<u64>().ok())
+            {
+                if number >= min_log {
+                    logs.push(number);
+                }
+            }
+        }
+        logs.sort_unstable();
+
+        for number in logs {
+            let path = self.dir.join(format!("{number:06}.log"));
+            for batch in read_records(&fs::read(&path)?, number)? {
+                self.apply_batch(&batch)
+                    .map_err(|e| ChainDataError::corrupt(&path, &e.0))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_batch(&mut self, batch: &[u8]) -> Result<(), ChainDataError> {
+        if batch.len() < 12 {
+            return Err(ChainDataError("truncated batch header".into()));
+        }
+        let mut seq = u64::from_le_bytes(batch[..8].try_into().unwrap());
+        let mut buf = &batch[12..];
+        while !buf.is_empty() {
+            let kind = buf[0];
+            buf = &buf[1..];
+            let key = read_bytes(&mut buf)?;
+            if kind == KIND_LOG_DATA {
+                continue;
+            }
+            let value = match kind {
+                KIND_DELETE | KIND_SINGLE_DELETE | KIND_INGEST_SST => &[][..],
+                _ => read_bytes(&mut buf)?,
+            };
+            if kind == KIND_RANGE_DELETE {
+                self.mem_tombstones.push(RangeTombstone {
+                    start: key.to_vec(),
+                    end: value.to_vec(),
+                    seq,
+                });
+            } else {
+                self.memtable
+                    .insert(key.to_vec(), (seq, kind, value.to_vec()));
+            }
+            seq += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Sequence number of the newest tombstone covering `key`, or 0.
+fn newest_covering(tombstones: &[RangeTombstone], key: &[u8]) -> u64 {
+    tombstones
+        .iter()
+        .filter(|t| t.covers(key))
+        .map(|t| t.seq)
+        .max()
+        .unwrap_or(0)
+}
+
+fn live_value(seq: u64, kind: u8, value: &[u8], deleted_below: u64) -> Option<Vec<u8>> {
+    let live = matches!(kind, KIND_SET | KIND_MERGE | KIND_SET_WITH_DELETE);
+    (live && seq >= deleted_below).then(|| value.to_vec())
+}
+
+fn file_number(name: &str, prefix: &str) -> Option<u64> {
+    name.strip_prefix(prefix)?.parse().ok()
+}
+
+/// Apply one manifest record to the per-level file sets.
+fn apply_version_edit(
+    mut buf: &[u8],
+    files: &mut [HashMap<u64, TableFile>],
+    log_number: &mut u64,
+) -> Result<(), ChainDataError> {
+    let buf = &mut buf;
+    let level = |buf: &mut &[u8]| -> Result<usize, ChainDataError> {
+        let level = uvarint(buf)? as usize;
+        if level >= NUM_LEVELS {
+            return Err(ChainDataError(format!("invalid level {level}")));
+        }
+        Ok(level)
+    };
+
+    while !buf.is_empty() {
+        let tag = uvarint(buf)?;
+        match tag {
+            TAG_COMPARATOR => {
+                let name = read_bytes(buf)?;
+                if name != b"leveldb.BytewiseComparator" {
+                    return Err(ChainDataError(format!(
+                        "unsupported comparator {}",
+                        String::from_utf8_lossy(name)
+                    )));
+                }
+            }
+            TAG_LOG_NUMBER => *log_number = uvarint(buf)?,
+            TAG_NEXT_FILE_NUMBER
+            | TAG_LAST_SEQUENCE
+            | TAG_PREV_LOG_NUMBER
+            | TAG_MAX_COLUMN_FAMILY => {
+                uvarint(buf)?;
+            }
+            TAG_COMPACT_POINTER => {
+                level(buf)?;
+                read_bytes(buf)?;
+            }
+            TAG_DELETED_FILE => {
+                let level = level(buf)?;
+                files[level].remove(&uvarint(buf)?);
+            }
+            TAG_NEW_FILE | TAG_NEW_FILE2 | TAG_NEW_FILE3 | TAG_NEW_FILE4 => {
+                let level = level(buf)?;
+                let number = uvarint(buf)?;
+                if tag == TAG_NEW_FILE3 {
+                    uvarint(buf)?; // path id
+                }
+                uvarint(buf)?; // size
+                let smallest = InternalKey::user_key_of(read_bytes(buf)?).to_vec();
+                let largest = InternalKey::user_key_of(read_bytes(buf)?).to_vec();
+                let mut largest_seq = 0;
+                if tag != TAG_NEW_FILE {
+                    uvarint(buf)?;
+                    largest_seq = uvarint(buf)?;
+                }
+                if tag == TAG_NEW_FILE4 {
+                    loop {
+                        let custom = uvarint(buf)?;
+                        if custom == CUSTOM_TAG_TERMINATE {
+                            break;
+                        }
+                        if custom & CUSTOM_TAG_NON_SAFE_IGNORE_MASK != 0 {
+                            return Err(ChainDataError(format!(
+                                "unsupported new-file field {custom}"
+                            )));
+                        }
+                        read_bytes(buf)?;
+                    }
+                }
+                files[level].insert(
+                    number,
+                    TableFile {
+                        number,
+                        smallest,
+                        largest,
+                        largest_seq,
+                        table: Mutex::new(None),
+                    },
+                );
+            }
+            other => {
+                return Err(ChainDataError(format!(
+                    "unsupported version edit tag {other}"
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_db::create;
+    use super::*;
+
+    #[test]
+    #[ignore = "needs the seed peer database under ../perf_comparision"]
+    fn reads_seed_peer_database() {
+        let db =
+            ChainData::open("../perf_comparision/seed_peer/seed_peer_data/geth/chaindata").unwrap();
+        // Head pointers come from the WAL, receipts from the one table kept.
+        let head = db.get(b"LastBlock").unwrap().unwrap();
+        assert_eq!(
+            hex::encode(head),
+            "159dc3d801c46a51fbbb40ff3ec098a9aa4a1af27db72f27ae376b1cd68e0e57"
+        );
+        assert_eq!(db.get(b"PruneAncientFlag").unwrap(), Some(Vec::new()));
+        let receipts = hex::decode(
+            "7200000000000170fa1e454a9c129c40370f5030410578065d7986420788c628a3fbe9f3726b898891",
+        )
+        .unwrap();
+        assert_eq!(db.get(&receipts).unwrap(), Some(vec![0xc0]));
+        assert_eq!(db.get(b"unclean-shutdown\xff").unwrap(), None);
+    }
+
+    #[test]
+    fn later_writes_and_deletions_win() {
+        let dir = create(
+            "store",
+            &[
+                vec![
+                    (KIND_SET, b"a".to_vec(), b"1".to_vec()),
+                    (KIND_SET, b"b".to_vec(), b"1".to_vec()),
+                    (KIND_SET, b"c".to_vec(), b"1".to_vec()),
+                ],
+                vec![
+                    (KIND_SET, b"a".to_vec(), b"2".to_vec()),
+                    (KIND_DELETE, b"b".to_vec(), Vec::new()),
+                    (KIND_RANGE_DELETE, b"c".to_vec(), b"d".to_vec()),
+                ],
+            ],
+        );
+        let db = ChainData::open(&dir).unwrap();
+        assert_eq!(db.get(b"a").unwrap(), Some(b"2".to_vec()));
+        assert_eq!(db.get(b"b").unwrap(), None);
+        assert_eq!(db.get(b"c").unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+//! Reader for the block-based `*.sst` tables written by pebble (RocksDB table
+//! format, footer version 2, snappy compression).
+//!
+//! Opening a table loads its index – flattening a two-level index if the
+//! table has one – and its range tombstones.  Data blocks are read from disk
+//! on every lookup and checked against their CRC32c checksum, the only
+//! checksum type geth's pebble writes.  Bloom filters are not consulted.
+
+use std::fs::File;
+use std::path::Path;
+
+use crc::{Crc, CRC_32_ISCSI};
+
+use super::{read_exact_at, uvarint, ChainDataError, InternalKey, RangeTombstone};
+
+const FOOTER_SIZE: usize = 53;
+const TABLE_MAGIC: u64 = 0x88e2_41b7_85f4_cff7;
+const BLOCK_TRAILER_SIZE: usize = 5;
+
+const NO_CHECKSUM: u8 = 0;
+const CRC32C_CHECKSUM: u8 = 1;
+pub(super) const CRC32C: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+/// Added to the rotated CRC stored in block trailers (leveldb's `crc.Mask`).
+const CRC_MASK_DELTA: u32 = 0xa282_ead8;
+
+const NO_COMPRESSION: u8 = 0;
+const SNAPPY_COMPRESSION: u8 = 1;
+
+const PROPERTIES_BLOCK: &[u8] = b"rocksdb.properties";
+const RANGE_DEL_BLOCKS: [&[u8]; 2] = [b"rocksdb.range_del2", b"rocksdb.range_del"];
+const INDEX_TYPE_PROPERTY: &[u8] = b"rocksdb.block.based.table.index.type";
+const TWO_LEVEL_INDEX: u32 = 2;
+
+#[derive(Clone, Copy, Debug)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+impl BlockHandle {
+    fn decode(buf: &mut &[u8]) -> Result<Self, ChainDataError> {
+        Ok(Self {
+            offset: uvarint(buf)?,
+            size: uvarint(buf)?,
+        })
+    }
+}
+
+/// An open, immutable table file.
+#[derive(Debug)]
+pub(super) struct Table {
+    file: File,
+    /// Checksum type from the footer
+    checksum: u8,
+    /// `(separator, handle)` pairs; every key in a block sorts at or before
+    /// its separator.
+    index: Vec<(Vec<u8>, BlockHandle)>,
+    pub(super) tombstones: Vec<RangeTombstone>,
+}
+
+impl Table {
+    pub(super) fn open(path: &Path) -> Result<Self, ChainDataError> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < FOOTER_SIZE as u64 {
+            return Err(ChainDataError::corrupt(path, "file shorter than footer"));
+        }
+
+        let mut footer = [0u8; FOOTER_SIZE];
+        read_exact_at(&file, &mut footer, len - FOOTER_SIZE as u64)?;
+        let magic = u64::from_le_bytes(footer[FOOTER_SIZE - 8..].try_into().unwrap());
+        if magic != TABLE_MAGIC {
+            return Err(ChainDataError::corrupt(path, "unsupported table format"));
+        }
+        // Byte 0 is the checksum type; the handles follow.
+        let checksum = footer[0];
+        if checksum != NO_CHECKSUM && checksum != CRC32C_CHECKSUM {
+            return Err(ChainDataError::corrupt(
+                path,
+                &format!("unsupported checksum type {checksum}"),
+            ));
+        }
+        let mut handles = &footer[1..];
+        let metaindex = BlockHandle::decode(&mut handles)?;
+        let index_handle = BlockHandle::decode(&mut handles)?;
+
+        let mut table = Self {
+            file,
+            checksum,
+            index: Vec::new(),
+            tombstones: Vec::new(),
+        };
+
+        let mut two_level = false;
+        let mut range_del = None;
+        for (name, value) in block_entries(&table.read_block(metaindex)?)? {
+            let handle = BlockHandle::decode(&mut value.as_slice())?;
+            if name == PROPERTIES_BLOCK {
+                for (key, value) in block_entries(&table.read_block(handle)?)? {
+                    if key == INDEX_TYPE_PROPERTY && value.len() == 4 {
+                        two_level =
+                            u32::from_le_bytes(value[..].try_into().unwrap()) == TWO_LEVEL_INDEX;
+                    }
+                }
+            } else if RANGE_DEL_BLOCKS.contains(&name.as_slice()) {
+                range_del = Some(handle);
+            }
+        }
+
+        let mut index = Vec::new();
+        for (separator, value) in block_entries(&table.read_block(index_handle)?)? {
+            let handle = BlockHandle::decode(&mut value.as_slice())?;
+            if two_level {
+                for (key, value) in block_entries(&table.read_block(handle)?)? {
+                    index.push((key, BlockHandle::decode(&mut value.as_slice())?));
+                }
+            } else {
+                index.push((separator, handle));
+            }
+        }
+        table.index = index;
+
+        if let Some(handle) = range_del {
+            for (start, end) in block_entries(&table.read_block(handle)?)? {
+                let key = InternalKey::parse(&start)?;
+                table.tombstones.push(RangeTombstone {
+                    start: key.user_key.to_vec(),
+                    end,
+                    seq: key.seq,
+                });
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// The newest entry for `user_key`, as `(sequence, kind, value)`.
+    pub(super) fn get(
+        &self,
+        user_key: &[u8],
+    ) -> Result<Option<(u64, u8, Vec<u8>)>, ChainDataError> {
+        // First block whose separator is not below the key.  A separator with
+        // the same user key may still leave older versions in the next block,
+        // so keep scanning forward until a key at or past `user_key` shows up.
+        let first = self
+            .index
+            .partition_point(|(separator, _)| InternalKey::user_key_of(separator) < user_key);
+        for (_, handle) in &self.index[first..] {
+            for (key, value) in block_entries(&self.read_block(*handle)?)? {
+                let key = InternalKey::parse(&key)?;
+                match key.user_key.cmp(user_key) {
+                    std::cmp::Ordering::Less => continue,
+                    std::cmp::Ordering::Equal => return Ok(Some((key.seq, key.kind, value))),
+                    std::cmp::Ordering::Greater => return Ok(None),
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn read_block(&self, handle: BlockHandle) -> Result<Vec<u8>, ChainDataError> {
+        let mut buf = vec![0u8; handle.size as usize + BLOCK_TRAILER_SIZE];
+        read_exact_at(&self.file, &mut buf, handle.offset)?;
+        let compression = buf[handle.size as usize];
+        if self.checksum == CRC32C_CHECKSUM {
+            // The checksum covers the block and its compression byte.
+            let stored = u32::from_le_bytes(buf[handle.size as usize + 1..].try_into().unwrap());
+            if mask_crc(CRC32C.checksum(&buf[..=handle.size as usize])) != stored {
+                return Err(ChainDataError(format!(
+                    "block checksum mismatch at offset {}",
+                    handle.offset
+                )));
+            }
+        }
+        buf.truncate(handle.size as usize);
+        match compression {
+            NO_COMPRESSION => Ok(buf),
+            SNAPPY_COMPRESSION => snap::raw::Decoder::new()
+                .decompress_vec(&buf)
+                .map_err(|e| ChainDataError(format!("snappy: {e}"))),
+            other => Err(ChainDataError(format!(
+                "unsupported block compression {other}"
+            ))),
+        }
+    }
+}
+
+/// leveldb's masking of stored CRCs.
+pub(super) fn mask_crc(crc: u32) -> u32 {
+    crc.rotate_right(15).wrapping_add(CRC_MASK_DELTA)
+}
+
+/// A decoded `(key, value)` block entry.
+type Entry = (Vec<u8>, Vec<u8>);
+
+/// Decode every `(key, value)` entry of a block, expanding prefix-compressed
+/// keys.
+fn block_entries(block: &[u8]) -> Result<Vec<Entry>, ChainDataError> {
+    let truncated = || ChainDataError("truncated block".into());
+    if block.len() < 4 {
+        return Err(truncated());
+    }
+    let restarts = u32::from_le_bytes(block[block.len() - 4..].try_into().unwrap()) as usize;
+    let data_len = block
+        .len()
+        .checked_sub(4 + 4 * restarts)
+        .ok_or_else(truncated)?;
+
+    let mut buf = &block[..data_len];
+    let mut key = Vec::new();
+    let mut entries = Vec::new();
+    while !buf.is_empty() {
+        let shared = uvarint(&mut buf)? as usize;
+        let unshared = uvarint(&mut buf)? as usize;
+        let value_len = uvarint(&mut buf)? as usize;
+        if shared > key.len() || buf.len() < unshared + value_len {
+            return Err(truncated());
+        }
+        key.truncate(shared);
+        key.extend_from_slice(&buf[..unshared]);
+        let value = buf[unshared..unshared + value_len].to_vec();
+        buf = &buf[unshared + value_len..];
+        entries.push((key.clone(), value));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_corrupted_blocks() {
+        let block = b"block contents".to_vec();
+        let mut file = block.clone();
+        file.push(NO_COMPRESSION);
+        let crc = mask_crc(CRC32C.checksum(&file));
+        file.extend_from_slice(&crc.to_le_bytes());
+
+        let path = std::env::temp_dir().join(format!("revm-table-{}.sst", std::process::id()));
+        let handle = BlockHandle { offset: 0, size: block.len() as u64 };
+        let open = |contents: &[u8]| {
+            std::fs::write(&path, contents).unwrap();
+            Table {
+                file: File::open(&path).unwrap(),
+                checksum: CRC32C_CHECKSUM,
+                index: Vec::new(),
+                tombstones: Vec::new(),
+            }
+        };
+
+        assert_eq!(open(&file).read_block(handle).unwrap(), block);
+        file[3] ^= 1;
+        let err = open(&file).read_block(handle).unwrap_err();
+        assert_eq!(err.0, "block checksum mismatch at offset 0");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,437 @@
+//! EIP-3155 opcode traces in the format of `geth evm --json`.
+//!
+//! An [`Eip3155Tracer`] attached to an instance traces every execution that
+//! goes through the regular entry points (`revm_execute`,
+//! `revm_call_contract`, the StateDB calls, ...): one JSON line per executed
+//! opcode and a summary line per transaction, written to a file or handed to
+//! a host callback.  Field names, number encodings and key order follow
+//! geth's JSON logger so existing diff tooling can compare the two.  Storage
+//! is not part of geth's output; disable its capture for byte-identical lines.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{c_void, CString};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::os::raw::c_char;
+
+use anyhow::Result;
+use revm::{
+    bytecode::opcode::{self, OpCode},
+    context_interface::{ContextTr, JournalTr},
+    inspector::Inspector,
+    interpreter::{
+        interpreter_types::{InputsTr, Jumps, LoopControl, MemoryTr},
+        CallInputs, CallOutcome, CreateInputs, CreateOutcome, InstructionResult, Interpreter,
+        InterpreterResult,
+    },
+    primitives::{Address, B256},
+};
+use serde::Serialize;
+
+use crate::types::TraceOptionsFFI;
+
+/// Host callback receiving one trace line (JSON, no trailing newline).  The
+/// string is only valid for the duration of the call.
+pub type TraceCallback = unsafe extern "C" fn(user_data: *mut c_void, line: *const c_char);
+
+/// Where trace lines go.
+pub enum TraceSink {
+    File(BufWriter<File>),
+    Callback {
+        callback: TraceCallback,
+        user_data: *mut c_void,
+    },
+}
+
+impl TraceSink {
+    pub fn file(path: &str) -> Result<Self> {
+        Ok(Self::File(BufWriter::new(File::create(path)?)))
+    }
+
+    fn write_line(&mut self, line: &str) {
+        match self {
+            // Tracing is best effort; a full disk must not fail execution.
+            Self::File(file) => {
+                let _ = writeln!(file, "{line}");
+            }
+            Self::Callback {
+                callback,
+                user_data,
+            } => {
+                if let Ok(line) = CString::new(line) {
+                    unsafe { callback(*user_data, line.as_ptr()) };
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Self::File(file) = self {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Which parts of the machine state each line captures.
+#[derive(Clone, Copy, Debug)]
+pub struct TraceOptions {
+    pub memory: bool,
+    pub stack: bool,
+    pub storage: bool,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            memory: true,
+            stack: true,
+            storage: true,
+        }
+    }
+}
+
+impl From<&TraceOptionsFFI> for TraceOptions {
+    fn from(options: &TraceOptionsFFI) -> Self {
+        Self {
+            memory: !options.disable_memory,
+            stack: !options.disable_stack,
+            storage: !options.disable_storage,
+        }
+    }
+}
+
+/// One opcode, in geth's `StructLog` field order.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StepLine<'a> {
+    pc: u64,
+    op: u8,
+    gas: String,
+    gas_cost: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<String>,
+    mem_size: u64,
+    /// `null` when stack capture is off, as in geth.
+    stack: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    storage: Option<&'a BTreeMap<B256, B256>>,
+    depth: u64,
+    refund: u64,
+    op_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+/// End of a transaction, as geth prints it.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SummaryLine {
+    output: String,
+    gas_used: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+/// State captured before an opcode runs; the line is written once its cost
+/// is known.
+struct PendingStep {
+    pc: u64,
+    op: u8,
+    gas: u64,
+    memory: Option<String>,
+    mem_size: u64,
+    stack: Option<Vec<String>>,
+    depth: u64,
+    refund: u64,
+    /// Slot operand of SLOAD/SSTORE.
+    slot: Option<B256>,
+    /// Value operand of SSTORE.
+    value: Option<B256>,
+}
+
+/// Inspector writing EIP-3155 traces to a [`TraceSink`].
+pub struct Eip3155Tracer {
+    sink: TraceSink,
+    options: TraceOptions,
+    pending: Option<PendingStep>,
+    /// Refunds are tracked per frame by the interpreter but reported
+    /// transaction-wide, so remember the total each frame started from.
+    refund_bases: Vec<i64>,
+    last_refund: i64,
+    new_frame: bool,
+    /// Storage seen through SLOAD/SSTORE, per contract.
+    storage: HashMap<Address, BTreeMap<B256, B256>>,
+}
+
+impl Eip3155Tracer {
+    pub fn new(sink: TraceSink, options: TraceOptions) -> Self {
+        Self {
+            sink,
+            options,
+            pending: None,
+            refund_bases: Vec::new(),
+            last_refund: 0,
+            new_frame: false,
+            storage: HashMap::new(),
+        }
+    }
+
+    fn write<T: Serialize>(&mut self, line: &T) {
+        if let Ok(line) = serde_json::to_string(line) {
+            self.sink.write_line(&line);
+        }
+    }
+
+    fn frame_end<CTX: ContextTr>(&mut self, context: &mut CTX, result: &InterpreterResult) {
+        if context.journal().depth() != 0 {
+            return;
+        }
+        let summary = SummaryLine {
+            output: hex::encode(&result.output),
+            gas_used: format!("{:#x}", result.gas.spent()),
+            error: geth_error(result.result),
+        };
+        self.write(&summary);
+        self.sink.flush();
+        self.refund_bases.clear();
+        self.last_refund = 0;
+        self.storage.clear();
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for Eip3155Tracer {
+    fn initialize_interp(&mut self, _interp: &mut Interpreter, _context: &mut CTX) {
+        self.new_frame = true;
+    }
+
+    fn step(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        let depth = context.journal().depth();
+        if self.new_frame {
+            self.refund_bases.truncate(depth.saturating_sub(1));
+            self.refund_bases.push(self.last_refund);
+            self.new_frame = false;
+        } else {
+            self.refund_bases.truncate(depth);
+        }
+        let base = self.refund_bases.last().copied().unwrap_or_default();
+        self.last_refund = base + interp.control.gas().refunded();
+
+        let op = interp.bytecode.opcode();
+        let operand = |n| interp.stack.peek(n).ok().map(B256::from);
+        let (slot, value) = match op {
+            opcode::SLOAD if self.options.storage => (operand(0), None),
+            opcode::SSTORE if self.options.storage => (operand(0), operand(1)),
+            _ => (None, None),
+        };
+        let mem_size = interp.memory.size();
+        self.pending = Some(PendingStep {
+            pc: interp.bytecode.pc() as u64,
+            op,
+            gas: interp.control.gas().remaining(),
+            // geth omits empty memory.
+            memory: (self.options.memory && mem_size > 0)
+                .then(|| format!("0x{}", hex::encode(&*interp.memory.slice(0..mem_size)))),
+            mem_size: mem_size as u64,
+            stack: self.options.stack.then(|| {
+                interp
+                    .stack
+                    .data()
+                    .iter()
+                    .map(|value| format!("{value:#x}"))
+                    .collect()
+            }),
+            depth: depth as u64,
+            refund: self.last_refund.max(0) as u64,
+            slot,
+            value,
+        });
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let Some(step) = self.pending.take() else {
+            return;
+        };
+        let gas_cost = step.gas.saturating_sub(interp.control.gas().remaining());
+        let result = interp.control.instruction_result();
+
+        let storage = match step.slot {
+            Some(slot) if !result.is_error() => {
+                // After SLOAD the loaded value is on top of the stack.
+                let value = step
+                    .value
+                    .or_else(|| interp.stack.peek(0).ok().map(B256::from))
+                    .unwrap_or_default();
+                let storage = self
+                    .storage
+                    .entry(interp.input.target_address())
+                    .or_default();
+                storage.insert(slot, value);
+                Some(storage.clone())
+            }
+            _ => None,
+        };
+        let line = StepLine {
+            pc: step.pc,
+            op: step.op,
+            gas: format!("{:#x}", step.gas),
+            gas_cost: format!("{gas_cost:#x}"),
+            memory: step.memory,
+            mem_size: step.mem_size,
+            stack: step.stack,
+            storage: storage.as_ref(),
+            depth: step.depth,
+            refund: step.refund,
+            op_name: match OpCode::new(step.op) {
+                Some(op) => op.as_str().to_string(),
+                None => format!("opcode {:#x} not defined", step.op),
+            },
+            error: result.is_error().then(|| geth_error(result)).flatten(),
+        };
+        self.write(&line);
+    }
+
+    fn call_end(&mut self, context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.frame_end(context, &outcome.result);
+    }
+
+    fn create_end(
+        &mut self,
+        context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.frame_end(context, &outcome.result);
+    }
+}
+
+/// geth's error message for a failed opcode or frame.
+pub(crate) fn geth_error(result: InstructionResult) -> Option<&'static str> {
+    use InstructionResult::*;
+    Some(match result {
+        Continue | Stop | Return | SelfDestruct | ReturnContract | CallOrCreate => return None,
+        Revert => "execution reverted",
+        CallTooDeep => "max call depth exceeded",
+        OutOfFunds => "insufficient balance for transfer",
+        OutOfGas | MemoryOOG | MemoryLimitOOG | PrecompileOOG | InvalidOperandOOG
+        | ReentrancySentryOOG => "out of gas",
+        OpcodeNotFound | InvalidFEOpcode | NotActivated | EOFOpcodeDisabledInLegacy => {
+            "invalid opcode"
+        }
+        CallNotAllowedInsideStatic | StateChangeDuringStaticCall => "write protection",
+        InvalidJump => "invalid jump destination",
+        StackUnderflow => "stack underflow",
+        StackOverflow => "stack limit reached 1024 (1023)",
+        OutOfOffset => "return data out of bounds",
+        CreateCollision => "contract address collision",
+        NonceOverflow => "nonce uint64 overflow",
+        CreateContractSizeLimit => "max code size exceeded",
+        CreateContractStartingWithEF => "invalid code: must not begin with 0xef",
+        CreateInitCodeSizeLimit => "max initcode size exceeded",
+        OverflowPayment => "gas uint64 overflow",
+        _ => "execution failed",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::U256;
+    use std::ffi::{c_void, CStr, CString};
+
+    /// `SSTORE(0, 0x2a); POP(SLOAD(0)); STOP`
+    const RUNTIME: [u8; 8] = [0x60, 0x2a, 0x5f, 0x55, 0x5f, 0x54, 0x50, 0x00];
+
+    unsafe extern "C" fn collect(user_data: *mut c_void, line: *const c_char) {
+        let lines = &mut *(user_data as *mut Vec<String>);
+        lines.push(CStr::from_ptr(line).to_str().unwrap().to_string());
+    }
+
+    #[test]
+    fn traces_in_geth_json_format() {
+        // In-memory instance, everything captured.
+        let mut lines: Vec<String> = Vec::new();
+        unsafe {
+            let instance = instance_with(&RUNTIME);
+            let options = std::ptr::null();
+            assert_eq!(
+                revm_set_tracer_callback(
+                    instance,
+                    collect,
+                    &mut lines as *mut Vec<String> as *mut c_void,
+                    options
+                ),
+                0
+            );
+            let result = call(instance, CONTRACT);
+            assert_eq!((*result).success, 1);
+            revm_free_execution_result(result);
+            revm_free(instance);
+        }
+        // Seven opcodes and the summary.
+        assert_eq!(lines.len(), 8, "{lines:#?}");
+        assert_eq!(
+            lines[0],
+            r#"{"pc":0,"op":96,"gas":"0x13498","gasCost":"0x3","memSize":0,"stack":[],"depth":1,"refund":0,"opName":"PUSH1"}"#
+        );
+        let lines: Vec<serde_json::Value> = lines
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let slot = format!("{:#x}", revm::primitives::B256::ZERO);
+        let value = format!("{:#x}", revm::primitives::B256::from(U256::from(0x2a)));
+        assert_eq!(lines[2]["opName"], "SSTORE");
+        assert_eq!(lines[2]["gasCost"], "0x5654");
+        assert_eq!(lines[2]["stack"], serde_json::json!(["0x2a", "0x0"]));
+        assert_eq!(lines[2]["storage"][&slot], value);
+        assert_eq!(lines[4]["opName"], "SLOAD");
+        assert_eq!(lines[4]["storage"][&slot], value);
+        assert_eq!(lines[5]["stack"], serde_json::json!(["0x2a"]));
+        assert_eq!(lines[6]["opName"], "STOP");
+        let summary = lines.last().unwrap();
+        assert_eq!(summary["output"], "");
+        assert_eq!(summary["gasUsed"], "0x56c1");
+        assert!(summary.get("error").is_none());
+
+        // StateDB instance tracing to a file with captures disabled.
+        let path = std::env::temp_dir().join(format!("revm-trace-{}.jsonl", std::process::id()));
+        let path_c = CString::new(path.to_str().unwrap()).unwrap();
+        let host = host_with(&RUNTIME);
+        let (from, to) = (c_address(CALLER), c_address(CONTRACT));
+        let options = TraceOptionsFFI {
+            disable_memory: true,
+            disable_stack: true,
+            disable_storage: true,
+        };
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+            assert_eq!(
+                revm_statedb_set_tracer(instance, path_c.as_ptr(), &options),
+                0
+            );
+            let result = revm_call_contract_statedb(
+                instance,
+                from.as_ptr(),
+                to.as_ptr(),
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+                100_000,
+            );
+            assert_eq!((*result).success, 1);
+            revm_free_execution_result(result);
+            revm_statedb_clear_tracer(instance);
+            revm_free_statedb_instance(instance);
+        }
+        let traced = std::fs::read_to_string(&path).unwrap();
+        let file_lines: Vec<&str> = traced.lines().collect();
+        assert_eq!(file_lines.len(), lines.len());
+        assert!(file_lines[2].contains(r#""stack":null"#));
+        assert!(!file_lines[2].contains("storage"));
+        assert_eq!(
+            file_lines.last().unwrap(),
+            &r#"{"output":"","gasUsed":"0x56c1"}"#
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
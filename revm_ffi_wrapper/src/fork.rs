@@ -0,0 +1,361 @@
+//! Copy-on-write forks of in-memory `RevmInstance`s.
+//!
+//! An instance's database is a `CacheDB<ForkedDB>`: a private write overlay on
+//! top of a chain of frozen, reference-counted parent layers.  Forking moves
+//! the parent's overlay into a new shared layer and gives both parent and
+//! child a fresh, empty overlay on top of it, so the cost does not depend on
+//! the number of accounts loaded.  Writes made after the fork are visible only
+//! to the instance that made them.
+//!
+//! Reads walk the layers from the top, so a chain of forks made after writes
+//! would make every read slower.  Once a fork would leave more than
+//! [`MAX_FORK_DEPTH`] layers, the frozen ones are merged into a single layer,
+//! a copy proportional to the state held in memory.  Block hashes that have
+//! left the BLOCKHASH window are dropped while merging; until then a frozen
+//! layer keeps every hash it was frozen with.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use revm::{
+    bytecode::Bytecode,
+    database::{AccountState, Cache, CacheDB, DbAccount},
+    database_interface::DatabaseRef,
+    handler::MainnetContext,
+    primitives::{Address, HashMap, StorageKey, StorageValue, B256, U256},
+    state::AccountInfo,
+    MainBuilder,
+};
+
+use crate::block_hashes::BLOCK_HASH_WINDOW;
+use crate::types::RevmInstance;
+
+/// Layers a database may read through, its own overlay included, before the
+/// frozen ones are merged.
+pub(crate) const MAX_FORK_DEPTH: usize = 8;
+
+/// Read-only view of a frozen parent layer.  With no parent it holds
+/// nothing: accounts are missing, storage is zero and so is the hash of every
+/// block that was not registered, as geth returns for unknown ancestors.
+#[derive(Clone, Debug, Default)]
+pub struct ForkedDB {
+    parent: Option<Arc<CacheDB<ForkedDB>>>,
+}
+
+impl ForkedDB {
+    fn from_parent(parent: Arc<CacheDB<ForkedDB>>) -> Self {
+        Self { parent: Some(parent) }
+    }
+
+    /// The frozen layer this database reads through to, if any.
+    pub fn parent(&self) -> Option<&CacheDB<ForkedDB>> {
+        self.parent.as_deref()
+    }
+}
+
+impl DatabaseRef for ForkedDB {
+    type Error = Infallible;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        match &self.parent {
+            Some(parent) => parent.basic_ref(address),
+            None => Ok(None),
+        }
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        match &self.parent {
+            Some(parent) => parent.code_by_hash_ref(code_hash),
+            None => Ok(Bytecode::default()),
+        }
+    }
+
+    fn storage_ref(
+        &self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        match &self.parent {
+            Some(parent) => parent.storage_ref(address, index),
+            None => Ok(StorageValue::ZERO),
+        }
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        match &self.parent {
+            Some(parent) => parent.block_hash_ref(number),
+            None => Ok(B256::ZERO),
+        }
+    }
+}
+
+/// An account as seen through every layer of a `CacheDB<ForkedDB>`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FlatAccount {
+    pub(crate) info: AccountInfo,
+    /// Non-zero storage slots.
+    pub(crate) storage: HashMap<StorageKey, StorageValue>,
+}
+
+/// Caches of `db` and all frozen layers below it, bottom layer first.
+pub(crate) fn layers(db: &CacheDB<ForkedDB>) -> Vec<&Cache> {
+    let mut layers = vec![&db.cache];
+    let mut next = db.db.parent();
+    while let Some(layer) = next {
+        layers.push(&layer.cache);
+        next = layer.db.parent();
+    }
+    layers.reverse();
+    layers
+}
+
+/// Apply one layer's view of an account on top of what the lower layers hold.
+fn merge_account(flat: &mut HashMap<Address, FlatAccount>, address: Address, account: &DbAccount) {
+    match account.account_state {
+        AccountState::NotExisting => {
+            flat.remove(&address);
+        }
+        AccountState::StorageCleared => {
+            flat.insert(
+                address,
+                FlatAccount { info: account.info.clone(), storage: account.storage.clone() },
+            );
+        }
+        AccountState::Touched | AccountState::None => {
+            let entry = flat.entry(address).or_default();
+            entry.info = account.info.clone();
+            entry.storage.extend(account.storage.iter().map(|(k, v)| (*k, *v)));
+        }
+    }
+}
+
+/// A single parentless layer holding everything visible through `db`, minus
+/// the hashes of blocks before `oldest_block`.
+fn collapse(db: &CacheDB<ForkedDB>, oldest_block: u64) -> CacheDB<ForkedDB> {
+    let oldest_block = U256::from(oldest_block);
+    let mut flat = CacheDB::new(ForkedDB::default());
+    for cache in layers(db) {
+        flat.cache.contracts.extend(cache.contracts.iter().map(|(hash, code)| (*hash, code.clone())));
+        flat.cache.block_hashes.extend(
+            cache
+                .block_hashes
+                .iter()
+                .filter(|(n, _)| **n >= oldest_block)
+                .map(|(n, hash)| (*n, *hash)),
+        );
+        for (address, account) in &cache.accounts {
+            match (flat.cache.accounts.get_mut(address), &account.account_state) {
+                (Some(lower), AccountState::Touched | AccountState::None) => {
+                    lower.info = account.info.clone();
+                    lower.storage.extend(account.storage.iter().map(|(k, v)| (*k, *v)));
+                    if lower.account_state == AccountState::NotExisting {
+                        lower.account_state = AccountState::Touched;
+                    }
+                }
+                _ => {
+                    flat.cache.accounts.insert(*address, account.clone());
+                }
+            }
+        }
+    }
+    flat
+}
+
+/// Every existing account visible through `db`, with its full storage.
+pub(crate) fn flatten_accounts(db: &CacheDB<ForkedDB>) -> HashMap<Address, FlatAccount> {
+    let mut flat = HashMap::default();
+    for cache in layers(db) {
+        for (address, account) in &cache.accounts {
+            merge_account(&mut flat, *address, account);
+        }
+    }
+    for account in flat.values_mut() {
+        account.storage.retain(|_, value| !value.is_zero());
+    }
+    flat
+}
+
+/// Full storage of a single account visible through `db`.
+pub(crate) fn flatten_account(db: &CacheDB<ForkedDB>, address: Address) -> Option<FlatAccount> {
+    let mut flat = HashMap::default();
+    for cache in layers(db) {
+        if let Some(account) = cache.accounts.get(&address) {
+            merge_account(&mut flat, address, account);
+        }
+    }
+    let mut account = flat.remove(&address)?;
+    account.storage.retain(|_, value| !value.is_zero());
+    Some(account)
+}
+
+impl RevmInstance {
+    /// Create an independent copy-on-write fork of this instance.
+    ///
+    /// The child shares the current state read-only and inherits the
+    /// configuration and block environment; checkpoints are not inherited and
+    /// its state trie is rebuilt on the first root request.
+    pub fn fork(&mut self) -> RevmInstance {
+        let oldest_block = self.evm.ctx.block.number.saturating_sub(BLOCK_HASH_WINDOW);
+        let db = &mut self.evm.ctx.journaled_state.database;
+        let cache = &db.cache;
+        let base = if cache.accounts.is_empty()
+            && cache.contracts.is_empty()
+            && cache.block_hashes.is_empty()
+            && cache.logs.is_empty()
+        {
+            // Nothing written since the last fork – share the same layer
+            // instead of stacking an empty one.
+            db.db.clone()
+        } else {
+            let mut frozen = std::mem::take(db);
+            if layers(&frozen).len() >= MAX_FORK_DEPTH {
+                frozen = collapse(&frozen, oldest_block);
+            }
+            let frozen = Arc::new(frozen);
+            *db = CacheDB::new(ForkedDB::from_parent(frozen.clone()));
+            ForkedDB::from_parent(frozen)
+        };
+
+        let ctx = &self.evm.ctx;
+        let context = MainnetContext::new(CacheDB::new(base), ctx.cfg.spec)
+            .with_cfg(ctx.cfg.clone())
+            .with_block(ctx.block.clone());
+
+        RevmInstance {
+            evm: context.build_mainnet(),
+            last_error: None,
+            snapshots: Default::default(),
+            next_snapshot_id: 1,
+            state_trie: None,
+            inspectors: Default::default(),
+            block_witness: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use revm::context_interface::{context::ContextTr, journaled_state::JournalTr};
+    use revm::database_interface::Database;
+    use revm::primitives::{Address, U256};
+    use revm::state::AccountInfo;
+
+    fn balance(instance: &mut RevmInstance, addr: Address) -> U256 {
+        instance
+            .evm
+            .ctx()
+            .journal()
+            .db()
+            .basic(addr)
+            .unwrap()
+            .map(|acc| acc.balance)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn fork_isolates_writes() {
+        unsafe {
+            let parent = revm_new();
+            let db = (*parent).evm.ctx().journal().db();
+            for i in 0..20_000u64 {
+                let info = AccountInfo { balance: U256::from(i), ..Default::default() };
+                db.insert_account_info(Address::with_last_byte(0).create(i), info);
+            }
+            let alice = Address::repeat_byte(0xaa);
+            db.insert_account_info(alice, AccountInfo { balance: U256::from(1), ..Default::default() });
+            db.insert_account_storage(alice, U256::from(7), U256::from(70)).unwrap();
+
+            let child = revm_fork_instance(parent);
+            assert!(!child.is_null());
+            let grandchild = revm_fork_instance(child);
+
+            (*child)
+                .evm
+                .ctx()
+                .journal()
+                .db()
+                .insert_account_info(alice, AccountInfo { balance: U256::from(2), ..Default::default() });
+            (*parent)
+                .evm
+                .ctx()
+                .journal()
+                .db()
+                .insert_account_storage(alice, U256::from(7), U256::from(71))
+                .unwrap();
+
+            assert_eq!(balance(&mut *parent, alice), U256::from(1));
+            assert_eq!(balance(&mut *child, alice), U256::from(2));
+            assert_eq!(balance(&mut *grandchild, alice), U256::from(1));
+            assert_eq!(
+                balance(&mut *child, Address::with_last_byte(0).create(123)),
+                U256::from(123)
+            );
+
+            let slot = |inst: *mut RevmInstance| {
+                (*inst).evm.ctx().journal().db().storage(alice, U256::from(7)).unwrap()
+            };
+            assert_eq!(slot(parent), U256::from(71));
+            assert_eq!(slot(child), U256::from(70));
+            assert_eq!(slot(grandchild), U256::from(70));
+
+            revm_free(parent);
+            assert_eq!(balance(&mut *grandchild, alice), U256::from(1), "layers outlive the parent");
+            revm_free(child);
+            revm_free(grandchild);
+        }
+    }
+
+    #[test]
+    fn fork_chain_depth_is_bounded() {
+        unsafe {
+            let alice = Address::repeat_byte(0xaa);
+            let mut instance = revm_new();
+            let info = AccountInfo { balance: U256::from(1), ..Default::default() };
+            (*instance).evm.ctx().journal().db().insert_account_info(alice, info);
+            let mut forks = vec![];
+            for i in 0..3 * fork::MAX_FORK_DEPTH as u64 {
+                let db = (*instance).evm.ctx().journal().db();
+                db.insert_account_storage(alice, U256::from(i), U256::from(i + 1)).unwrap();
+                forks.push(instance);
+                instance = revm_fork_instance(instance);
+                let db = &(*instance).evm.ctx.journaled_state.database;
+                assert!(fork::layers(db).len() <= fork::MAX_FORK_DEPTH);
+            }
+
+            let db = (*instance).evm.ctx().journal().db();
+            for i in 0..3 * fork::MAX_FORK_DEPTH as u64 {
+                assert_eq!(db.storage(alice, U256::from(i)).unwrap(), U256::from(i + 1));
+            }
+            let first = forks[0];
+            assert_eq!((*first).evm.ctx().journal().db().storage(alice, U256::from(1)).unwrap(), U256::ZERO);
+            for fork in forks {
+                revm_free(fork);
+            }
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn revert_across_fork_drops_frozen_writes() {
+        unsafe {
+            let parent = revm_new();
+            let alice = Address::repeat_byte(0xaa);
+            let id = revm_snapshot(parent);
+            (*parent)
+                .evm
+                .ctx()
+                .journal()
+                .db()
+                .insert_account_info(alice, AccountInfo { balance: U256::from(5), ..Default::default() });
+
+            let child = revm_fork_instance(parent);
+            assert_eq!(revm_revert_to(parent, id), 0);
+            assert_eq!(balance(&mut *parent, alice), U256::ZERO);
+            assert_eq!(balance(&mut *child, alice), U256::from(5));
+
+            revm_free(parent);
+            revm_free(child);
+        }
+    }
+}
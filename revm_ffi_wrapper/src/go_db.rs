@@ -4,24 +4,24 @@
 //! REVM's `Database`/`DatabaseRef` traits.  A single `GoDatabase` just wraps an
 //! opaque handle (`usize`) that Go gives us.  All heavy lifting is delegated
 //! to the callbacks.
+//!
+//! Bytecode is cached by code hash.  Since it is content-addressed it stays
+//! valid when the instance is rebound to another handle; block hashes are
+//! cached per handle and dropped on rebind.
 
+use crate::recorder::{RecordEntry, Recorder};
 use crate::statedb_types::{FFIAccountInfo, FFIAddress, FFIHash, FFIU256};
 use libc::free;
 use revm::bytecode::Bytecode;
 use revm::database_interface::{Database, DatabaseRef, DBErrorMarker};
-use revm::primitives::{Address, Bytes, StorageKey, StorageValue, B256, U256};
+use revm::primitives::{Address, Bytes, HashMap, StorageKey, StorageValue, B256, KECCAK_EMPTY, U256};
 use revm::state::AccountInfo;
 use std::ffi::c_void;
 use std::ptr;
 use std::{error::Error, fmt};
-use core::sync::atomic::{AtomicUsize, Ordering};
 use revm::state::Account;
-use std::collections::HashMap;
 use revm::database_interface::DatabaseCommit;
 
-#[cfg(test)]
-pub static TEST_LAST_HANDLE: AtomicUsize = AtomicUsize::new(0);
-
 /// Type alias for the error we bubble up.  We keep it simple for now – every
 /// failure returns a descriptive string.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -38,16 +38,50 @@ impl Error for GoDBError {}
 impl DBErrorMarker for GoDBError {}
 
 /// Opaque database that forwards requests to Go.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct GoDatabase {
     handle: usize,
+    /// Bytecode keyed by code hash – independent of the bound state.
+    code_cache: HashMap<B256, Bytecode>,
+    /// Block hashes served by the current handle.
+    block_hash_cache: HashMap<u64, B256>,
+    /// Traffic recorder, see `recorder.rs`.
+    recorder: Option<Recorder>,
 }
 
 impl GoDatabase {
     /// Safety: `handle` must be a valid value previously obtained from the Go
     /// side via `NewStateDB`.  No further lifetime guarantees are made.
     pub fn new(handle: usize) -> Self {
-        Self { handle }
+        Self {
+            handle,
+            code_cache: HashMap::default(),
+            block_hash_cache: HashMap::default(),
+            recorder: None,
+        }
+    }
+
+    /// Handle of the Go StateDB currently backing this database.
+    pub fn handle(&self) -> usize {
+        self.handle
+    }
+
+    /// Point the database at another Go StateDB.  Warm bytecode is kept,
+    /// everything tied to the previous state is dropped.
+    pub fn set_handle(&mut self, handle: usize) {
+        self.handle = handle;
+        self.block_hash_cache.clear();
+    }
+
+    /// Start (`Some`) or stop (`None`) recording callback traffic.  Returns
+    /// the recorder that was active.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+        std::mem::replace(&mut self.recorder, recorder)
+    }
+
+    /// The active recorder, if any.
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
     }
 
     fn address_to_ffi(addr: Address) -> FFIAddress {
@@ -116,10 +150,9 @@ fn ffi_account_to_revm(acc: &FFIAccountInfo) -> AccountInfo {
 //  Trait impls
 // ---------------------------------------------------------------------------
 
-impl DatabaseRef for GoDatabase {
-    type Error = GoDBError;
-
-    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+/// Raw callbacks; the `DatabaseRef` impl below adds recording on top.
+impl GoDatabase {
+    fn fetch_basic(&self, address: Address) -> Result<Option<AccountInfo>, GoDBError> {
         unsafe {
             let mut out_info = FFIAccountInfo {
                 balance: FFIU256 { bytes: [0u8; 32] },
@@ -139,7 +172,13 @@ impl DatabaseRef for GoDatabase {
         }
     }
 
-    fn code_by_hash_ref(&self, code_hash: B256) -> Result<revm::state::Bytecode, Self::Error> {
+    fn fetch_code(&self, code_hash: B256) -> Result<revm::state::Bytecode, GoDBError> {
+        if code_hash == KECCAK_EMPTY {
+            return Ok(Bytecode::new());
+        }
+        if let Some(code) = self.code_cache.get(&code_hash) {
+            return Ok(code.clone());
+        }
         unsafe {
             let mut ptr: *mut u8 = ptr::null_mut();
             let mut len: u32 = 0;
@@ -166,11 +205,11 @@ impl DatabaseRef for GoDatabase {
         }
     }
 
-    fn storage_ref(
+    fn fetch_storage(
         &self,
         address: Address,
         index: StorageKey,
-    ) -> Result<StorageValue, Self::Error> {
+    ) -> Result<StorageValue, GoDBError> {
         unsafe {
             let mut out = FFIU256 { bytes: [0u8; 32] };
             let ret = re_state_storage(
@@ -186,7 +225,10 @@ impl DatabaseRef for GoDatabase {
         }
     }
 
-    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+    fn fetch_block_hash(&self, number: u64) -> Result<B256, GoDBError> {
+        if let Some(hash) = self.block_hash_cache.get(&number) {
+            return Ok(*hash);
+        }
         unsafe {
             let mut out = FFIHash { bytes: [0u8; 32] };
             let ret = re_state_block_hash(self.handle, number, &mut out as *mut _);
@@ -198,6 +240,62 @@ impl DatabaseRef for GoDatabase {
     }
 }
 
+impl DatabaseRef for GoDatabase {
+    type Error = GoDBError;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        let response = self.fetch_basic(address);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&RecordEntry::Basic {
+                address,
+                response: response.clone().map_err(|e| e.0),
+            });
+        }
+        response
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<revm::state::Bytecode, Self::Error> {
+        let response = self.fetch_code(code_hash);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&RecordEntry::Code {
+                code_hash,
+                response: response
+                    .as_ref()
+                    .map(|code| code.original_bytes())
+                    .map_err(|e| e.0.clone()),
+            });
+        }
+        response
+    }
+
+    fn storage_ref(
+        &self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        let response = self.fetch_storage(address, index);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&RecordEntry::Storage {
+                address,
+                slot: index,
+                response: response.clone().map_err(|e| e.0),
+            });
+        }
+        response
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        let response = self.fetch_block_hash(number);
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&RecordEntry::BlockHash {
+                number,
+                response: response.clone().map_err(|e| e.0),
+            });
+        }
+        response
+    }
+}
+
 impl Database for GoDatabase {
     type Error = GoDBError;
 
@@ -206,7 +304,12 @@ impl Database for GoDatabase {
     }
 
     fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
-        self.code_by_hash_ref(code_hash)
+        let code = self.code_by_hash_ref(code_hash)?;
+        // An empty result may just mean "not found"; only cache real code.
+        if !code.is_empty() {
+            self.code_cache.entry(code_hash).or_insert_with(|| code.clone());
+        }
+        Ok(code)
     }
 
     fn storage(
@@ -218,13 +321,18 @@ impl Database for GoDatabase {
     }
 
     fn block_hash(&mut self, number: u64) -> Result<B256, Self::Error> {
-        self.block_hash_ref(number)
+        let hash = self.block_hash_ref(number)?;
+        self.block_hash_cache.insert(number, hash);
+        Ok(hash)
     }
 }
 
 impl DatabaseCommit for GoDatabase {
     fn commit(&mut self, changes: HashMap<Address, Account>) {
         println!("[Rust] GoDatabase.commit invoked, {} account(s)", changes.len());
+        if let Some(recorder) = &self.recorder {
+            recorder.record_commit(&changes);
+        }
         for (addr, account) in changes {
             // Debug print
             println!(
@@ -259,93 +367,45 @@ impl DatabaseCommit for GoDatabase {
 }
 
 // ---------------------------------------------------------------------------
-//  Unit tests with mocked FFI callbacks
+//  Unit tests against the in-memory mock host
 // ---------------------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    static CALLS_BASIC: AtomicUsize = AtomicUsize::new(0);
-
-    // --- Mock implementations ---
-    #[no_mangle]
-    extern "C" fn re_state_basic(
-        _handle: usize,
-        _addr: FFIAddress,
-        out_info: *mut FFIAccountInfo,
-    ) -> i32 {
-        unsafe {
-            let info = FFIAccountInfo {
-                balance: FFIU256 { bytes: [0u8; 32] },
-                nonce: 42,
-                code_hash: FFIHash { bytes: [0u8; 32] },
-            };
-            *out_info = info;
-        }
-        #[cfg(test)]
-        {
-            TEST_LAST_HANDLE.store(_handle, Ordering::SeqCst);
-        }
-        CALLS_BASIC.fetch_add(1, Ordering::SeqCst);
-        0
-    }
-
-    #[no_mangle]
-    extern "C" fn re_state_storage(
-        _handle: usize,
-        _addr: FFIAddress,
-        _slot: FFIHash,
-        out_val: *mut FFIU256,
-    ) -> i32 {
-        unsafe {
-            *out_val = FFIU256 { bytes: [1u8; 32] };
-        }
-        0
-    }
-
-    #[no_mangle]
-    extern "C" fn re_state_block_hash(
-        _handle: usize,
-        _number: u64,
-        out_hash: *mut FFIHash,
-    ) -> i32 {
-        unsafe {
-            *out_hash = FFIHash { bytes: [2u8; 32] };
-        }
-        0
-    }
+    use crate::mock_host::{HostCall, MockHost};
 
-    #[no_mangle]
-    extern "C" fn re_state_code(
-        _handle: usize,
-        _code_hash: FFIHash,
-        out_ptr: *mut *mut u8,
-        out_len: *mut u32,
-    ) -> i32 {
-        let data = vec![0xde, 0xad, 0xbe, 0xef];
-        unsafe {
-            let cbuf = libc::malloc(data.len()) as *mut u8;
-            ptr::copy_nonoverlapping(data.as_ptr(), cbuf, data.len());
-            *out_ptr = cbuf;
-            *out_len = data.len() as u32;
-        }
-        0
+    fn host() -> MockHost {
+        let host = MockHost::new();
+        host.insert_account(
+            Address::ZERO,
+            AccountInfo {
+                nonce: 42,
+                code_hash: B256::ZERO,
+                ..Default::default()
+            },
+        );
+        host.set_storage(Address::ZERO, U256::ZERO, U256::from_be_bytes([1u8; 32]));
+        host.set_code(B256::ZERO, Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]));
+        host
     }
 
     #[test]
     fn test_basic() {
-        let db = GoDatabase::new(1);
+        let host = host();
+        let db = GoDatabase::new(host.handle());
         let info = db
             .basic_ref(Address::ZERO)
             .expect("basic success")
             .expect("some");
         assert_eq!(info.nonce, 42);
-        assert_eq!(CALLS_BASIC.load(Ordering::SeqCst), 1);
+        assert_eq!(host.calls(HostCall::Basic), 1);
     }
 
     #[test]
     fn test_storage() {
-        let db = GoDatabase::new(1);
+        let host = host();
+        let db = GoDatabase::new(host.handle());
         let val = db
             .storage_ref(Address::ZERO, U256::ZERO)
             .expect("storage success");
@@ -354,10 +414,29 @@ mod tests {
 
     #[test]
     fn test_code() {
-        let db = GoDatabase::new(1);
+        let host = host();
+        let db = GoDatabase::new(host.handle());
         let bc = db
             .code_by_hash_ref(B256::ZERO)
             .expect("code");
         assert!(bc.bytes_slice().starts_with(&[0xde, 0xad, 0xbe, 0xef]));
     }
+
+    #[test]
+    fn test_set_handle_keeps_code_cache() {
+        let host = host();
+        let mut db = GoDatabase::new(host.handle());
+        let hash = B256::repeat_byte(0xcc);
+        host.set_code(hash, Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]));
+        db.code_by_hash(hash).expect("code");
+        db.block_hash(7).expect("block hash");
+        assert!(db.code_cache.contains_key(&hash));
+        assert!(db.block_hash_cache.contains_key(&7));
+
+        let other = MockHost::new();
+        db.set_handle(other.handle());
+        assert_eq!(db.handle(), other.handle());
+        assert!(db.code_cache.contains_key(&hash), "bytecode survives rebind");
+        assert!(db.block_hash_cache.is_empty(), "per-state cache is dropped");
+    }
 } 
\ No newline at end of file
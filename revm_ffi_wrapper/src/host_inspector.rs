@@ -0,0 +1,452 @@
+//! Inspector callbacks implemented by the host.
+//!
+//! The host registers an [`InspectorCallbacksFFI`] table; every callback is
+//! optional and receives the registered `user_data` together with a pointer
+//! to a `#[repr(C)]` record that is only valid for the duration of the call.
+//! Frames are reported on entry and exit, logs and selfdestructs as they
+//! happen and, when `on_step` is set, every opcode before it executes.
+
+use std::ffi::{c_void, CString};
+use std::os::raw::{c_char, c_uint};
+use std::ptr;
+
+use revm::{
+    context_interface::{ContextTr, JournalTr},
+    inspector::Inspector,
+    interpreter::{
+        interpreter_types::{InputsTr, Jumps, LoopControl, MemoryTr},
+        CallInputs, CallOutcome, CallScheme, CreateInputs, CreateOutcome, CreateScheme,
+        Interpreter, InterpreterResult,
+    },
+    primitives::{Address, Log, U256},
+};
+
+use crate::eip3155::geth_error;
+use crate::statedb_types::{FFIAddress, FFIHash, FFIU256};
+
+/// Kind of a frame reported to `on_enter`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKindFFI {
+    Call = 0,
+    StaticCall = 1,
+    DelegateCall = 2,
+    CallCode = 3,
+    Create = 4,
+    Create2 = 5,
+}
+
+/// A call or create frame being entered.
+#[repr(C)]
+pub struct FrameEnterFFI {
+    /// 1 for the transaction's top frame
+    pub depth: c_uint,
+    pub kind: FrameKindFFI,
+    pub caller: FFIAddress,
+    /// Account the frame runs as; the address to be created for creates
+    pub target: FFIAddress,
+    /// Account whose code runs; equals `target` except for DELEGATECALL and
+    /// CALLCODE
+    pub code_address: FFIAddress,
+    pub value: FFIU256,
+    pub gas_limit: u64,
+    /// Calldata, or init code for creates
+    pub input: *const u8,
+    pub input_len: c_uint,
+}
+
+/// A frame that has returned.
+#[repr(C)]
+pub struct FrameExitFFI {
+    pub depth: c_uint,
+    pub success: bool,
+    /// True when the frame ended in REVERT rather than an exceptional halt
+    pub reverted: bool,
+    pub gas_used: u64,
+    pub output: *const u8,
+    pub output_len: c_uint,
+    /// The new contract's address for successful creates, zero otherwise
+    pub created_address: FFIAddress,
+    /// geth's error message for failed frames, null on success
+    pub error: *const c_char,
+}
+
+/// A log emitted by the frame at `depth`.
+#[repr(C)]
+pub struct LogEventFFI {
+    pub depth: c_uint,
+    pub address: FFIAddress,
+    pub topics: *const FFIHash,
+    pub topics_count: c_uint,
+    pub data: *const u8,
+    pub data_len: c_uint,
+}
+
+/// A contract selfdestructing in the frame at `depth`.
+#[repr(C)]
+pub struct SelfDestructFFI {
+    pub depth: c_uint,
+    pub contract: FFIAddress,
+    pub beneficiary: FFIAddress,
+    pub value: FFIU256,
+}
+
+/// An opcode about to execute.
+#[repr(C)]
+pub struct StepFFI {
+    pub depth: c_uint,
+    pub pc: u64,
+    pub opcode: u8,
+    /// Gas left before the opcode
+    pub gas_remaining: u64,
+    /// Account whose storage the frame uses
+    pub address: FFIAddress,
+    /// The stack, bottom first
+    pub stack: *const FFIU256,
+    pub stack_len: c_uint,
+    pub memory: *const u8,
+    pub memory_len: c_uint,
+}
+
+pub type FrameEnterCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, frame: *const FrameEnterFFI);
+pub type FrameExitCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, frame: *const FrameExitFFI);
+pub type LogCallback = unsafe extern "C" fn(user_data: *mut c_void, log: *const LogEventFFI);
+pub type SelfDestructCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, selfdestruct: *const SelfDestructFFI);
+pub type StepCallback = unsafe extern "C" fn(user_data: *mut c_void, step: *const StepFFI);
+
+/// Callbacks registered by the host; null entries are skipped.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InspectorCallbacksFFI {
+    pub on_enter: Option<FrameEnterCallback>,
+    pub on_exit: Option<FrameExitCallback>,
+    pub on_log: Option<LogCallback>,
+    pub on_selfdestruct: Option<SelfDestructCallback>,
+    pub on_step: Option<StepCallback>,
+}
+
+/// Inspector forwarding execution events to host callbacks.
+pub struct HostInspector {
+    callbacks: InspectorCallbacksFFI,
+    user_data: *mut c_void,
+    depth: c_uint,
+}
+
+impl HostInspector {
+    pub fn new(callbacks: InspectorCallbacksFFI, user_data: *mut c_void) -> Self {
+        Self {
+            callbacks,
+            user_data,
+            depth: 0,
+        }
+    }
+
+    /// Report `frame`, whose depth is filled in here.
+    fn enter(&mut self, mut frame: FrameEnterFFI) {
+        self.depth += 1;
+        if let Some(on_enter) = self.callbacks.on_enter {
+            frame.depth = self.depth;
+            unsafe { on_enter(self.user_data, &frame) };
+        }
+    }
+
+    fn exit(&mut self, result: &InterpreterResult, created_address: Option<Address>) {
+        let depth = self.depth;
+        self.depth = self.depth.saturating_sub(1);
+        let Some(on_exit) = self.callbacks.on_exit else {
+            return;
+        };
+        let success = result.result.is_ok();
+        let error = geth_error(result.result).and_then(|error| CString::new(error).ok());
+        let frame = FrameExitFFI {
+            depth,
+            success,
+            reverted: result.result.is_revert(),
+            gas_used: result.gas.spent(),
+            output: result.output.as_ptr(),
+            output_len: result.output.len() as c_uint,
+            created_address: address_ffi(created_address.filter(|_| success).unwrap_or_default()),
+            error: error.as_ref().map_or(ptr::null(), |error| error.as_ptr()),
+        };
+        unsafe { on_exit(self.user_data, &frame) };
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for HostInspector {
+    fn step(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let Some(on_step) = self.callbacks.on_step else {
+            return;
+        };
+        let stack: Vec<FFIU256> = interp.stack.data().iter().copied().map(u256_ffi).collect();
+        let memory_len = interp.memory.size();
+        let memory = (memory_len > 0).then(|| interp.memory.slice(0..memory_len));
+        let step = StepFFI {
+            depth: self.depth,
+            pc: interp.bytecode.pc() as u64,
+            opcode: interp.bytecode.opcode(),
+            gas_remaining: interp.control.gas().remaining(),
+            address: address_ffi(interp.input.target_address()),
+            stack: stack.as_ptr(),
+            stack_len: stack.len() as c_uint,
+            memory: memory
+                .as_ref()
+                .map_or(ptr::null(), |memory| memory.as_ptr()),
+            memory_len: memory_len as c_uint,
+        };
+        unsafe { on_step(self.user_data, &step) };
+    }
+
+    fn log(&mut self, _interp: &mut Interpreter, _context: &mut CTX, log: Log) {
+        let Some(on_log) = self.callbacks.on_log else {
+            return;
+        };
+        let topics: Vec<FFIHash> = log
+            .data
+            .topics()
+            .iter()
+            .map(|topic| FFIHash { bytes: topic.0 })
+            .collect();
+        let event = LogEventFFI {
+            depth: self.depth,
+            address: address_ffi(log.address),
+            topics: topics.as_ptr(),
+            topics_count: topics.len() as c_uint,
+            data: log.data.data.as_ptr(),
+            data_len: log.data.data.len() as c_uint,
+        };
+        unsafe { on_log(self.user_data, &event) };
+    }
+
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        let kind = match inputs.scheme {
+            CallScheme::Call | CallScheme::ExtCall => FrameKindFFI::Call,
+            CallScheme::StaticCall | CallScheme::ExtStaticCall => FrameKindFFI::StaticCall,
+            CallScheme::DelegateCall | CallScheme::ExtDelegateCall => FrameKindFFI::DelegateCall,
+            CallScheme::CallCode => FrameKindFFI::CallCode,
+        };
+        let input = inputs.input.bytes(context);
+        self.enter(FrameEnterFFI {
+            depth: 0,
+            kind,
+            caller: address_ffi(inputs.caller),
+            target: address_ffi(inputs.target_address),
+            code_address: address_ffi(inputs.bytecode_address),
+            value: u256_ffi(inputs.value.get()),
+            gas_limit: inputs.gas_limit,
+            input: input.as_ptr(),
+            input_len: input.len() as c_uint,
+        });
+        None
+    }
+
+    fn call_end(&mut self, _context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.exit(&outcome.result, None);
+    }
+
+    fn create(&mut self, context: &mut CTX, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        let kind = match inputs.scheme {
+            CreateScheme::Create2 { .. } => FrameKindFFI::Create2,
+            _ => FrameKindFFI::Create,
+        };
+        let nonce = context
+            .journal()
+            .load_account(inputs.caller)
+            .map(|account| account.info.nonce)
+            .unwrap_or_default();
+        let target = address_ffi(inputs.created_address(nonce));
+        self.enter(FrameEnterFFI {
+            depth: 0,
+            kind,
+            caller: address_ffi(inputs.caller),
+            target,
+            code_address: target,
+            value: u256_ffi(inputs.value),
+            gas_limit: inputs.gas_limit,
+            input: inputs.init_code.as_ptr(),
+            input_len: inputs.init_code.len() as c_uint,
+        });
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.exit(&outcome.result, outcome.address);
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+        let Some(on_selfdestruct) = self.callbacks.on_selfdestruct else {
+            return;
+        };
+        let selfdestruct = SelfDestructFFI {
+            depth: self.depth,
+            contract: address_ffi(contract),
+            beneficiary: address_ffi(target),
+            value: u256_ffi(value),
+        };
+        unsafe { on_selfdestruct(self.user_data, &selfdestruct) };
+    }
+}
+
+pub(crate) fn address_ffi(address: Address) -> FFIAddress {
+    FFIAddress {
+        bytes: address.into_array(),
+    }
+}
+
+pub(crate) fn u256_ffi(value: U256) -> FFIU256 {
+    FFIU256 {
+        bytes: value.to_be_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::{Address, U256};
+    use std::ffi::{c_void, CStr};
+
+    fn events<'a>(user_data: *mut c_void) -> &'a mut Vec<String> {
+        unsafe { &mut *(user_data as *mut Vec<String>) }
+    }
+
+    unsafe extern "C" fn on_enter(user_data: *mut c_void, frame: *const FrameEnterFFI) {
+        let frame = &*frame;
+        events(user_data).push(format!(
+            "enter {} {:?} {} -> {}",
+            frame.depth,
+            frame.kind,
+            Address::from(frame.caller.bytes),
+            Address::from(frame.target.bytes)
+        ));
+    }
+
+    unsafe extern "C" fn on_exit(user_data: *mut c_void, frame: *const FrameExitFFI) {
+        let frame = &*frame;
+        let error = (!frame.error.is_null()).then(|| CStr::from_ptr(frame.error).to_str().unwrap());
+        events(user_data).push(format!(
+            "exit {} {} {:?}",
+            frame.depth, frame.success, error
+        ));
+    }
+
+    unsafe extern "C" fn on_log(user_data: *mut c_void, log: *const LogEventFFI) {
+        let log = &*log;
+        let topics = std::slice::from_raw_parts(log.topics, log.topics_count as usize);
+        events(user_data).push(format!(
+            "log {} {} {}",
+            log.depth,
+            Address::from(log.address.bytes),
+            U256::from_be_bytes(topics[0].bytes)
+        ));
+    }
+
+    unsafe extern "C" fn on_selfdestruct(
+        user_data: *mut c_void,
+        selfdestruct: *const SelfDestructFFI,
+    ) {
+        let selfdestruct = &*selfdestruct;
+        events(user_data).push(format!(
+            "selfdestruct {} {} -> {}",
+            selfdestruct.depth,
+            Address::from(selfdestruct.contract.bytes),
+            Address::from(selfdestruct.beneficiary.bytes)
+        ));
+    }
+
+    unsafe extern "C" fn on_step(user_data: *mut c_void, step: *const StepFFI) {
+        let step = &*step;
+        let stack = if step.stack_len == 0 {
+            &[][..]
+        } else {
+            std::slice::from_raw_parts(step.stack, step.stack_len as usize)
+        };
+        let stack: Vec<_> = stack
+            .iter()
+            .map(|value| U256::from_be_bytes(value.bytes).to_string())
+            .collect();
+        events(user_data).push(format!(
+            "step {} {} {:#04x} [{}]",
+            step.depth,
+            step.pc,
+            step.opcode,
+            stack.join(", ")
+        ));
+    }
+
+    #[test]
+    fn invokes_host_callbacks() {
+        let (caller, outer) = (CALLER, CONTRACT);
+        let inner = Address::repeat_byte(0xdd);
+        // POP(CALL(GAS, inner, 0, 0, 0, 0, 0)); STOP
+        let mut code = vec![0x5f, 0x5f, 0x5f, 0x5f, 0x5f, 0x73];
+        code.extend_from_slice(inner.as_slice());
+        code.extend_from_slice(&[0x5a, 0xf1, 0x50, 0x00]);
+        // LOG1(0, 0, 1); SELFDESTRUCT(CALLER)
+        let inner_code = [0x60, 0x01, 0x5f, 0x5f, 0xa1, 0x33, 0xff];
+        let callbacks = InspectorCallbacksFFI {
+            on_enter: Some(on_enter),
+            on_exit: Some(on_exit),
+            on_log: Some(on_log),
+            on_selfdestruct: Some(on_selfdestruct),
+            on_step: Some(on_step),
+        };
+
+        let mut events: Vec<String> = Vec::new();
+        unsafe {
+            let instance = instance_with_contracts(&[(outer, &code), (inner, &inner_code)]);
+            assert_eq!(
+                revm_set_inspector_callbacks(
+                    instance,
+                    &callbacks,
+                    &mut events as *mut Vec<String> as *mut c_void
+                ),
+                0
+            );
+            let call = || {
+                let result = call(instance, outer);
+                assert_eq!((*result).success, 1);
+                revm_free_execution_result(result);
+            };
+            call();
+            let seen = events.len();
+
+            // Nothing is reported once the callbacks are cleared.
+            revm_clear_inspector_callbacks(instance);
+            call();
+            assert_eq!(events.len(), seen);
+            revm_free(instance);
+        }
+
+        let frames: Vec<_> = events
+            .iter()
+            .filter(|e| !e.starts_with("step"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                format!("enter 1 Call {caller} -> {outer}"),
+                format!("enter 2 Call {outer} -> {inner}"),
+                format!("log 2 {inner} 1"),
+                format!("selfdestruct 2 {inner} -> {outer}"),
+                "exit 2 true None".to_string(),
+                "exit 1 true None".to_string(),
+            ]
+        );
+        // Steps come before the opcode runs, with the stack bottom first.
+        assert_eq!(events[1], "step 1 0 0x5f []");
+        assert!(events.contains(&"step 2 4 0xa1 [1, 0, 0]".to_string()));
+        assert_eq!(
+            events.iter().filter(|e| e.starts_with("step")).count(),
+            10 + 6
+        );
+    }
+}
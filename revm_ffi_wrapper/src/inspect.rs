@@ -0,0 +1,334 @@
+//! Running a transaction under an inspector.
+//!
+//! The EVM held by an instance is built without an inspector, so inspected
+//! execution builds a throwaway EVM over the instance's database with the
+//! same cfg, block and tx env.  Inspectors are written against a generic
+//! `CTX: ContextTr` so they work with whatever database the caller passes.
+//!
+//! Inspectors attached to an instance ([`AttachedInspectors`]) apply to every
+//! execution through the regular entry points; without any attached the
+//! plain EVM runs.
+
+use std::convert::Infallible;
+
+use anyhow::{anyhow, Result};
+use revm::{
+    context::{BlockEnv, CfgEnv, TxEnv},
+    context_interface::{
+        result::{EVMError, ResultAndState},
+        ContextTr,
+    },
+    database::CacheDB,
+    database_interface::Database,
+    handler::{EvmTr, ExecuteEvm, MainnetEvm},
+    inspector::{InspectEvm, Inspector},
+    interpreter::{CallInputs, CallOutcome, CreateInputs, CreateOutcome, Interpreter},
+    primitives::{Address, Log, U256},
+    Context, Journal, MainBuilder,
+};
+
+use crate::eip3155::Eip3155Tracer;
+use crate::fork::ForkedDB;
+use crate::go_db::GoDatabase;
+use crate::host_inspector::HostInspector;
+use crate::log_stream::LogStream;
+use crate::profiler::GasProfiler;
+use crate::revert_point::RevertDiagnostics;
+use crate::types::RevmInstance;
+use crate::RevmInstanceStateDB;
+
+/// Context of the EVM built by [`inspect`] over database `DB`.
+pub type InspectContext<DB> = Context<BlockEnv, TxEnv, CfgEnv, DB, Journal<DB>, ()>;
+
+/// Execute `tx` on `db` under `inspector` without committing, returning the
+/// outcome together with the inspector.
+pub fn inspect<DB, I>(
+    db: DB,
+    cfg: &CfgEnv,
+    block: &BlockEnv,
+    tx: &TxEnv,
+    inspector: I,
+) -> Result<(ResultAndState, I)>
+where
+    DB: Database,
+    I: Inspector<InspectContext<DB>>,
+{
+    let mut evm = InspectContext::<DB>::new(db, cfg.spec)
+        .with_cfg(cfg.clone())
+        .with_block(block.clone())
+        .with_tx(tx.clone())
+        .build_mainnet_with_inspector(inspector);
+    let outcome = evm
+        .inspect_replay()
+        .map_err(|e| anyhow!("Execution failed: {e}"))?;
+    Ok((outcome, evm.inspector))
+}
+
+/// Inspectors attached to an instance for all of its executions.
+#[derive(Default)]
+pub struct AttachedInspectors {
+    /// EIP-3155 tracer, set with `revm_set_tracer`
+    pub tracer: Option<Eip3155Tracer>,
+    /// Gas profiler, set with `revm_start_profiling`
+    pub profiler: Option<GasProfiler>,
+    /// Host callbacks, set with `revm_set_inspector_callbacks`
+    pub host: Option<HostInspector>,
+    /// Streamed logs, set with `revm_set_log_callback`
+    pub logs: Option<LogStream>,
+    /// Revert-point diagnostics, enabled with `revm_enable_revert_diagnostics`
+    pub revert: Option<RevertDiagnostics>,
+}
+
+impl AttachedInspectors {
+    pub fn is_empty(&self) -> bool {
+        self.tracer.is_none()
+            && self.profiler.is_none()
+            && self.host.is_none()
+            && self.logs.is_none()
+            && self.revert.is_none()
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for AttachedInspectors {
+    fn initialize_interp(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.initialize_interp(interp, context);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.initialize_interp(interp, context);
+        }
+        if let Some(host) = &mut self.host {
+            host.initialize_interp(interp, context);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.initialize_interp(interp, context);
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.initialize_interp(interp, context);
+        }
+    }
+
+    fn step(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.step(interp, context);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.step(interp, context);
+        }
+        if let Some(host) = &mut self.host {
+            host.step(interp, context);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.step(interp, context);
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.step(interp, context);
+        }
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.step_end(interp, context);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.step_end(interp, context);
+        }
+        if let Some(host) = &mut self.host {
+            host.step_end(interp, context);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.step_end(interp, context);
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.step_end(interp, context);
+        }
+    }
+
+    fn log(&mut self, interp: &mut Interpreter, context: &mut CTX, log: Log) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.log(interp, context, log.clone());
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.log(interp, context, log.clone());
+        }
+        if let Some(host) = &mut self.host {
+            host.log(interp, context, log.clone());
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.log(interp, context, log.clone());
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.log(interp, context, log);
+        }
+    }
+
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.call(context, inputs);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(context, inputs);
+        }
+        if let Some(host) = &mut self.host {
+            host.call(context, inputs);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.call(context, inputs);
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.call(context, inputs);
+        }
+        None
+    }
+
+    fn call_end(&mut self, context: &mut CTX, inputs: &CallInputs, outcome: &mut CallOutcome) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.call_end(context, inputs, outcome);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call_end(context, inputs, outcome);
+        }
+        if let Some(host) = &mut self.host {
+            host.call_end(context, inputs, outcome);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.call_end(context, inputs, outcome);
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.call_end(context, inputs, outcome);
+        }
+    }
+
+    fn create(&mut self, context: &mut CTX, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.create(context, inputs);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.create(context, inputs);
+        }
+        if let Some(host) = &mut self.host {
+            host.create(context, inputs);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.create(context, inputs);
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.create(context, inputs);
+        }
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        context: &mut CTX,
+        inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.create_end(context, inputs, outcome);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.create_end(context, inputs, outcome);
+        }
+        if let Some(host) = &mut self.host {
+            host.create_end(context, inputs, outcome);
+        }
+        if let Some(logs) = &mut self.logs {
+            logs.create_end(context, inputs, outcome);
+        }
+        if let Some(revert) = &mut self.revert {
+            revert.create_end(context, inputs, outcome);
+        }
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+        if let Some(tracer) = &mut self.tracer {
+            Inspector::<CTX>::selfdestruct(tracer, contract, target, value);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            Inspector::<CTX>::selfdestruct(profiler, contract, target, value);
+        }
+        if let Some(host) = &mut self.host {
+            Inspector::<CTX>::selfdestruct(host, contract, target, value);
+        }
+        if let Some(logs) = &mut self.logs {
+            Inspector::<CTX>::selfdestruct(logs, contract, target, value);
+        }
+        if let Some(revert) = &mut self.revert {
+            Inspector::<CTX>::selfdestruct(revert, contract, target, value);
+        }
+    }
+}
+
+/// Execute the pending transaction of `evm` under the attached inspectors,
+/// which are put back afterwards.
+pub(crate) fn replay_attached<DB: Database>(
+    evm: &mut MainnetEvm<InspectContext<DB>>,
+    attached: &mut AttachedInspectors,
+) -> Result<ResultAndState, EVMError<DB::Error>> {
+    if let Some(revert) = &mut attached.revert {
+        // A transaction failing validation runs no frame to reset it.
+        revert.clear();
+    }
+    if attached.is_empty() {
+        return evm.replay();
+    }
+    let ctx = evm.ctx();
+    let mut inspected =
+        InspectContext::<&mut DB>::new(&mut ctx.journaled_state.database, ctx.cfg.spec)
+            .with_cfg(ctx.cfg.clone())
+            .with_block(ctx.block.clone())
+            .with_tx(ctx.tx.clone())
+            .build_mainnet_with_inspector(std::mem::take(attached));
+    let outcome = inspected.inspect_replay();
+    *attached = inspected.inspector;
+    outcome
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction without committing, under the
+    /// attached inspectors if there are any.
+    pub fn replay_pending(&mut self) -> Result<ResultAndState, EVMError<Infallible>> {
+        replay_attached(&mut self.evm, &mut self.inspectors)
+    }
+
+    /// Execute the pending transaction under `inspector`.  State changes are
+    /// returned, not committed; pass them to [`RevmInstance::commit_state`].
+    pub fn inspect_pending<I>(&mut self, inspector: I) -> Result<(ResultAndState, I)>
+    where
+        I: for<'a> Inspector<InspectContext<&'a mut CacheDB<ForkedDB>>>,
+    {
+        let ctx = &mut self.evm.ctx;
+        inspect(
+            &mut ctx.journaled_state.database,
+            &ctx.cfg,
+            &ctx.block,
+            &ctx.tx,
+            inspector,
+        )
+    }
+}
+
+impl RevmInstanceStateDB {
+    /// [`RevmInstance::inspect_pending`] against the Go StateDB, recorded if
+    /// recording is on.  Commit the returned state through the database.
+    pub fn inspect_pending<I>(&mut self, inspector: I) -> Result<(ResultAndState, I)>
+    where
+        I: for<'a> Inspector<InspectContext<&'a mut GoDatabase>>,
+    {
+        let recorder = crate::record_execution(&self.evm.ctx);
+        let ctx = &mut self.evm.ctx;
+        let outcome = inspect(
+            &mut ctx.journaled_state.database,
+            &ctx.cfg,
+            &ctx.block,
+            &ctx.tx,
+            inspector,
+        );
+        crate::record_result(
+            recorder,
+            &outcome.as_ref().map(|(outcome, _)| outcome.clone()),
+        );
+        outcome
+    }
+}
@@ -0,0 +1,1179 @@
+//! geth's JavaScript tracers, run on the embedded Boa engine.
+//!
+//! A tracer is the same object literal geth's `debug_traceTransaction`
+//! accepts as `tracer`: `result(ctx, db)` and `fault(log, db)` are required,
+//! `step(log, db)`, `enter(frame)`/`exit(frameResult)` and `setup(config)`
+//! optional.  The `log`, `db`, `ctx`, `frame` and `frameResult` objects and
+//! the `toHex`, `toWord`, `toAddress`, `toContract`, `toContract2`, `slice`,
+//! `isPrecompiled` and `bigInt` globals follow geth's tracer API.  Big
+//! numbers are native `BigInt`s, extended with the `big-integer` methods
+//! (`add`, `subtract`, `toJSNumber`, ...) geth's scripts use.
+//!
+//! `step` runs once the opcode has executed so that `log.getCost()` is
+//! known; the stack, memory and gas it sees are those from before the
+//! opcode, as in geth.  `db` reads the pre-opcode state too, so its reads
+//! match geth's: the storage slot an SSTORE writes and the balances a
+//! SELFDESTRUCT moves are read before the opcode runs and served in their
+//! place, and calls and creations only move value and bump nonces once
+//! `step` has returned.  No other opcode writes state while it executes.
+//! State read through `db` during execution is loaded without warming
+//! accounts or slots, so tracing does not change gas.
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::rc::Rc;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Result};
+use boa_engine::{
+    js_string,
+    object::{
+        builtins::{JsArray, JsUint8Array},
+        ObjectInitializer,
+    },
+    property::Attribute,
+    Context, JsBigInt, JsError, JsNativeError, JsObject, JsResult, JsValue, NativeFunction, Source,
+};
+use revm::{
+    bytecode::opcode::{OpCode, SELFDESTRUCT, SSTORE},
+    context_interface::{result::ResultAndState, Block, ContextTr, JournalTr, Transaction},
+    database_interface::{Database, DatabaseRef},
+    inspector::Inspector,
+    interpreter::{
+        interpreter_types::{InputsTr, Jumps, LoopControl, MemoryTr},
+        CallInput, CallInputs, CallOutcome, CreateInputs, CreateOutcome, CreateScheme, Interpreter,
+        InterpreterResult,
+    },
+    primitives::{Address, Bytes, HashSet, B256, U256},
+    state::{AccountInfo, EvmState},
+};
+
+use crate::call_tracer::call_kind;
+use crate::eip3155::geth_error;
+use crate::prestate::code_of;
+
+/// Globals geth provides besides the native helpers: `bigInt` and the
+/// `big-integer` methods on `BigInt`.
+const PRELUDE: &str = r#"
+var bigInt = function (value, base) {
+  if (typeof value === "bigint") return value;
+  if (value === undefined || value === null) return BigInt(0);
+  if (typeof value === "number") return BigInt(Math.trunc(value));
+  value = String(value);
+  if (base === 16 && value.indexOf("0x") !== 0) value = "0x" + value;
+  return BigInt(value);
+};
+(function (p) {
+  "use strict";
+  p.add = p.plus = function (v) { return this + bigInt(v); };
+  p.subtract = p.minus = function (v) { return this - bigInt(v); };
+  p.multiply = p.times = function (v) { return this * bigInt(v); };
+  p.divide = p.over = function (v) { return this / bigInt(v); };
+  p.mod = p.remainder = function (v) { return this % bigInt(v); };
+  p.pow = function (v) { return this ** bigInt(v); };
+  p.and = function (v) { return this & bigInt(v); };
+  p.or = function (v) { return this | bigInt(v); };
+  p.xor = function (v) { return this ^ bigInt(v); };
+  p.shiftLeft = function (v) { return this << bigInt(v); };
+  p.shiftRight = function (v) { return this >> bigInt(v); };
+  p.negate = function () { return -this; };
+  p.abs = function () { return this < 0 ? -this : this; };
+  p.compare = p.compareTo = function (v) {
+    v = bigInt(v);
+    return this < v ? -1 : this > v ? 1 : 0;
+  };
+  p.equals = p.eq = function (v) { return this === bigInt(v); };
+  p.notEquals = p.neq = function (v) { return this !== bigInt(v); };
+  p.lesser = p.lt = function (v) { return this < bigInt(v); };
+  p.greater = p.gt = function (v) { return this > bigInt(v); };
+  p.lesserOrEquals = p.leq = function (v) { return this <= bigInt(v); };
+  p.greaterOrEquals = p.geq = function (v) { return this >= bigInt(v); };
+  p.isZero = function () { return this === BigInt(0); };
+  p.isNegative = function () { return this < 0; };
+  p.isPositive = function () { return this > 0; };
+  p.isEven = function () { return this % BigInt(2) === BigInt(0); };
+  p.isOdd = function () { return !this.isEven(); };
+  p.toJSNumber = function () { return Number(this); };
+  p.toJSON = function () { return this.toString(); };
+})(BigInt.prototype);
+"#;
+
+/// The `log` object handed to `step` and `fault`; the tracer fills in the
+/// underscored fields before each call.
+const LOG: &str = r#"
+(function () {
+  var log = {
+    op: {
+      toNumber: function () { return log._op; },
+      toString: function () { return log._opName; },
+      isPush: function () { return log._op >= 0x5f && log._op <= 0x7f; }
+    },
+    stack: {
+      peek: function (i) {
+        var size = log._stack.length;
+        if (i < 0 || i >= size) {
+          throw new Error("tracer accessed out of bound stack: size " + size + ", index " + i);
+        }
+        return log._stack[size - i - 1];
+      },
+      length: function () { return log._stack.length; }
+    },
+    memory: {
+      slice: function (begin, end) {
+        if (end < begin || begin < 0 || end > log._memory.length) {
+          throw new Error("tracer accessed out of bound memory: available " +
+            log._memory.length + ", offset " + begin + ", size " + (end - begin));
+        }
+        return slice(log._memory, begin, end);
+      },
+      getUint: function (offset) {
+        return bigInt(toHex(log.memory.slice(offset, offset + 32)));
+      },
+      length: function () { return log._memory.length; }
+    },
+    contract: {
+      getCaller: function () { return log._caller; },
+      getAddress: function () { return log._address; },
+      getValue: function () { return log._value; },
+      getInput: function () { return log._input; }
+    },
+    getPC: function () { return log._pc; },
+    getGas: function () { return log._gas; },
+    getCost: function () { return log._cost; },
+    getDepth: function () { return log._depth; },
+    getRefund: function () { return log._refund; },
+    getError: function () { return log._error; }
+  };
+  return log;
+})()
+"#;
+
+/// Constructors of the `frame` and `frameResult` objects handed to `enter`
+/// and `exit`.
+const FRAMES: &str = r#"
+[
+  function (type, from, to, input, gas, value) {
+    return {
+      getType: function () { return type; },
+      getFrom: function () { return from; },
+      getTo: function () { return to; },
+      getInput: function () { return input; },
+      getGas: function () { return gas; },
+      getValue: function () { return value; }
+    };
+  },
+  function (gasUsed, output, error) {
+    return {
+      getGasUsed: function () { return gasUsed; },
+      getOutput: function () { return output; },
+      getError: function () { return error; }
+    };
+  }
+]
+"#;
+
+/// State reads behind the `db` object.
+trait StateReader {
+    fn balance(&mut self, address: Address) -> Result<U256, String>;
+    fn nonce(&mut self, address: Address) -> Result<u64, String>;
+    fn code(&mut self, address: Address) -> Result<Bytes, String>;
+    fn storage(&mut self, address: Address, slot: U256) -> Result<U256, String>;
+    fn exists(&mut self, address: Address) -> Result<bool, String>;
+}
+
+/// The state while the transaction runs, read through its journal.
+struct Live<'a, J>(&'a mut J);
+
+impl<J: JournalTr> Live<'_, J> {
+    fn read<T>(
+        &mut self,
+        f: impl FnOnce(&mut J) -> Result<T, <J::Database as Database>::Error>,
+    ) -> Result<T, String> {
+        // Loading warms what it touches; reverting to a checkpoint marks it
+        // cold again.
+        let checkpoint = self.0.checkpoint();
+        let value = f(self.0);
+        self.0.checkpoint_revert(checkpoint);
+        value.map_err(|e| e.to_string())
+    }
+}
+
+impl<J: JournalTr> StateReader for Live<'_, J> {
+    fn balance(&mut self, address: Address) -> Result<U256, String> {
+        self.read(|journal| Ok(journal.load_account(address)?.info.balance))
+    }
+
+    fn nonce(&mut self, address: Address) -> Result<u64, String> {
+        self.read(|journal| Ok(journal.load_account(address)?.info.nonce))
+    }
+
+    fn code(&mut self, address: Address) -> Result<Bytes, String> {
+        self.read(|journal| Ok(journal.code(address)?.data))
+    }
+
+    fn storage(&mut self, address: Address, slot: U256) -> Result<U256, String> {
+        self.read(|journal| {
+            journal.load_account(address)?;
+            Ok(journal.sload(address, slot)?.data)
+        })
+    }
+
+    fn exists(&mut self, address: Address) -> Result<bool, String> {
+        self.read(|journal| Ok(!journal.load_account(address)?.is_loaded_as_not_existing()))
+    }
+}
+
+/// State an opcode is about to overwrite, read before it runs.
+#[derive(Default)]
+struct Overwritten {
+    /// `(address, slot, value)` of the slot an SSTORE writes.
+    storage: Option<(Address, U256, U256)>,
+    /// Balances a SELFDESTRUCT moves.
+    balances: Vec<(Address, U256)>,
+}
+
+impl Overwritten {
+    /// Read what the opcode `interp` is at writes.
+    fn read(interp: &Interpreter, state: &mut dyn StateReader) -> Self {
+        let address = interp.input.target_address();
+        let mut overwritten = Self::default();
+        match interp.bytecode.opcode() {
+            SSTORE => {
+                if let Ok(slot) = interp.stack.peek(0) {
+                    let value = state.storage(address, slot);
+                    overwritten.storage = value.ok().map(|value| (address, slot, value));
+                }
+            }
+            SELFDESTRUCT => {
+                if let Ok(target) = interp.stack.peek(0) {
+                    let target = Address::from_word(target.into());
+                    for address in [address, target] {
+                        if let Ok(balance) = state.balance(address) {
+                            overwritten.balances.push((address, balance));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        overwritten
+    }
+}
+
+/// `state` as it was before an opcode overwrote parts of it.
+struct PreOpcode<'a, R> {
+    state: R,
+    overwritten: &'a Overwritten,
+}
+
+impl<R: StateReader> StateReader for PreOpcode<'_, R> {
+    fn balance(&mut self, address: Address) -> Result<U256, String> {
+        let balances = &self.overwritten.balances;
+        match balances
+            .iter()
+            .find(|(overwritten, _)| *overwritten == address)
+        {
+            Some((_, balance)) => Ok(*balance),
+            None => self.state.balance(address),
+        }
+    }
+
+    fn nonce(&mut self, address: Address) -> Result<u64, String> {
+        self.state.nonce(address)
+    }
+
+    fn code(&mut self, address: Address) -> Result<Bytes, String> {
+        self.state.code(address)
+    }
+
+    fn storage(&mut self, address: Address, slot: U256) -> Result<U256, String> {
+        match self.overwritten.storage {
+            Some((overwritten, key, value)) if overwritten == address && key == slot => Ok(value),
+            _ => self.state.storage(address, slot),
+        }
+    }
+
+    fn exists(&mut self, address: Address) -> Result<bool, String> {
+        self.state.exists(address)
+    }
+}
+
+/// The state after the transaction: its uncommitted changes over `db`.
+struct Post<'a, DB> {
+    db: &'a DB,
+    state: &'a EvmState,
+}
+
+impl<DB: DatabaseRef> StateReader for Post<'_, DB> {
+    fn balance(&mut self, address: Address) -> Result<U256, String> {
+        match self.state.get(&address) {
+            Some(account) => Ok(account.info.balance),
+            None => Ok(self.basic(address)?.balance),
+        }
+    }
+
+    fn nonce(&mut self, address: Address) -> Result<u64, String> {
+        match self.state.get(&address) {
+            Some(account) => Ok(account.info.nonce),
+            None => Ok(self.basic(address)?.nonce),
+        }
+    }
+
+    fn code(&mut self, address: Address) -> Result<Bytes, String> {
+        let info = match self.state.get(&address) {
+            Some(account) => account.info.clone(),
+            None => self.basic(address)?,
+        };
+        code_of(self.db, &info).map_err(|e| e.to_string())
+    }
+
+    fn storage(&mut self, address: Address, slot: U256) -> Result<U256, String> {
+        let account = self.state.get(&address);
+        match account.and_then(|account| account.storage.get(&slot)) {
+            Some(value) => Ok(value.present_value),
+            None => self
+                .db
+                .storage_ref(address, slot)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    fn exists(&mut self, address: Address) -> Result<bool, String> {
+        match self.state.get(&address) {
+            Some(account) => Ok(!account.is_loaded_as_not_existing()),
+            None => Ok(self
+                .db
+                .basic_ref(address)
+                .map_err(|e| e.to_string())?
+                .is_some()),
+        }
+    }
+}
+
+impl<DB: DatabaseRef> Post<'_, DB> {
+    fn basic(&self, address: Address) -> Result<AccountInfo, String> {
+        let info = self.db.basic_ref(address).map_err(|e| e.to_string())?;
+        Ok(info.unwrap_or_default())
+    }
+}
+
+/// The reader `db` uses, set only while a tracer function runs.
+type StateSlot = Rc<Cell<Option<*mut dyn StateReader>>>;
+
+/// The execution as `result`'s `ctx` describes it.
+struct TxContext {
+    kind: &'static str,
+    from: Address,
+    to: Option<Address>,
+    input: Bytes,
+    gas: u64,
+    gas_price: u128,
+    value: U256,
+    block: u64,
+    output: Bytes,
+    error: Option<&'static str>,
+}
+
+/// An opcode seen in `step`, reported once it has executed.
+struct PendingStep {
+    pc: usize,
+    op: u8,
+    gas: u64,
+    depth: usize,
+    refund: i64,
+    stack: Vec<U256>,
+    memory: Vec<u8>,
+    caller: Address,
+    address: Address,
+    value: U256,
+    input: Bytes,
+    overwritten: Overwritten,
+}
+
+/// Inspector running a geth JavaScript tracer.
+pub struct JsTracer {
+    context: Context,
+    tracer: JsObject,
+    step_fn: Option<JsObject>,
+    fault_fn: JsObject,
+    enter_fn: Option<JsObject>,
+    exit_fn: Option<JsObject>,
+    result_fn: JsObject,
+    log: JsObject,
+    db: JsObject,
+    new_frame: JsObject,
+    new_frame_result: JsObject,
+    state: StateSlot,
+    precompiles: Rc<RefCell<HashSet<Address>>>,
+    pending: Option<PendingStep>,
+    /// Refund counter of each frame on the stack, which revm merges into
+    /// the parent when a frame returns.
+    refunds: Vec<i64>,
+    depth: usize,
+    tx: Option<TxContext>,
+    started: Instant,
+    /// The first exception a tracer function threw; tracing stops there.
+    error: Option<String>,
+}
+
+impl JsTracer {
+    /// Compile tracer `code`, calling its `setup` with `config` (the
+    /// `tracerConfig`, `null` for none) when it has one.
+    pub fn new(code: &str, config: &serde_json::Value) -> Result<Self> {
+        let mut context = Context::default();
+        let state = StateSlot::default();
+        let precompiles = Rc::new(RefCell::new(HashSet::default()));
+        register_globals(&mut context, &precompiles).map_err(|e| js_error(e, &mut context))?;
+
+        let mut eval = |source: &str| -> Result<JsObject> {
+            let value = context
+                .eval(Source::from_bytes(source))
+                .map_err(|e| js_error(e, &mut context))?;
+            value
+                .as_object()
+                .cloned()
+                .ok_or_else(|| anyhow!("tracer must be an object"))
+        };
+        let tracer = eval(&format!("({code}\n)"))?;
+        let log = eval(LOG)?;
+        let frames = eval(FRAMES)?;
+        let db = db_object(&mut context, &state);
+
+        let mut function = |name: &str| -> Result<Option<JsObject>> {
+            let value = tracer
+                .get(js_string!(name), &mut context)
+                .map_err(|e| js_error(e, &mut context))?;
+            Ok(value.as_callable().cloned())
+        };
+        let step_fn = function("step")?;
+        let enter_fn = function("enter")?;
+        let exit_fn = function("exit")?;
+        let setup_fn = function("setup")?;
+        let Some(result_fn) = function("result")? else {
+            bail!("trace object must expose a function result()");
+        };
+        let Some(fault_fn) = function("fault")? else {
+            bail!("trace object must expose a function fault()");
+        };
+        if enter_fn.is_some() != exit_fn.is_some() {
+            bail!("trace object must expose either both or none of enter() and exit()");
+        }
+        let mut frame = |index: u32| -> Result<JsObject> {
+            let value = frames
+                .get(index, &mut context)
+                .map_err(|e| js_error(e, &mut context))?;
+            value
+                .as_callable()
+                .cloned()
+                .ok_or_else(|| anyhow!("frame constructor is not a function"))
+        };
+        let (new_frame, new_frame_result) = (frame(0)?, frame(1)?);
+
+        if let Some(setup) = setup_fn {
+            let config = if config.is_null() {
+                "{}".to_string()
+            } else {
+                config.to_string()
+            };
+            setup
+                .call(
+                    &tracer.clone().into(),
+                    &[js_string!(config).into()],
+                    &mut context,
+                )
+                .map_err(|e| js_error(e, &mut context))?;
+        }
+
+        Ok(Self {
+            context,
+            tracer,
+            step_fn,
+            fault_fn,
+            enter_fn,
+            exit_fn,
+            result_fn,
+            log,
+            db,
+            new_frame,
+            new_frame_result,
+            state,
+            precompiles,
+            pending: None,
+            refunds: Vec::new(),
+            depth: 0,
+            tx: None,
+            started: Instant::now(),
+            error: None,
+        })
+    }
+
+    /// Call `result(ctx, db)` for `outcome`, an uncommitted execution on
+    /// `db`, and return what it produced as JSON.
+    pub fn into_result<DB: DatabaseRef>(
+        mut self,
+        db: &DB,
+        outcome: &ResultAndState,
+    ) -> Result<serde_json::Value> {
+        if let Some(error) = self.error.take() {
+            bail!(error);
+        }
+        let tx = self
+            .tx
+            .take()
+            .ok_or_else(|| anyhow!("transaction produced no call frame"))?;
+        let ctx = self
+            .tx_context(tx, outcome.result.gas_used())
+            .map_err(|e| js_error(e, &mut self.context))?;
+
+        let mut post = Post {
+            db,
+            state: &outcome.state,
+        };
+        let result_fn = self.result_fn.clone();
+        let db_value = self.db.clone().into();
+        let result = self.with_state(&mut post, |tracer| {
+            result_fn.call(
+                &tracer.tracer.clone().into(),
+                &[ctx, db_value],
+                &mut tracer.context,
+            )
+        });
+        let result = result.and_then(|value| self.stringify(value));
+        result.map_err(|e| js_error(e, &mut self.context))
+    }
+
+    /// Run `f` with `db` reading from `state`.
+    fn with_state<R>(&mut self, state: &mut dyn StateReader, f: impl FnOnce(&mut Self) -> R) -> R {
+        let state: *mut (dyn StateReader + '_) = state;
+        // SAFETY: only the `db` functions dereference the pointer, and only
+        // while `f` runs, which `state` outlives; it is cleared afterwards.
+        let state = unsafe {
+            mem::transmute::<*mut (dyn StateReader + '_), *mut (dyn StateReader + 'static)>(state)
+        };
+        self.state.set(Some(state));
+        let result = f(self);
+        self.state.set(None);
+        result
+    }
+
+    /// Call tracer function `f`, remembering the first exception and
+    /// calling nothing after it.
+    fn invoke(&mut self, f: &JsObject, args: &[JsValue]) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = f.call(&self.tracer.clone().into(), args, &mut self.context) {
+            self.error = Some(js_error(e, &mut self.context).to_string());
+        }
+    }
+
+    /// Record the top frame for `ctx`.
+    fn begin<CTX: ContextTr>(
+        &mut self,
+        context: &mut CTX,
+        kind: &'static str,
+        to: Option<Address>,
+        input: Bytes,
+    ) {
+        *self.precompiles.borrow_mut() = context.journal().precompile_addresses().clone();
+        let basefee = context.block().basefee() as u128;
+        let tx = context.tx();
+        self.tx = Some(TxContext {
+            kind,
+            from: tx.caller(),
+            to,
+            input,
+            gas: tx.gas_limit(),
+            gas_price: tx.effective_gas_price(basefee),
+            value: tx.value(),
+            block: context.block().number(),
+            output: Bytes::new(),
+            error: None,
+        });
+        self.started = Instant::now();
+    }
+
+    /// Call `enter` for a frame below the top one.
+    fn enter(
+        &mut self,
+        kind: &str,
+        from: Address,
+        to: Address,
+        input: &[u8],
+        gas: u64,
+        value: Option<U256>,
+    ) {
+        let Some(enter) = self.enter_fn.clone() else {
+            return;
+        };
+        let frame = (|| {
+            let args = [
+                js_string!(kind).into(),
+                bytes_value(from.as_slice(), &mut self.context)?,
+                bytes_value(to.as_slice(), &mut self.context)?,
+                bytes_value(input, &mut self.context)?,
+                JsValue::from(gas as f64),
+                value.map_or(JsValue::undefined(), big_value),
+            ];
+            self.new_frame
+                .call(&JsValue::undefined(), &args, &mut self.context)
+        })();
+        match frame {
+            Ok(frame) => self.invoke(&enter, &[frame]),
+            Err(e) => self.error = Some(js_error(e, &mut self.context).to_string()),
+        }
+    }
+
+    /// Finish the current frame: `exit` below the top, the transaction's
+    /// output and error at the top.
+    fn exit(&mut self, result: &InterpreterResult, address: Option<Address>) {
+        self.depth -= 1;
+        let error = geth_error(result.result);
+        if self.depth == 0 {
+            if let Some(tx) = &mut self.tx {
+                tx.output = result.output.clone();
+                tx.error = error;
+                tx.to = tx.to.or(address);
+            }
+            return;
+        }
+        self.exit_frame(result.gas.spent(), &result.output, error);
+    }
+
+    fn exit_frame(&mut self, gas_used: u64, output: &[u8], error: Option<&str>) {
+        let Some(exit) = self.exit_fn.clone() else {
+            return;
+        };
+        let result = (|| {
+            let args = [
+                JsValue::from(gas_used as f64),
+                bytes_value(output, &mut self.context)?,
+                error.map_or(JsValue::undefined(), |error| js_string!(error).into()),
+            ];
+            self.new_frame_result
+                .call(&JsValue::undefined(), &args, &mut self.context)
+        })();
+        match result {
+            Ok(result) => self.invoke(&exit, &[result]),
+            Err(e) => self.error = Some(js_error(e, &mut self.context).to_string()),
+        }
+    }
+
+    /// Fill the `log` object with `step`.
+    fn fill_log(&mut self, step: PendingStep, cost: u64, error: Option<&str>) -> JsResult<()> {
+        let op_name = match OpCode::new(step.op) {
+            Some(op) => op.as_str().to_string(),
+            None => format!("opcode {:#x} not defined", step.op),
+        };
+        let stack = JsArray::from_iter(step.stack.into_iter().map(big_value), &mut self.context);
+        let fields = [
+            ("_op", JsValue::from(step.op)),
+            ("_opName", js_string!(op_name).into()),
+            ("_stack", stack.into()),
+            ("_memory", bytes_value(&step.memory, &mut self.context)?),
+            (
+                "_caller",
+                bytes_value(step.caller.as_slice(), &mut self.context)?,
+            ),
+            (
+                "_address",
+                bytes_value(step.address.as_slice(), &mut self.context)?,
+            ),
+            ("_value", big_value(step.value)),
+            ("_input", bytes_value(&step.input, &mut self.context)?),
+            ("_pc", JsValue::from(step.pc as f64)),
+            ("_gas", JsValue::from(step.gas as f64)),
+            ("_cost", JsValue::from(cost as f64)),
+            ("_depth", JsValue::from(step.depth as f64)),
+            ("_refund", JsValue::from(step.refund.max(0) as f64)),
+            (
+                "_error",
+                error.map_or(JsValue::undefined(), |error| js_string!(error).into()),
+            ),
+        ];
+        for (name, value) in fields {
+            self.log
+                .set(js_string!(name), value, true, &mut self.context)?;
+        }
+        Ok(())
+    }
+
+    /// The `ctx` object for `result`.
+    fn tx_context(&mut self, tx: TxContext, gas_used: u64) -> JsResult<JsValue> {
+        let context = &mut self.context;
+        let from = bytes_value(tx.from.as_slice(), context)?;
+        let to = match tx.to {
+            Some(to) => bytes_value(to.as_slice(), context)?,
+            None => JsValue::undefined(),
+        };
+        let input = bytes_value(&tx.input, context)?;
+        let output = bytes_value(&tx.output, context)?;
+        let time = format!("{:?}", self.started.elapsed());
+        let mut ctx = ObjectInitializer::new(context);
+        ctx.property(js_string!("type"), js_string!(tx.kind), Attribute::all())
+            .property(js_string!("from"), from, Attribute::all())
+            .property(js_string!("to"), to, Attribute::all())
+            .property(js_string!("input"), input, Attribute::all())
+            .property(js_string!("gas"), tx.gas as f64, Attribute::all())
+            .property(js_string!("gasUsed"), gas_used as f64, Attribute::all())
+            .property(
+                js_string!("gasPrice"),
+                big_value(U256::from(tx.gas_price)),
+                Attribute::all(),
+            )
+            .property(js_string!("value"), big_value(tx.value), Attribute::all())
+            .property(js_string!("block"), tx.block as f64, Attribute::all())
+            .property(js_string!("output"), output, Attribute::all())
+            .property(js_string!("time"), js_string!(time), Attribute::all());
+        if let Some(error) = tx.error {
+            ctx.property(js_string!("error"), js_string!(error), Attribute::all());
+        }
+        Ok(ctx.build().into())
+    }
+
+    /// `value` as JSON, converted the way `JSON.stringify` does.
+    fn stringify(&mut self, value: JsValue) -> JsResult<serde_json::Value> {
+        let json = self
+            .context
+            .global_object()
+            .get(js_string!("JSON"), &mut self.context)?;
+        let stringify = json
+            .as_object()
+            .map(|json| json.get(js_string!("stringify"), &mut self.context))
+            .transpose()?
+            .and_then(|stringify| stringify.as_callable().cloned())
+            .ok_or_else(|| JsNativeError::typ().with_message("JSON.stringify is missing"))?;
+        let text = stringify.call(&json, &[value], &mut self.context)?;
+        let Some(text) = text.as_string() else {
+            return Ok(serde_json::Value::Null);
+        };
+        serde_json::from_str(&text.to_std_string_escaped())
+            .map_err(|e| JsNativeError::error().with_message(e.to_string()).into())
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for JsTracer {
+    fn step(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        if self.error.is_some() {
+            return;
+        }
+        let depth = context.journal().depth();
+        self.refunds.resize(depth, 0);
+        if let Some(refund) = self.refunds.last_mut() {
+            *refund = interp.control.gas().refunded();
+        }
+        let input = match interp.input.input() {
+            CallInput::Bytes(bytes) => bytes.clone(),
+            // The memory is not sliceable while it is empty.
+            CallInput::SharedBuffer(range) if range.is_empty() => Bytes::new(),
+            CallInput::SharedBuffer(range) => {
+                Bytes::copy_from_slice(&interp.memory.global_slice(range.clone()))
+            }
+        };
+        let mem_size = interp.memory.size();
+        self.pending = Some(PendingStep {
+            pc: interp.bytecode.pc(),
+            op: interp.bytecode.opcode(),
+            gas: interp.control.gas().remaining(),
+            depth,
+            refund: self.refunds.iter().sum(),
+            stack: interp.stack.data().clone(),
+            memory: if mem_size > 0 {
+                interp.memory.slice(0..mem_size).to_vec()
+            } else {
+                Vec::new()
+            },
+            caller: interp.input.caller_address(),
+            address: interp.input.target_address(),
+            value: interp.input.call_value(),
+            input,
+            overwritten: Overwritten::read(interp, &mut Live(context.journal())),
+        });
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        let Some(mut step) = self.pending.take() else {
+            return;
+        };
+        let overwritten = mem::take(&mut step.overwritten);
+        let cost = step.gas.saturating_sub(interp.control.gas().remaining());
+        let result = interp.control.instruction_result();
+        let error = result.is_error().then(|| geth_error(result)).flatten();
+        if let Err(e) = self.fill_log(step, cost, error) {
+            self.error = Some(js_error(e, &mut self.context).to_string());
+            return;
+        }
+        let args = [self.log.clone().into(), self.db.clone().into()];
+        let (step_fn, fault_fn) = (self.step_fn.clone(), self.fault_fn.clone());
+        let mut state = PreOpcode {
+            state: Live(context.journal()),
+            overwritten: &overwritten,
+        };
+        self.with_state(&mut state, |tracer| {
+            if let Some(step) = &step_fn {
+                tracer.invoke(step, &args);
+            }
+            if error.is_some() {
+                tracer.invoke(&fault_fn, &args);
+            }
+        });
+    }
+
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        self.depth += 1;
+        let input = inputs.input.bytes(context);
+        if self.depth == 1 {
+            self.begin(context, "CALL", Some(inputs.target_address), input);
+            return None;
+        }
+        let (kind, from) = call_kind(inputs);
+        let value = (kind != "STATICCALL").then(|| inputs.value.get());
+        let (to, gas) = (inputs.bytecode_address, inputs.gas_limit);
+        self.enter(kind, from, to, &input, gas, value);
+        None
+    }
+
+    fn call_end(&mut self, _context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.exit(&outcome.result, None);
+    }
+
+    fn create(&mut self, context: &mut CTX, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        self.depth += 1;
+        if self.depth == 1 {
+            self.begin(context, "CREATE", None, inputs.init_code.clone());
+            return None;
+        }
+        let kind = match inputs.scheme {
+            CreateScheme::Create2 { .. } => "CREATE2",
+            _ => "CREATE",
+        };
+        let nonce = context
+            .journal()
+            .load_account(inputs.caller)
+            .map(|account| account.info.nonce)
+            .unwrap_or_default();
+        let to = inputs.created_address(nonce);
+        let (from, gas, value) = (inputs.caller, inputs.gas_limit, inputs.value);
+        self.enter(kind, from, to, &inputs.init_code.clone(), gas, Some(value));
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.exit(&outcome.result, outcome.address);
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+        self.enter("SELFDESTRUCT", contract, target, &[], 0, Some(value));
+        self.exit_frame(0, &[], None);
+    }
+}
+
+/// Register geth's helper functions and the prelude.
+fn register_globals(
+    context: &mut Context,
+    precompiles: &Rc<RefCell<HashSet<Address>>>,
+) -> JsResult<()> {
+    let helpers: [(&str, usize, NativeFunction); 6] = [
+        ("toHex", 1, NativeFunction::from_fn_ptr(to_hex)),
+        ("toWord", 1, NativeFunction::from_fn_ptr(to_word)),
+        ("toAddress", 1, NativeFunction::from_fn_ptr(to_address)),
+        ("toContract", 2, NativeFunction::from_fn_ptr(to_contract)),
+        ("toContract2", 3, NativeFunction::from_fn_ptr(to_contract2)),
+        ("slice", 3, NativeFunction::from_fn_ptr(slice)),
+    ];
+    for (name, length, function) in helpers {
+        context.register_global_builtin_callable(js_string!(name), length, function)?;
+    }
+    let precompiles = precompiles.clone();
+    // SAFETY: the closure captures no garbage-collected values.
+    let is_precompiled = unsafe {
+        NativeFunction::from_closure(move |_, args, context| {
+            let address = address_arg(args, 0, context)?;
+            Ok(precompiles.borrow().contains(&address).into())
+        })
+    };
+    context.register_global_builtin_callable(js_string!("isPrecompiled"), 1, is_precompiled)?;
+    context.eval(Source::from_bytes(PRELUDE))?;
+    Ok(())
+}
+
+/// The `db` object, reading through `state`.
+fn db_object(context: &mut Context, state: &StateSlot) -> JsObject {
+    type Read = fn(&mut dyn StateReader, &[JsValue], &mut Context) -> JsResult<JsValue>;
+    let functions: [(&str, usize, Read); 5] = [
+        ("getBalance", 1, |state, args, context| {
+            let address = address_arg(args, 0, context)?;
+            Ok(big_value(state.balance(address).map_err(db_error)?))
+        }),
+        ("getNonce", 1, |state, args, context| {
+            let address = address_arg(args, 0, context)?;
+            Ok(JsValue::from(state.nonce(address).map_err(db_error)? as f64))
+        }),
+        ("getCode", 1, |state, args, context| {
+            let address = address_arg(args, 0, context)?;
+            bytes_value(&state.code(address).map_err(db_error)?, context)
+        }),
+        ("getState", 2, |state, args, context| {
+            let address = address_arg(args, 0, context)?;
+            let slot = B256::left_padding_from(&tail(bytes_arg(args, 1, context)?, 32));
+            let value = state.storage(address, slot.into()).map_err(db_error)?;
+            bytes_value(&value.to_be_bytes::<32>(), context)
+        }),
+        ("exists", 1, |state, args, context| {
+            let address = address_arg(args, 0, context)?;
+            Ok(state.exists(address).map_err(db_error)?.into())
+        }),
+    ];
+    let mut db = ObjectInitializer::new(context);
+    for (name, length, read) in functions {
+        let state = state.clone();
+        let closure = move |_: &JsValue, args: &[JsValue], context: &mut Context| {
+            let Some(reader) = state.get() else {
+                return Err(JsNativeError::error()
+                    .with_message("db is only available inside tracer calls")
+                    .into());
+            };
+            // SAFETY: set by `JsTracer::with_state` for the duration of the
+            // tracer call this runs in.
+            read(unsafe { &mut *reader }, args, context)
+        };
+        // SAFETY: the closure captures no garbage-collected values.
+        let function = unsafe { NativeFunction::from_closure(closure) };
+        db.function(function, js_string!(name), length);
+    }
+    db.build()
+}
+
+fn to_hex(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let bytes = bytes_arg(args, 0, context)?;
+    Ok(js_string!(format!("0x{}", hex::encode(bytes))).into())
+}
+
+fn to_word(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let word = B256::left_padding_from(&tail(bytes_arg(args, 0, context)?, 32));
+    bytes_value(word.as_slice(), context)
+}
+
+fn to_address(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let address = address_arg(args, 0, context)?;
+    bytes_value(address.as_slice(), context)
+}
+
+fn to_contract(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let from = address_arg(args, 0, context)?;
+    let nonce = args.get(1).cloned().unwrap_or_default().to_index(context)?;
+    bytes_value(from.create(nonce).as_slice(), context)
+}
+
+fn to_contract2(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let from = address_arg(args, 0, context)?;
+    let salt = B256::left_padding_from(&tail(bytes_arg(args, 1, context)?, 32));
+    let init_code = bytes_arg(args, 2, context)?;
+    bytes_value(from.create2_from_code(salt, init_code).as_slice(), context)
+}
+
+fn slice(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let bytes = bytes_arg(args, 0, context)?;
+    let start = args.get(1).cloned().unwrap_or_default().to_index(context)? as usize;
+    let end = args.get(2).cloned().unwrap_or_default().to_index(context)? as usize;
+    if start > end || end > bytes.len() {
+        return Err(JsNativeError::range()
+            .with_message(format!(
+                "tracer accessed out of bound memory: available {}, offset {start}, size {}",
+                bytes.len(),
+                end.saturating_sub(start)
+            ))
+            .into());
+    }
+    bytes_value(&bytes[start..end], context)
+}
+
+/// The last `n` bytes of `bytes`.
+fn tail(bytes: Vec<u8>, n: usize) -> Vec<u8> {
+    bytes[bytes.len().saturating_sub(n)..].to_vec()
+}
+
+fn address_arg(args: &[JsValue], index: usize, context: &mut Context) -> JsResult<Address> {
+    Ok(Address::left_padding_from(&tail(
+        bytes_arg(args, index, context)?,
+        20,
+    )))
+}
+
+/// Argument `index` as bytes: a hex string, a `BigInt` or an array of bytes.
+fn bytes_arg(args: &[JsValue], index: usize, context: &mut Context) -> JsResult<Vec<u8>> {
+    let invalid = |value: &JsValue| {
+        JsError::from(
+            JsNativeError::typ().with_message(format!("invalid buffer {}", value.display())),
+        )
+    };
+    let value = args.get(index).cloned().unwrap_or_default();
+    match &value {
+        JsValue::String(text) => {
+            let text = text.to_std_string_escaped();
+            let digits = text.strip_prefix("0x").unwrap_or(&text);
+            let digits = if digits.len() % 2 == 1 {
+                format!("0{digits}")
+            } else {
+                digits.to_string()
+            };
+            hex::decode(digits).map_err(|_| invalid(&value))
+        }
+        JsValue::BigInt(number) => {
+            let number = number.to_string_radix(16);
+            let number = U256::from_str_radix(&number, 16).map_err(|_| invalid(&value))?;
+            Ok(number.to_be_bytes_trimmed_vec())
+        }
+        JsValue::Object(object) => {
+            let length = object
+                .get(js_string!("length"), context)?
+                .to_length(context)?;
+            (0..length)
+                .map(|i| Ok(object.get(i, context)?.to_u32(context)? as u8))
+                .collect()
+        }
+        _ => Err(invalid(&value)),
+    }
+}
+
+fn bytes_value(bytes: &[u8], context: &mut Context) -> JsResult<JsValue> {
+    Ok(JsUint8Array::from_iter(bytes.iter().copied(), context)?.into())
+}
+
+fn big_value(value: U256) -> JsValue {
+    JsBigInt::from_string(&value.to_string()).map_or(JsValue::undefined(), JsValue::BigInt)
+}
+
+fn db_error(error: String) -> JsError {
+    JsNativeError::error().with_message(error).into()
+}
+
+/// A JavaScript exception as an error, with its message when it is an
+/// `Error`.
+fn js_error(error: JsError, context: &mut Context) -> anyhow::Error {
+    match error.try_native(context) {
+        Ok(native) => anyhow!("{native}"),
+        Err(_) => anyhow!("{error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::{Address, U256};
+    use std::ffi::CString;
+
+    const TRACER: &str = r#"{
+        ops: {},
+        calls: [],
+        setup: function (config) { this.config = JSON.parse(config); },
+        step: function (log, db) {
+            var op = log.op.toString();
+            this.ops[op] = (this.ops[op] || 0) + 1;
+            if (log.getPC() === 0 && log.getDepth() === 1) {
+                // Reading state must not warm the slot the inner call writes.
+                this.before = toHex(db.getState(this.config.inner, toWord(bigInt(0))));
+            }
+            // ... and the state from before it.
+            if (op === "SSTORE") {
+                this.stored = toHex(db.getState(log.contract.getAddress(), toWord(log.stack.peek(0))));
+            }
+            // Steps see the stack from before the opcode.
+            if (op === "POP" && this.sload === undefined) {
+                this.sload = log.stack.peek(0).add(1).toJSNumber();
+            }
+        },
+        fault: function (log, db) { this.fault = log.getError(); },
+        enter: function (frame) {
+            this.calls.push(frame.getType() + " " + toHex(frame.getTo()));
+        },
+        exit: function (result) { this.calls.push("gasUsed " + result.getGasUsed()); },
+        result: function (ctx, db) {
+            return {
+                type: ctx.type,
+                to: toHex(ctx.to),
+                gasUsed: ctx.gasUsed,
+                value: ctx.value,
+                ops: this.ops,
+                calls: this.calls,
+                before: this.before,
+                stored: this.stored,
+                after: toHex(db.getState(this.config.inner, "0x00")),
+                sload: this.sload,
+                balance: db.getBalance(toHex(ctx.from)),
+                fault: this.fault
+            };
+        }
+    }"#;
+
+    unsafe fn trace(instance: *mut RevmInstance, tracer: &str, config: &str) -> *mut c_char {
+        let tracer = CString::new(tracer).unwrap();
+        let config = CString::new(config).unwrap();
+        revm_trace(instance, tracer.as_ptr(), config.as_ptr(), false)
+    }
+
+    #[test]
+    fn runs_geth_javascript_tracers() {
+        let outer = CONTRACT;
+        let inner = Address::repeat_byte(0xdd);
+        // POP(SLOAD(0)); POP(CALL(GAS, inner, 0, 0, 0, 0, 0)); STOP
+        let mut code = vec![0x5f, 0x54, 0x50, 0x5f, 0x5f, 0x5f, 0x5f, 0x5f, 0x73];
+        code.extend_from_slice(inner.as_slice());
+        code.extend_from_slice(&[0x5a, 0xf1, 0x50, 0x00]);
+        // SSTORE(0, 1); STOP
+        let inner_code = [0x60, 0x01, 0x5f, 0x55, 0x00];
+        let config = format!(r#"{{"inner":"{inner:#x}"}}"#);
+
+        unsafe {
+            let instance = instance_with_contracts(&[(outer, &code), (inner, &inner_code)]);
+            let db = &mut (*instance).evm.ctx.journaled_state.database;
+            db.insert_account_storage(outer, U256::ZERO, U256::from(41))
+                .unwrap();
+            set_call(instance, outer, &[], 0);
+
+            let result = take_json(trace(instance, TRACER, &config));
+
+            let word = |n: u8| format!("0x{}", hex::encode(U256::from(n).to_be_bytes::<32>()));
+            assert_eq!(result["type"], "CALL");
+            assert_eq!(result["to"], format!("{outer:#x}"));
+            assert_eq!(result["value"], "0");
+            assert_eq!(result["balance"], "1000000000000000000");
+            assert_eq!(result["ops"]["PUSH0"], 7);
+            assert_eq!(result["ops"]["SSTORE"], 1);
+            assert_eq!(result["ops"]["STOP"], 2);
+            assert_eq!(result["sload"], 42);
+            assert_eq!(result["before"], word(0));
+            assert_eq!(result["stored"], word(0));
+            assert_eq!(result["after"], word(1));
+            // A cold SSTORE from zero, so the early read left the slot cold.
+            assert_eq!(
+                result["calls"],
+                serde_json::json!([format!("CALL {inner:#x}"), "gasUsed 22105"])
+            );
+            assert!(result.get("fault").is_none());
+
+            // Tracer errors and exceptions are reported.
+            assert!(trace(instance, "{fault: function () {}}", "{}").is_null());
+            let error = (*instance).last_error.as_deref().unwrap();
+            assert_eq!(error, "trace object must expose a function result()");
+            let throwing = "{step: function (log) { log.stack.peek(5); }, fault: function () {}, \
+                            result: function () { return 1; }}";
+            assert!(trace(instance, throwing, "{}").is_null());
+            let error = (*instance).last_error.as_deref().unwrap();
+            assert!(
+                error.contains("tracer accessed out of bound stack"),
+                "{error}"
+            );
+
+            // Nothing was committed.
+            assert_eq!(revm_get_nonce(instance, c_address(CALLER).as_ptr()), 0);
+            revm_free(instance);
+        }
+    }
+}
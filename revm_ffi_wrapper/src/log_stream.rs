@@ -0,0 +1,226 @@
+//! Streaming logs to the host while a transaction executes.
+//!
+//! Each log is delivered to the registered callback as soon as it is
+//! emitted, with its index within the transaction and the depth of the
+//! emitting frame.  Logs only survive if no enclosing frame fails, which is
+//! not known at that point: when a frame reverts or halts, the logs emitted
+//! inside it are delivered a second time, with the same index and
+//! `reverted` set.  A log is marked reverted at most once.
+
+use std::ffi::c_void;
+use std::os::raw::c_uint;
+
+use revm::{
+    context_interface::ContextTr,
+    inspector::Inspector,
+    interpreter::{CallInputs, CallOutcome, CreateInputs, CreateOutcome, Interpreter},
+    primitives::Log,
+};
+
+use crate::host_inspector::address_ffi;
+use crate::statedb_types::{FFIAddress, FFIHash};
+
+/// A log emitted during execution.
+#[repr(C)]
+pub struct StreamedLogFFI {
+    /// Position of the log among those emitted by the transaction, reverted
+    /// ones included
+    pub index: c_uint,
+    /// Depth of the emitting frame, 1 for the transaction's top frame
+    pub depth: c_uint,
+    /// False when the log is emitted; true when it is delivered again
+    /// because an enclosing frame failed
+    pub reverted: bool,
+    pub address: FFIAddress,
+    pub topics: *const FFIHash,
+    pub topics_count: c_uint,
+    pub data: *const u8,
+    pub data_len: c_uint,
+}
+
+pub type StreamedLogCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, log: *const StreamedLogFFI);
+
+struct EmittedLog {
+    log: Log,
+    depth: c_uint,
+    reverted: bool,
+}
+
+/// Inspector delivering logs to a host callback as they are emitted.
+pub struct LogStream {
+    callback: StreamedLogCallback,
+    user_data: *mut c_void,
+    /// Logs of the current transaction
+    logs: Vec<EmittedLog>,
+    /// Index of the first log of each open frame
+    frames: Vec<usize>,
+}
+
+impl LogStream {
+    pub fn new(callback: StreamedLogCallback, user_data: *mut c_void) -> Self {
+        Self {
+            callback,
+            user_data,
+            logs: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    fn deliver(&self, index: usize) {
+        let EmittedLog {
+            log,
+            depth,
+            reverted,
+        } = &self.logs[index];
+        let topics: Vec<FFIHash> = log
+            .data
+            .topics()
+            .iter()
+            .map(|topic| FFIHash { bytes: topic.0 })
+            .collect();
+        let streamed = StreamedLogFFI {
+            index: index as c_uint,
+            depth: *depth,
+            reverted: *reverted,
+            address: address_ffi(log.address),
+            topics: topics.as_ptr(),
+            topics_count: topics.len() as c_uint,
+            data: log.data.data.as_ptr(),
+            data_len: log.data.data.len() as c_uint,
+        };
+        unsafe { (self.callback)(self.user_data, &streamed) };
+    }
+
+    fn exit(&mut self, success: bool) {
+        let start = self.frames.pop().unwrap_or_default();
+        if !success {
+            for index in start..self.logs.len() {
+                if !self.logs[index].reverted {
+                    self.logs[index].reverted = true;
+                    self.deliver(index);
+                }
+            }
+        }
+        if self.frames.is_empty() {
+            self.logs.clear();
+        }
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for LogStream {
+    fn log(&mut self, _interp: &mut Interpreter, _context: &mut CTX, log: Log) {
+        self.logs.push(EmittedLog {
+            log,
+            depth: self.frames.len() as c_uint,
+            reverted: false,
+        });
+        self.deliver(self.logs.len() - 1);
+    }
+
+    fn call(&mut self, _context: &mut CTX, _inputs: &mut CallInputs) -> Option<CallOutcome> {
+        self.frames.push(self.logs.len());
+        None
+    }
+
+    fn call_end(&mut self, _context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.exit(outcome.result.result.is_ok());
+    }
+
+    fn create(&mut self, _context: &mut CTX, _inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        self.frames.push(self.logs.len());
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.exit(outcome.result.result.is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::{Address, U256};
+    use std::ffi::c_void;
+
+    unsafe extern "C" fn collect(user_data: *mut c_void, log: *const StreamedLogFFI) {
+        let log = &*log;
+        let topics = std::slice::from_raw_parts(log.topics, log.topics_count as usize);
+        let logs = &mut *(user_data as *mut Vec<(u32, u32, bool, Address, U256)>);
+        logs.push((
+            log.index,
+            log.depth,
+            log.reverted,
+            Address::from(log.address.bytes),
+            U256::from_be_bytes(topics[0].bytes),
+        ));
+    }
+
+    #[test]
+    fn streams_logs_and_reverts() {
+        let outer = CONTRACT;
+        let inner = Address::repeat_byte(0xdd);
+        let reverting = Address::repeat_byte(0xee);
+        // LOG1(0, 0, 1); POP(CALL(GAS, inner, 0, 0, 0, 0, 0)); LOG1(0, 0, 3)
+        let mut code = vec![
+            0x60, 0x01, 0x5f, 0x5f, 0xa1, 0x5f, 0x5f, 0x5f, 0x5f, 0x5f, 0x73,
+        ];
+        code.extend_from_slice(inner.as_slice());
+        code.extend_from_slice(&[0x5a, 0xf1, 0x50, 0x60, 0x03, 0x5f, 0x5f, 0xa1, 0x00]);
+        // LOG1(0, 0, 2); REVERT(0, 0), and the same with topic 4
+        let inner_code = [0x60, 0x02, 0x5f, 0x5f, 0xa1, 0x5f, 0x5f, 0xfd];
+        let reverting_code = [0x60, 0x04, 0x5f, 0x5f, 0xa1, 0x5f, 0x5f, 0xfd];
+
+        let mut logs: Vec<(u32, u32, bool, Address, U256)> = Vec::new();
+        unsafe {
+            let instance = instance_with_contracts(&[
+                (outer, &code),
+                (inner, &inner_code),
+                (reverting, &reverting_code),
+            ]);
+            assert_eq!(
+                revm_set_log_callback(instance, None, std::ptr::null_mut()),
+                -1
+            );
+            assert_eq!(
+                revm_set_log_callback(
+                    instance,
+                    Some(collect),
+                    &mut logs as *mut Vec<(u32, u32, bool, Address, U256)> as *mut c_void
+                ),
+                0
+            );
+            let call = |to: Address, success: i32| {
+                let result = call(instance, to);
+                assert_eq!((*result).success, success);
+                revm_free_execution_result(result);
+            };
+            call(outer, 1);
+            call(reverting, 0);
+
+            revm_clear_log_callback(instance);
+            call(outer, 1);
+            revm_free(instance);
+        }
+
+        let u = U256::from;
+        assert_eq!(
+            logs,
+            [
+                (0, 1, false, outer, u(1)),
+                (1, 2, false, inner, u(2)),
+                (1, 2, true, inner, u(2)),
+                (2, 1, false, outer, u(3)),
+                // Indices restart with each transaction.
+                (0, 1, false, reverting, u(4)),
+                (0, 1, true, reverting, u(4)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! In-memory implementation of the `re_state_*` host callbacks.
+//!
+//! Normally the Go node exports these functions and `GoDatabase` calls them
+//! with a StateDB handle.  With the `test-utils` feature (and in this crate's
+//! own tests) they are defined here instead and dispatch the handle to a
+//! [`MockHost`], so `RevmInstanceStateDB` can be driven end to end without
+//! Go.  Never enable the feature in a build that links against the Go host:
+//! both would define the same symbols.
+//!
+//! A host is backed by plain maps, counts every callback, can be told to fail
+//! upcoming calls and can add a fixed latency to each call to mimic the cost
+//! of crossing into Go.  `benches/statedb_mock_host.rs` times committed calls
+//! against it with and without that latency.
+//!
+//! Commits reach the host through `re_state_set_basic`/`re_state_set_storage`,
+//! which carry no bytecode, so code of contracts created by REVM has to be
+//! added with [`MockHost::set_code`] before it can be read back.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use revm::primitives::{Address, Bytes, B256, U256};
+use revm::state::AccountInfo;
+
+use crate::statedb_types::{FFIAccountInfo, FFIAddress, FFIHash, FFIU256};
+
+/// The host callbacks, for counting and fault injection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HostCall {
+    Basic,
+    Storage,
+    Code,
+    BlockHash,
+    SetBasic,
+    SetStorage,
+}
+
+#[derive(Default)]
+struct HostState {
+    /// Balance, nonce and code hash; code lives in `codes`.
+    accounts: BTreeMap<Address, (U256, u64, B256)>,
+    storage: BTreeMap<(Address, U256), U256>,
+    codes: BTreeMap<B256, Bytes>,
+    block_hashes: BTreeMap<u64, B256>,
+    calls: HashMap<HostCall, usize>,
+    /// Number of upcoming calls of each kind that fail.
+    faults: HashMap<HostCall, usize>,
+    latency: Duration,
+}
+
+impl HostState {
+    /// Count `call` and decide whether it should fail.
+    fn enter(&mut self, call: HostCall) -> bool {
+        *self.calls.entry(call).or_default() += 1;
+        match self.faults.get_mut(&call) {
+            Some(remaining) if *remaining > 0 => {
+                *remaining -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+type Shared = Arc<Mutex<HostState>>;
+
+fn registry() -> &'static Mutex<HashMap<usize, Shared>> {
+    static HOSTS: OnceLock<Mutex<HashMap<usize, Shared>>> = OnceLock::new();
+    HOSTS.get_or_init(Default::default)
+}
+
+/// Handles start above zero so a zeroed handle never reaches a host.
+static NEXT_HANDLE: AtomicUsize = AtomicUsize::new(1);
+
+/// An in-memory StateDB reachable through its [`handle`](MockHost::handle).
+/// Dropping the host unregisters it; later callbacks on its handle fail.
+pub struct MockHost {
+    handle: usize,
+    state: Shared,
+}
+
+impl MockHost {
+    pub fn new() -> Self {
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        let state = Shared::default();
+        registry()
+            .lock()
+            .unwrap()
+            .insert(handle, Arc::clone(&state));
+        Self { handle, state }
+    }
+
+    /// The handle to pass to `revm_new_with_statedb` or `GoDatabase::new`.
+    pub fn handle(&self) -> usize {
+        self.handle
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, HostState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Store an account; code carried by `info` is stored under its hash.
+    pub fn insert_account(&self, address: Address, info: AccountInfo) {
+        let mut state = self.state();
+        if let Some(code) = info.code.filter(|code| !code.is_empty()) {
+            state.codes.insert(info.code_hash, code.original_bytes());
+        }
+        state
+            .accounts
+            .insert(address, (info.balance, info.nonce, info.code_hash));
+    }
+
+    /// The account as the host currently holds it, without code.
+    pub fn account(&self, address: Address) -> Option<AccountInfo> {
+        self.state()
+            .accounts
+            .get(&address)
+            .map(|&(balance, nonce, code_hash)| AccountInfo {
+                balance,
+                nonce,
+                code_hash,
+                code: None,
+            })
+    }
+
+    pub fn set_storage(&self, address: Address, slot: U256, value: U256) {
+        self.state().storage.insert((address, slot), value);
+    }
+
+    pub fn storage(&self, address: Address, slot: U256) -> U256 {
+        self.state()
+            .storage
+            .get(&(address, slot))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Serve `code` for `code_hash`.  The hash is not checked, so tests can
+    /// pair arbitrary hashes with code.
+    pub fn set_code(&self, code_hash: B256, code: Bytes) {
+        self.state().codes.insert(code_hash, code);
+    }
+
+    pub fn set_block_hash(&self, number: u64, hash: B256) {
+        self.state().block_hashes.insert(number, hash);
+    }
+
+    /// Number of `call` callbacks received so far.
+    pub fn calls(&self, call: HostCall) -> usize {
+        self.state().calls.get(&call).copied().unwrap_or_default()
+    }
+
+    /// Make the next `times` `call` callbacks fail (`usize::MAX` for all).
+    pub fn fail_next(&self, call: HostCall, times: usize) {
+        self.state().faults.insert(call, times);
+    }
+
+    /// Delay every callback by `latency`.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+}
+
+impl Default for MockHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MockHost {
+    fn drop(&mut self) {
+        if let Ok(mut hosts) = registry().lock() {
+            hosts.remove(&self.handle);
+        }
+    }
+}
+
+/// Run `f` on the host behind `handle`, or return -1 if there is none or a
+/// fault is due.  The latency is served without holding any lock.
+fn with_host(handle: usize, call: HostCall, f: impl FnOnce(&mut HostState) -> i32) -> i32 {
+    let Some(host) = registry().lock().unwrap().get(&handle).cloned() else {
+        return -1;
+    };
+    let latency = host.lock().unwrap().latency;
+    if !latency.is_zero() {
+        std::thread::sleep(latency);
+    }
+    let mut state = host.lock().unwrap();
+    if state.enter(call) {
+        return -1;
+    }
+    f(&mut state)
+}
+
+#[no_mangle]
+extern "C" fn re_state_basic(
+    handle: usize,
+    addr: FFIAddress,
+    out_info: *mut FFIAccountInfo,
+) -> i32 {
+    with_host(handle, HostCall::Basic, |state| {
+        match state.accounts.get(&Address::from(addr.bytes)) {
+            Some(&(balance, nonce, code_hash)) => {
+                let info = FFIAccountInfo {
+                    balance: FFIU256 {
+                        bytes: balance.to_be_bytes(),
+                    },
+                    nonce,
+                    code_hash: FFIHash { bytes: code_hash.0 },
+                };
+                unsafe { *out_info = info };
+                0
+            }
+            None => 1,
+        }
+    })
+}
+
+#[no_mangle]
+extern "C" fn re_state_storage(
+    handle: usize,
+    addr: FFIAddress,
+    slot: FFIHash,
+    out_val: *mut FFIU256,
+) -> i32 {
+    with_host(handle, HostCall::Storage, |state| {
+        let key = (Address::from(addr.bytes), U256::from_be_bytes(slot.bytes));
+        let value = state.storage.get(&key).copied().unwrap_or_default();
+        unsafe {
+            *out_val = FFIU256 {
+                bytes: value.to_be_bytes(),
+            }
+        };
+        0
+    })
+}
+
+#[no_mangle]
+extern "C" fn re_state_block_hash(handle: usize, number: u64, out_hash: *mut FFIHash) -> i32 {
+    with_host(handle, HostCall::BlockHash, |state| {
+        let hash = state.block_hashes.get(&number).copied().unwrap_or_default();
+        unsafe { *out_hash = FFIHash { bytes: hash.0 } };
+        0
+    })
+}
+
+/// Code is handed out in a `malloc` buffer, which `GoDatabase` frees.
+#[no_mangle]
+extern "C" fn re_state_code(
+    handle: usize,
+    code_hash: FFIHash,
+    out_ptr: *mut *mut u8,
+    out_len: *mut u32,
+) -> i32 {
+    with_host(handle, HostCall::Code, |state| {
+        let Some(code) = state.codes.get(&B256::from(code_hash.bytes)) else {
+            return 1;
+        };
+        unsafe {
+            let buf = libc::malloc(code.len().max(1)) as *mut u8;
+            ptr::copy_nonoverlapping(code.as_ptr(), buf, code.len());
+            *out_ptr = buf;
+            *out_len = code.len() as u32;
+        }
+        0
+    })
+}
+
+#[no_mangle]
+extern "C" fn re_state_set_basic(handle: usize, addr: FFIAddress, info: FFIAccountInfo) -> i32 {
+    with_host(handle, HostCall::SetBasic, |state| {
+        let account = (
+            U256::from_be_bytes(info.balance.bytes),
+            info.nonce,
+            B256::from(info.code_hash.bytes),
+        );
+        state.accounts.insert(Address::from(addr.bytes), account);
+        0
+    })
+}
+
+#[no_mangle]
+extern "C" fn re_state_set_storage(
+    handle: usize,
+    addr: FFIAddress,
+    slot: FFIHash,
+    val: FFIU256,
+) -> i32 {
+    with_host(handle, HostCall::SetStorage, |state| {
+        let key = (Address::from(addr.bytes), U256::from_be_bytes(slot.bytes));
+        state.storage.insert(key, U256::from_be_bytes(val.bytes));
+        0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::U256;
+    use std::time::{Duration, Instant};
+
+    /// `SSTORE(0, SLOAD(0) + 1); STOP`
+    const COUNTER: [u8; 8] = [0x5f, 0x54, 0x60, 0x01, 0x01, 0x5f, 0x55, 0x00];
+
+    #[test]
+    fn drives_statedb_instance() {
+        let (caller, contract) = (CALLER, CONTRACT);
+        let (from, to) = (c_address(caller), c_address(contract));
+        let host = host_with(&COUNTER);
+        host.set_storage(contract, U256::ZERO, U256::from(41));
+
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+            let result = revm_call_contract_statedb_commit(
+                instance,
+                from.as_ptr(),
+                to.as_ptr(),
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+                100_000,
+            );
+            assert!(!result.is_null());
+            assert_eq!((*result).success, 1);
+            revm_free_execution_result(result);
+            revm_free_statedb_instance(instance);
+        }
+        assert_eq!(host.storage(contract, U256::ZERO), U256::from(42));
+        assert_eq!(host.account(caller).unwrap().nonce, 1);
+        assert_eq!(host.calls(HostCall::Code), 1);
+        assert_eq!(host.calls(HostCall::SetStorage), 1);
+
+        // A failing storage read aborts the next call without committing.
+        host.fail_next(HostCall::Storage, 1);
+        host.set_latency(Duration::from_millis(5));
+        let start = Instant::now();
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+            let result = revm_call_contract_statedb_commit(
+                instance,
+                from.as_ptr(),
+                to.as_ptr(),
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+                100_000,
+            );
+            assert!(result.is_null());
+            revm_free_statedb_instance(instance);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(10));
+        assert_eq!(host.storage(contract, U256::ZERO), U256::from(42));
+        assert_eq!(host.account(caller).unwrap().nonce, 1);
+
+        let handle = host.handle();
+        drop(host);
+        let db = GoDatabase::new(handle);
+        assert!(revm::database_interface::DatabaseRef::basic_ref(&db, caller).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+//! geth's lightweight native tracers and tracer selection by name.
+//!
+//! [`FourByteTracer`] counts the selector and calldata size of every call
+//! like geth's `4byteTracer`, `noopTracer` produces `{}`, and `muxTracer`
+//! runs several tracers over one execution and reports each result under
+//! its name.  [`GethTracer`] picks any of these, `callTracer` or
+//! `prestateTracer` from the name and `tracerConfig` geth's
+//! `debug_traceTransaction` takes; with the `js-tracer` feature, any other
+//! name is a JavaScript tracer's source, as in geth.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use revm::{
+    context_interface::{
+        result::{ExecutionResult, ResultAndState},
+        Cfg, ContextTr,
+    },
+    database_interface::DatabaseRef,
+    inspector::Inspector,
+    interpreter::{CallInputs, CallOutcome, CreateInputs, CreateOutcome, Interpreter},
+    precompile::{PrecompileSpecId, Precompiles},
+    primitives::{Address, Log, U256},
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::call_tracer::{CallTracer, CallTracerConfig};
+#[cfg(feature = "js-tracer")]
+use crate::js_tracer::JsTracer;
+use crate::prestate::{prestate_trace, PrestateTracerConfig};
+use crate::types::RevmInstance;
+
+/// geth's `4byteTracer`: counts `0x<selector>-<calldata size>` over every
+/// call with at least four bytes of input, leaving out creations and calls
+/// to precompiles.
+#[derive(Clone, Debug, Default)]
+pub struct FourByteTracer {
+    ids: BTreeMap<String, u64>,
+}
+
+impl FourByteTracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The counts so far, keyed like geth's output.
+    pub fn ids(&self) -> &BTreeMap<String, u64> {
+        &self.ids
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for FourByteTracer {
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        let input = inputs.input.bytes(context);
+        if input.len() < 4 {
+            return None;
+        }
+        let precompiles =
+            Precompiles::new(PrecompileSpecId::from_spec_id(context.cfg().spec().into()));
+        if precompiles.contains(&inputs.bytecode_address) {
+            return None;
+        }
+        let key = format!("0x{}-{}", hex::encode(&input[..4]), input.len() - 4);
+        *self.ids.entry(key).or_default() += 1;
+        None
+    }
+}
+
+/// A geth tracer selected by name, as run by [`RevmInstance::execute_geth_trace`].
+pub enum GethTracer {
+    Call(CallTracer),
+    /// Computed from the execution's state once it has run.
+    Prestate(PrestateTracerConfig),
+    FourByte(FourByteTracer),
+    Noop,
+    /// The `muxTracer` tracers and their names.
+    Mux(Vec<(String, GethTracer)>),
+    #[cfg(feature = "js-tracer")]
+    Js(Box<JsTracer>),
+}
+
+impl GethTracer {
+    /// The tracer geth knows as `name`, configured with its `tracerConfig`
+    /// (`null` for the defaults).
+    pub fn new(name: &str, config: Value) -> Result<Self> {
+        Ok(match name {
+            "callTracer" => Self::Call(CallTracer::new(tracer_config::<CallTracerConfig>(config)?)),
+            "prestateTracer" => Self::Prestate(tracer_config(config)?),
+            "4byteTracer" => Self::FourByte(FourByteTracer::new()),
+            "noopTracer" => Self::Noop,
+            "muxTracer" => Self::Mux(
+                tracer_config::<BTreeMap<String, Value>>(config)?
+                    .into_iter()
+                    .map(|(name, config)| Ok((name.clone(), Self::new(&name, config)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            // geth runs any other tracer as JavaScript.
+            #[cfg(feature = "js-tracer")]
+            code => Self::Js(Box::new(JsTracer::new(code, &config)?)),
+            #[cfg(not(feature = "js-tracer"))]
+            _ => anyhow::bail!("unknown tracer {name}"),
+        })
+    }
+
+    /// The tracer's JSON result for `outcome`, an uncommitted execution on
+    /// `db`.
+    pub fn into_result<DB>(self, db: &DB, outcome: &ResultAndState) -> Result<Value>
+    where
+        DB: DatabaseRef,
+        DB::Error: std::error::Error + Send + Sync + 'static,
+    {
+        Ok(match self {
+            Self::Call(tracer) => {
+                let frame = tracer
+                    .into_frame(&outcome.result)
+                    .ok_or_else(|| anyhow::anyhow!("transaction produced no call frame"))?;
+                serde_json::to_value(frame)?
+            }
+            Self::Prestate(config) => {
+                serde_json::to_value(prestate_trace(db, &outcome.state, config)?)?
+            }
+            Self::FourByte(tracer) => serde_json::to_value(tracer.ids)?,
+            Self::Noop => Value::Object(Default::default()),
+            #[cfg(feature = "js-tracer")]
+            Self::Js(tracer) => tracer.into_result(db, outcome)?,
+            Self::Mux(tracers) => Value::Object(
+                tracers
+                    .into_iter()
+                    .map(|(name, tracer)| Ok((name, tracer.into_result(db, outcome)?)))
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+
+    /// Run `f` on every inspector this tracer is made of.
+    fn for_each<CTX: ContextTr>(&mut self, f: &mut impl FnMut(&mut dyn Inspector<CTX>)) {
+        match self {
+            Self::Call(tracer) => f(tracer),
+            Self::FourByte(tracer) => f(tracer),
+            #[cfg(feature = "js-tracer")]
+            Self::Js(tracer) => f(tracer.as_mut()),
+            Self::Mux(tracers) => tracers
+                .iter_mut()
+                .for_each(|(_, tracer)| tracer.for_each(f)),
+            Self::Prestate(_) | Self::Noop => {}
+        }
+    }
+}
+
+/// A `tracerConfig` value, `null` meaning the defaults.
+fn tracer_config<T: DeserializeOwned + Default>(config: Value) -> Result<T> {
+    if config.is_null() {
+        return Ok(T::default());
+    }
+    Ok(serde_json::from_value(config)?)
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for GethTracer {
+    fn initialize_interp(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        self.for_each(&mut |tracer| tracer.initialize_interp(interp, context));
+    }
+
+    fn step(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        self.for_each(&mut |tracer| tracer.step(interp, context));
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, context: &mut CTX) {
+        self.for_each(&mut |tracer| tracer.step_end(interp, context));
+    }
+
+    fn log(&mut self, interp: &mut Interpreter, context: &mut CTX, log: Log) {
+        self.for_each(&mut |tracer| tracer.log(interp, context, log.clone()));
+    }
+
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        self.for_each(&mut |tracer| {
+            tracer.call(context, inputs);
+        });
+        None
+    }
+
+    fn call_end(&mut self, context: &mut CTX, inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.for_each(&mut |tracer| tracer.call_end(context, inputs, outcome));
+    }
+
+    fn create(&mut self, context: &mut CTX, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        self.for_each(&mut |tracer| {
+            tracer.create(context, inputs);
+        });
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        context: &mut CTX,
+        inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.for_each(&mut |tracer| tracer.create_end(context, inputs, outcome));
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+        self.for_each::<CTX>(&mut |tracer| tracer.selfdestruct(contract, target, value));
+    }
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction under the geth tracer called `name`
+    /// with `tracerConfig` `config` and return its result with the tracer's
+    /// JSON output, committing when `commit` is true.
+    pub fn execute_geth_trace(
+        &mut self,
+        name: &str,
+        config: Value,
+        commit: bool,
+    ) -> Result<(ExecutionResult, Value)> {
+        let tracer = GethTracer::new(name, config)?;
+        let (outcome, tracer) = self.inspect_pending(tracer)?;
+        let trace = tracer.into_result(&self.evm.ctx.journaled_state.database, &outcome)?;
+        if commit {
+            self.commit_state(outcome.state);
+        }
+        Ok((outcome.result, trace))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::Address;
+    use std::ffi::CString;
+
+    fn trace(instance: *mut RevmInstance, name: &str, config: Option<&str>) -> serde_json::Value {
+        let name = CString::new(name).unwrap();
+        let config = config.map(|config| CString::new(config).unwrap());
+        unsafe {
+            take_json(revm_trace(
+                instance,
+                name.as_ptr(),
+                config
+                    .as_ref()
+                    .map_or(std::ptr::null(), |config| config.as_ptr()),
+                false,
+            ))
+        }
+    }
+
+    #[test]
+    fn runs_native_tracers_by_name() {
+        let outer = CONTRACT;
+        let inner = Address::repeat_byte(0xdd);
+        // MSTORE(0, 0x12345678 << 224); STATICCALL(GAS, inner, 0, 8, 0, 0);
+        // STATICCALL(GAS, identity, 0, 8, 0, 0); STOP
+        let mut code = vec![
+            0x63, 0x12, 0x34, 0x56, 0x78, 0x60, 0xe0, 0x1b, 0x5f, 0x52, 0x5f, 0x5f, 0x60, 0x08,
+            0x5f, 0x73,
+        ];
+        code.extend_from_slice(inner.as_slice());
+        code.extend_from_slice(&[
+            0x5a, 0xfa, 0x50, 0x5f, 0x5f, 0x60, 0x08, 0x5f, 0x60, 0x04, 0x5a, 0xfa, 0x50, 0x00,
+        ]);
+        let mut data = vec![0xaa, 0xbb, 0xcc, 0xdd];
+        data.extend_from_slice(&[0; 32]);
+
+        unsafe {
+            let instance = instance_with_contracts(&[(outer, &code), (inner, &[0x00])]);
+            set_call(instance, outer, &data, 0);
+
+            // The precompile call is left out.
+            let four_byte = serde_json::json!({ "0xaabbccdd-32": 1, "0x12345678-4": 1 });
+            assert_eq!(trace(instance, "4byteTracer", None), four_byte);
+            assert_eq!(trace(instance, "noopTracer", None), serde_json::json!({}));
+
+            let mux = trace(
+                instance,
+                "muxTracer",
+                Some(r#"{"4byteTracer":null,"callTracer":{"onlyTopCall":true},"noopTracer":{}}"#),
+            );
+            assert_eq!(mux["4byteTracer"], four_byte);
+            assert_eq!(mux["noopTracer"], serde_json::json!({}));
+            assert_eq!(mux["callTracer"]["to"], format!("{outer:#x}"));
+            assert!(mux["callTracer"].get("calls").is_none());
+            assert_eq!(
+                mux["callTracer"],
+                trace(instance, "callTracer", Some(r#"{"onlyTopCall":true}"#))
+            );
+
+            // With `js-tracer` other names are compiled as JavaScript.
+            let name = CString::new("structLogger").unwrap();
+            assert!(revm_trace(instance, name.as_ptr(), std::ptr::null(), false).is_null());
+            let error = (*instance).last_error.as_deref().unwrap();
+            #[cfg(not(feature = "js-tracer"))]
+            assert_eq!(error, "unknown tracer structLogger");
+            #[cfg(feature = "js-tracer")]
+            assert_eq!(error, "ReferenceError: structLogger is not defined");
+
+            // Nothing was committed.
+            assert_eq!(revm_get_nonce(instance, c_address(CALLER).as_ptr()), 0);
+            revm_free(instance);
+        }
+    }
+}
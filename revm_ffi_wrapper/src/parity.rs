@@ -0,0 +1,812 @@
+//! Parity/OpenEthereum-style traces (`trace_*`).
+//!
+//! [`ParityTracer`] records the flat trace of a transaction: one entry per
+//! call, create and selfdestruct in execution order, each with its
+//! `traceAddress` path, action and result (or error).  With `vmTrace` it
+//! also records every executed opcode with its cost, the stack items it
+//! pushed and the memory and storage it wrote, nested per call.  The
+//! `stateDiff` is computed from the execution's state changes against the
+//! database.  Block traces are the concatenation of the localized traces of
+//! each transaction; there are no reward traces.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use revm::{
+    bytecode::opcode::{self, OpCode},
+    context_interface::{result::ExecutionResult, ContextTr},
+    database_interface::DatabaseRef,
+    inspector::Inspector,
+    interpreter::{
+        interpreter_types::{Jumps, LoopControl, MemoryTr},
+        CallInputs, CallOutcome, CallScheme, CreateInputs, CreateOutcome, InstructionResult,
+        Interpreter, InterpreterResult,
+    },
+    primitives::{alloy_primitives::U64, Address, Bytes, B256, U256},
+    state::EvmState,
+};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+
+use crate::call_tracer::call_kind;
+use crate::prestate::code_of;
+use crate::types::RevmInstance;
+
+/// The trace types requested from `trace_replayTransaction`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParityTraceTypes {
+    pub trace: bool,
+    pub vm_trace: bool,
+    pub state_diff: bool,
+}
+
+impl ParityTraceTypes {
+    /// Parse a trace type list such as `["trace", "vmTrace", "stateDiff"]`.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self> {
+        let mut types = Self::default();
+        for name in names {
+            match name.as_ref() {
+                "trace" => types.trace = true,
+                "vmTrace" => types.vm_trace = true,
+                "stateDiff" => types.state_diff = true,
+                other => bail!("unknown trace type: {other}"),
+            }
+        }
+        Ok(types)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CallType {
+    Call,
+    Callcode,
+    Delegatecall,
+    Staticcall,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Action {
+    #[serde(rename_all = "camelCase")]
+    Call {
+        call_type: CallType,
+        from: Address,
+        gas: U64,
+        input: Bytes,
+        to: Address,
+        value: U256,
+    },
+    Create {
+        from: Address,
+        gas: U64,
+        init: Bytes,
+        value: U256,
+    },
+    #[serde(rename_all = "camelCase")]
+    Suicide {
+        address: Address,
+        balance: U256,
+        refund_address: Address,
+    },
+}
+
+impl Action {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Call { .. } => "call",
+            Self::Create { .. } => "create",
+            Self::Suicide { .. } => "suicide",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum TraceOutput {
+    #[serde(rename_all = "camelCase")]
+    Call { gas_used: U64, output: Bytes },
+    #[serde(rename_all = "camelCase")]
+    Create {
+        address: Address,
+        code: Bytes,
+        gas_used: U64,
+    },
+}
+
+/// One entry of a flat trace.  The block and transaction fields are only
+/// set for localized traces (`trace_transaction`, `trace_block`).
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionTrace {
+    pub action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<B256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// `null` for failed frames and selfdestructs.
+    pub result: Option<TraceOutput>,
+    pub subtraces: usize,
+    pub trace_address: Vec<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<B256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_position: Option<u64>,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+/// Where a transaction sits in its block, for localized traces.
+#[derive(Clone, Copy, Debug)]
+pub struct TraceLocation {
+    pub block_hash: B256,
+    pub transaction_hash: B256,
+    pub transaction_position: u64,
+}
+
+/// The opcodes of one call frame.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VmTrace {
+    pub code: Bytes,
+    pub ops: Vec<VmOperation>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct VmOperation {
+    pub pc: usize,
+    pub cost: u64,
+    /// `null` when the opcode failed.
+    pub ex: Option<VmExecuted>,
+    /// The frame entered by a call or create.
+    pub sub: Option<VmTrace>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct VmExecuted {
+    /// Gas left after the opcode.
+    pub used: u64,
+    pub push: Vec<U256>,
+    pub mem: Option<MemoryDelta>,
+    pub store: Option<StorageDelta>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MemoryDelta {
+    pub off: usize,
+    pub data: Bytes,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct StorageDelta {
+    pub key: U256,
+    pub val: U256,
+}
+
+/// A value in a state diff: `"="`, `{"+": new}`, `{"-": old}` or
+/// `{"*": {"from": old, "to": new}}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Delta<T> {
+    Unchanged,
+    Added(T),
+    Removed(T),
+    Changed { from: T, to: T },
+}
+
+impl<T: PartialEq> Delta<T> {
+    fn new(before: Option<T>, after: Option<T>) -> Self {
+        match (before, after) {
+            (None, None) => Self::Unchanged,
+            (None, Some(to)) => Self::Added(to),
+            (Some(from), None) => Self::Removed(from),
+            (Some(from), Some(to)) if from == to => Self::Unchanged,
+            (Some(from), Some(to)) => Self::Changed { from, to },
+        }
+    }
+
+    fn is_unchanged(&self) -> bool {
+        matches!(self, Self::Unchanged)
+    }
+}
+
+impl<T: Serialize> Serialize for Delta<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct FromTo<'a, T> {
+            from: &'a T,
+            to: &'a T,
+        }
+
+        let mut map = match self {
+            Self::Unchanged => return serializer.serialize_str("="),
+            _ => serializer.serialize_map(Some(1))?,
+        };
+        match self {
+            Self::Unchanged => unreachable!(),
+            Self::Added(value) => map.serialize_entry("+", value)?,
+            Self::Removed(value) => map.serialize_entry("-", value)?,
+            Self::Changed { from, to } => map.serialize_entry("*", &FromTo { from, to })?,
+        }
+        map.end()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AccountDiff {
+    pub balance: Delta<U256>,
+    pub code: Delta<Bytes>,
+    pub nonce: Delta<U64>,
+    pub storage: BTreeMap<B256, Delta<B256>>,
+}
+
+pub type StateDiff = BTreeMap<Address, AccountDiff>;
+
+/// The result of `trace_replayTransaction`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceResults {
+    pub output: Bytes,
+    pub state_diff: Option<StateDiff>,
+    pub trace: Vec<TransactionTrace>,
+    pub vm_trace: Option<VmTrace>,
+}
+
+/// OpenEthereum's message for a failed frame.
+fn parity_error(result: InstructionResult) -> Option<&'static str> {
+    use InstructionResult::*;
+    Some(match result {
+        Continue | Stop | Return | SelfDestruct | ReturnContract | CallOrCreate => return None,
+        Revert => "Reverted",
+        OutOfGas | MemoryOOG | MemoryLimitOOG | PrecompileOOG | InvalidOperandOOG
+        | ReentrancySentryOOG => "Out of gas",
+        OpcodeNotFound | InvalidFEOpcode | NotActivated | EOFOpcodeDisabledInLegacy => {
+            "Bad instruction"
+        }
+        InvalidJump => "Bad jump destination",
+        StackUnderflow => "Stack underflow",
+        StackOverflow | CallTooDeep => "Out of stack",
+        CallNotAllowedInsideStatic | StateChangeDuringStaticCall => {
+            "Mutable Call In Static Context"
+        }
+        PrecompileError => "Built-in failed",
+        OutOfOffset => "Out of bounds",
+        _ => "Internal error",
+    })
+}
+
+/// An opcode whose line is completed in `step_end`.
+struct PendingOp {
+    pc: usize,
+    op: u8,
+    gas: u64,
+    /// Memory range the opcode writes.
+    mem: Option<(usize, usize)>,
+    store: Option<StorageDelta>,
+}
+
+/// A call or create whose outcome is only known when its frame resumes.
+struct DeferredOp {
+    index: usize,
+    outputs: usize,
+    mem: Option<(usize, usize)>,
+}
+
+#[derive(Default)]
+struct VmFrame {
+    trace: VmTrace,
+    deferred: Option<DeferredOp>,
+}
+
+struct OpenFrame {
+    index: usize,
+    children: usize,
+}
+
+/// Inspector recording Parity flat traces and, optionally, the vmTrace.
+pub struct ParityTracer {
+    vm_trace: bool,
+    traces: Vec<TransactionTrace>,
+    open: Vec<OpenFrame>,
+    frames: Vec<VmFrame>,
+    root: Option<VmTrace>,
+    pending: Option<PendingOp>,
+}
+
+impl ParityTracer {
+    pub fn new(vm_trace: bool) -> Self {
+        Self {
+            vm_trace,
+            traces: Vec::new(),
+            open: Vec::new(),
+            frames: Vec::new(),
+            root: None,
+            pending: None,
+        }
+    }
+
+    /// The flat trace and, if recorded, the vmTrace.
+    pub fn into_parts(self) -> (Vec<TransactionTrace>, Option<VmTrace>) {
+        (self.traces, self.root)
+    }
+
+    /// Add a trace below the innermost open frame and return its index.
+    fn push_trace(&mut self, action: Action) -> usize {
+        let trace_address = match self.open.last_mut() {
+            Some(parent) => {
+                let mut path = self.traces[parent.index].trace_address.clone();
+                path.push(parent.children);
+                parent.children += 1;
+                path
+            }
+            None => Vec::new(),
+        };
+        self.traces.push(TransactionTrace {
+            kind: action.kind(),
+            action,
+            block_hash: None,
+            block_number: None,
+            error: None,
+            result: None,
+            subtraces: 0,
+            trace_address,
+            transaction_hash: None,
+            transaction_position: None,
+        });
+        self.traces.len() - 1
+    }
+
+    fn enter(&mut self, action: Action) {
+        let index = self.push_trace(action);
+        self.open.push(OpenFrame { index, children: 0 });
+        if self.vm_trace {
+            self.frames.push(VmFrame::default());
+        }
+    }
+
+    fn exit(&mut self, result: &InterpreterResult, created: Option<Address>) {
+        let Some(frame) = self.open.pop() else {
+            return;
+        };
+        let trace = &mut self.traces[frame.index];
+        trace.subtraces = frame.children;
+        let gas_used = U64::from(result.gas.spent());
+        match parity_error(result.result) {
+            Some(error) => trace.error = Some(error.to_string()),
+            None => {
+                trace.result = Some(match trace.action {
+                    Action::Create { .. } => TraceOutput::Create {
+                        address: created.unwrap_or_default(),
+                        code: result.output.clone(),
+                        gas_used,
+                    },
+                    _ => TraceOutput::Call {
+                        gas_used,
+                        output: result.output.clone(),
+                    },
+                })
+            }
+        }
+
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        match self.frames.last_mut() {
+            // The sub-trace belongs to the call or create that is waiting
+            // for this frame.
+            Some(parent) => {
+                if let Some(op) = parent.trace.ops.last_mut() {
+                    op.sub = Some(frame.trace);
+                }
+            }
+            None => self.root = Some(frame.trace),
+        }
+    }
+}
+
+/// Memory written by `op`, from its operands.
+fn written_memory(op: u8, interp: &Interpreter) -> Option<(usize, usize)> {
+    let arg = |n| {
+        interp
+            .stack
+            .peek(n)
+            .ok()
+            .map(|value| usize::try_from(value).unwrap_or(usize::MAX))
+    };
+    let (offset, len) = match op {
+        opcode::MSTORE => (arg(0)?, 32),
+        opcode::MSTORE8 => (arg(0)?, 1),
+        opcode::CALLDATACOPY | opcode::CODECOPY | opcode::RETURNDATACOPY | opcode::MCOPY => {
+            (arg(0)?, arg(2)?)
+        }
+        opcode::EXTCODECOPY => (arg(1)?, arg(3)?),
+        opcode::CALL | opcode::CALLCODE => (arg(5)?, arg(6)?),
+        opcode::DELEGATECALL | opcode::STATICCALL => (arg(4)?, arg(5)?),
+        _ => return None,
+    };
+    (len > 0).then_some((offset, len))
+}
+
+/// What an opcode left behind: gas, pushed items and written memory.
+fn executed(
+    interp: &Interpreter,
+    outputs: usize,
+    mem: Option<(usize, usize)>,
+    store: Option<StorageDelta>,
+) -> VmExecuted {
+    let stack = interp.stack.data();
+    let mem = mem.and_then(|(off, len)| {
+        let end = off.checked_add(len)?;
+        (end <= interp.memory.size()).then(|| MemoryDelta {
+            off,
+            data: Bytes::copy_from_slice(&interp.memory.slice(off..end)),
+        })
+    });
+    VmExecuted {
+        used: interp.control.gas().remaining(),
+        push: stack[stack.len().saturating_sub(outputs)..].to_vec(),
+        mem,
+        store,
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for ParityTracer {
+    fn initialize_interp(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.trace.code = interp.bytecode.original_bytes();
+        }
+    }
+
+    fn step(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        if let Some(deferred) = frame.deferred.take() {
+            let ex = executed(interp, deferred.outputs, deferred.mem, None);
+            frame.trace.ops[deferred.index].ex = Some(ex);
+        }
+        let op = interp.bytecode.opcode();
+        let store = match (op, interp.stack.peek(0), interp.stack.peek(1)) {
+            (opcode::SSTORE, Ok(key), Ok(val)) => Some(StorageDelta { key, val }),
+            _ => None,
+        };
+        self.pending = Some(PendingOp {
+            pc: interp.bytecode.pc(),
+            op,
+            gas: interp.control.gas().remaining(),
+            mem: written_memory(op, interp),
+            store,
+        });
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let (Some(step), Some(frame)) = (self.pending.take(), self.frames.last_mut()) else {
+            return;
+        };
+        let cost = step.gas.saturating_sub(interp.control.gas().remaining());
+        let outputs = OpCode::new(step.op).map_or(0, |op| op.outputs() as usize);
+        let index = frame.trace.ops.len();
+        let ex = if interp.control.instruction_result().is_error() {
+            None
+        } else if matches!(
+            step.op,
+            opcode::CALL
+                | opcode::CALLCODE
+                | opcode::DELEGATECALL
+                | opcode::STATICCALL
+                | opcode::CREATE
+                | opcode::CREATE2
+        ) {
+            frame.deferred = Some(DeferredOp {
+                index,
+                outputs,
+                mem: step.mem,
+            });
+            None
+        } else {
+            Some(executed(interp, outputs, step.mem, step.store))
+        };
+        frame.trace.ops.push(VmOperation {
+            pc: step.pc,
+            cost,
+            ex,
+            sub: None,
+        });
+    }
+
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        let (call_type, to) = match inputs.scheme {
+            CallScheme::Call | CallScheme::ExtCall => (CallType::Call, inputs.target_address),
+            CallScheme::StaticCall | CallScheme::ExtStaticCall => {
+                (CallType::Staticcall, inputs.target_address)
+            }
+            CallScheme::DelegateCall | CallScheme::ExtDelegateCall => {
+                (CallType::Delegatecall, inputs.bytecode_address)
+            }
+            CallScheme::CallCode => (CallType::Callcode, inputs.bytecode_address),
+        };
+        let (_, from) = call_kind(inputs);
+        self.enter(Action::Call {
+            call_type,
+            from,
+            gas: U64::from(inputs.gas_limit),
+            input: inputs.input.bytes(context),
+            to,
+            value: inputs.value.get(),
+        });
+        None
+    }
+
+    fn call_end(&mut self, _context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.exit(&outcome.result, None);
+    }
+
+    fn create(&mut self, _context: &mut CTX, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        self.enter(Action::Create {
+            from: inputs.caller,
+            gas: U64::from(inputs.gas_limit),
+            init: inputs.init_code.clone(),
+            value: inputs.value,
+        });
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.exit(&outcome.result, outcome.address);
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+        self.push_trace(Action::Suicide {
+            address: contract,
+            balance: value,
+            refund_address: target,
+        });
+    }
+}
+
+/// The Parity state diff of `state` against `db`, the state before it.
+pub fn state_diff<DB: DatabaseRef>(db: &DB, state: &EvmState) -> Result<StateDiff, DB::Error> {
+    let mut diff = StateDiff::new();
+    for (&address, account) in state {
+        let before = db.basic_ref(address)?;
+        // Selfdestructed and touched empty accounts are gone afterwards.
+        let removed = account.is_selfdestructed() || (account.is_touched() && account.is_empty());
+        let exists = !removed;
+        let after = exists.then_some(&account.info);
+        let code_before = before.as_ref().map(|info| code_of(db, info)).transpose()?;
+        let code_after = after.map(|info| code_of(db, info)).transpose()?;
+
+        let mut storage = BTreeMap::new();
+        for (slot, value) in &account.storage {
+            let (from, to) = (value.original_value, value.present_value);
+            let delta = match (before.is_some(), exists) {
+                (true, true) => Delta::new(Some(from), Some(to)),
+                (false, true) => Delta::new(None, (!to.is_zero()).then_some(to)),
+                (true, false) => Delta::new((!from.is_zero()).then_some(from), None),
+                (false, false) => Delta::Unchanged,
+            };
+            if !delta.is_unchanged() {
+                storage.insert(B256::from(*slot), map_delta(delta, B256::from));
+            }
+        }
+        let account_diff = AccountDiff {
+            balance: Delta::new(
+                before.as_ref().map(|info| info.balance),
+                after.map(|info| info.balance),
+            ),
+            code: Delta::new(code_before, code_after),
+            nonce: Delta::new(
+                before.as_ref().map(|info| U64::from(info.nonce)),
+                after.map(|info| U64::from(info.nonce)),
+            ),
+            storage,
+        };
+        if !(account_diff.balance.is_unchanged()
+            && account_diff.code.is_unchanged()
+            && account_diff.nonce.is_unchanged()
+            && account_diff.storage.is_empty())
+        {
+            diff.insert(address, account_diff);
+        }
+    }
+    Ok(diff)
+}
+
+fn map_delta<T, U>(delta: Delta<T>, f: impl Fn(T) -> U) -> Delta<U> {
+    match delta {
+        Delta::Unchanged => Delta::Unchanged,
+        Delta::Added(value) => Delta::Added(f(value)),
+        Delta::Removed(value) => Delta::Removed(f(value)),
+        Delta::Changed { from, to } => Delta::Changed {
+            from: f(from),
+            to: f(to),
+        },
+    }
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction and return what
+    /// `trace_replayTransaction` would for `types`.  Commits when `commit`
+    /// is true.
+    pub fn execute_parity_trace(
+        &mut self,
+        types: ParityTraceTypes,
+        commit: bool,
+    ) -> Result<(ExecutionResult, TraceResults)> {
+        let (outcome, tracer) = self.inspect_pending(ParityTracer::new(types.vm_trace))?;
+        let state_diff = if types.state_diff {
+            Some(state_diff(
+                &self.evm.ctx.journaled_state.database,
+                &outcome.state,
+            )?)
+        } else {
+            None
+        };
+        let (trace, vm_trace) = tracer.into_parts();
+        let results = TraceResults {
+            output: outcome.result.output().cloned().unwrap_or_default(),
+            state_diff,
+            trace: if types.trace { trace } else { Vec::new() },
+            vm_trace,
+        };
+        if commit {
+            self.commit_state(outcome.state);
+        }
+        Ok((outcome.result, results))
+    }
+
+    /// Execute the pending transaction and return its flat trace localized
+    /// at `location` in the current block, as `trace_transaction` does.
+    /// Run every transaction of a block in order (committing) and
+    /// concatenate the results for `trace_block`.
+    pub fn execute_localized_trace(
+        &mut self,
+        location: TraceLocation,
+        commit: bool,
+    ) -> Result<(ExecutionResult, Vec<TransactionTrace>)> {
+        let types = ParityTraceTypes {
+            trace: true,
+            ..Default::default()
+        };
+        let block_number = self.evm.ctx.block.number;
+        let (result, results) = self.execute_parity_trace(types, commit)?;
+        let mut traces = results.trace;
+        for trace in &mut traces {
+            trace.block_hash = Some(location.block_hash);
+            trace.block_number = Some(block_number);
+            trace.transaction_hash = Some(location.transaction_hash);
+            trace.transaction_position = Some(location.transaction_position);
+        }
+        Ok((result, traces))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::{Address, B256, U256};
+    use std::ffi::CString;
+
+    /// `SSTORE(0, 1); CALL(gas, 0xbb.., 0, 0, 0, 0, 0); STOP`
+    const OUTER: [u8; 34] = [
+        0x60, 0x01, 0x5f, 0x55, 0x5f, 0x5f, 0x5f, 0x5f, 0x5f, 0x73, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+        0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+        0x5a, 0xf1, 0x50, 0x00,
+    ];
+
+    /// `REVERT(0, 0)`
+    const INNER: [u8; 3] = [0x5f, 0x5f, 0xfd];
+
+    /// `DELEGATECALL(gas, 0xbb.., 0, 0, 0, 0); STOP`
+    const DELEGATOR: [u8; 28] = [
+        0x5f, 0x5f, 0x5f, 0x5f, 0x73, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+        0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0x5a, 0xf4, 0x00,
+    ];
+
+    #[test]
+    fn traces_in_parity_format() {
+        let (caller, outer) = (CALLER, CONTRACT);
+        let inner = Address::repeat_byte(0xbb);
+        let delegator = Address::repeat_byte(0xdd);
+
+        unsafe {
+            let instance = instance_with_contracts(&[
+                (outer, &OUTER),
+                (inner, &INNER),
+                (delegator, &DELEGATOR),
+            ]);
+            set_call(instance, outer, &[], 0);
+
+            let types = CString::new(r#"["trace","vmTrace","stateDiff"]"#).unwrap();
+            let replay = take_json(revm_trace_replay_transaction(
+                instance,
+                types.as_ptr(),
+                false,
+            ));
+            assert_eq!(replay["output"], "0x");
+            let trace = replay["trace"].as_array().unwrap();
+            assert_eq!(trace.len(), 2);
+            assert_eq!(trace[0]["type"], "call");
+            assert_eq!(trace[0]["action"]["callType"], "call");
+            assert_eq!(trace[0]["action"]["from"], format!("{caller:#x}"));
+            assert_eq!(trace[0]["subtraces"], 1);
+            assert_eq!(trace[0]["traceAddress"], serde_json::json!([]));
+            assert_eq!(trace[0]["result"]["output"], "0x");
+            assert_eq!(trace[1]["action"]["to"], format!("{inner:#x}"));
+            assert_eq!(trace[1]["traceAddress"], serde_json::json!([0]));
+            assert_eq!(trace[1]["error"], "Reverted");
+            assert!(trace[1]["result"].is_null());
+
+            let ops = replay["vmTrace"]["ops"].as_array().unwrap();
+            assert_eq!(
+                replay["vmTrace"]["code"],
+                format!("0x{}", hex::encode(OUTER))
+            );
+            assert_eq!(ops[0]["cost"], 3);
+            assert_eq!(ops[0]["ex"]["push"], serde_json::json!(["0x1"]));
+            let sstore = &ops[2]["ex"]["store"];
+            assert_eq!(*sstore, serde_json::json!({ "key": "0x0", "val": "0x1" }));
+            let call = &ops[10];
+            assert_eq!(call["pc"], 31);
+            // The failed call pushes 0 once the frame resumes.
+            assert_eq!(call["ex"]["push"], serde_json::json!(["0x0"]));
+            assert_eq!(call["sub"]["code"], format!("0x{}", hex::encode(INNER)));
+            assert_eq!(call["sub"]["ops"].as_array().unwrap().len(), 3);
+            assert!(call["sub"]["ops"][2]["ex"].is_object());
+
+            let diff = &replay["stateDiff"];
+            assert_eq!(
+                diff[format!("{caller:#x}")]["nonce"],
+                serde_json::json!({ "*": { "from": "0x0", "to": "0x1" } })
+            );
+            assert_eq!(diff[format!("{caller:#x}")]["balance"], "=");
+            let slot = format!("{:#x}", revm::primitives::B256::ZERO);
+            assert_eq!(
+                diff[format!("{outer:#x}")]["storage"][&slot]["*"]["to"],
+                format!("{:#x}", revm::primitives::B256::from(U256::from(1)))
+            );
+            assert!(diff.get(format!("{inner:#x}")).is_none());
+
+            let block_hash = CString::new(format!("{:#x}", B256::repeat_byte(1))).unwrap();
+            let tx_hash = CString::new(format!("{:#x}", B256::repeat_byte(2))).unwrap();
+            let localized = take_json(revm_trace_transaction(
+                instance,
+                block_hash.as_ptr(),
+                tx_hash.as_ptr(),
+                3,
+                true,
+            ));
+            assert_eq!(
+                localized[1]["blockHash"],
+                format!("{:#x}", B256::repeat_byte(1))
+            );
+            assert_eq!(localized[1]["blockNumber"], 0);
+            assert_eq!(
+                localized[1]["transactionHash"],
+                format!("{:#x}", B256::repeat_byte(2))
+            );
+            assert_eq!(localized[1]["transactionPosition"], 3);
+            assert_eq!(revm_get_nonce(instance, c_address(caller).as_ptr()), 1);
+
+            // A delegated frame runs as the delegating contract.
+            set_call(instance, delegator, &[], 1);
+            let types = CString::new(r#"["trace"]"#).unwrap();
+            let replay = take_json(revm_trace_replay_transaction(
+                instance,
+                types.as_ptr(),
+                false,
+            ));
+            let action = &replay["trace"][1]["action"];
+            assert_eq!(action["callType"], "delegatecall");
+            assert_eq!(action["from"], format!("{delegator:#x}"));
+            assert_eq!(action["to"], format!("{inner:#x}"));
+            revm_free(instance);
+        }
+    }
+}
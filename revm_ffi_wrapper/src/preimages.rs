@@ -0,0 +1,274 @@
+//! KECCAK256 preimage recording, the counterpart of geth's
+//! `--cache.preimages`.
+//!
+//! [`PreimageRecorder`] watches every KECCAK256 executed and keeps the hashed
+//! memory slice under its hash.  After execution the touched addresses and
+//! storage slots are added too, since the secure trie stores them hashed.
+//! Preimages can be collected into a map, streamed to a sink as they are
+//! first seen, or both.
+
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+
+use anyhow::Result;
+use revm::{
+    bytecode::opcode::KECCAK256,
+    context_interface::result::{ExecutionResult, ResultAndState},
+    database_interface::DatabaseCommit,
+    inspector::Inspector,
+    interpreter::{
+        interpreter_types::{Jumps, MemoryTr},
+        Interpreter,
+    },
+    primitives::{keccak256, Bytes, B256, U256},
+    state::EvmState,
+};
+
+use crate::types::RevmInstance;
+use crate::RevmInstanceStateDB;
+
+/// Host callback receiving one preimage: `keccak256(data[..len]) == hash`.
+/// Both pointers are only valid for the duration of the call.
+pub type PreimageCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, hash: *const u8, data: *const u8, len: usize);
+
+/// Receiver of preimages as they are first seen.
+pub type PreimageSink<'a> = dyn FnMut(B256, &[u8]) + 'a;
+
+/// Inspector collecting the preimages of all KECCAK256 computed.
+#[derive(Default)]
+pub struct PreimageRecorder<'a> {
+    /// `(offset, size)` of the KECCAK256 being executed.
+    pending: Option<(usize, usize)>,
+    preimages: BTreeMap<B256, Bytes>,
+    sink: Option<Box<PreimageSink<'a>>>,
+}
+
+impl<'a> PreimageRecorder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also pass every new preimage to `sink` as soon as it is seen.
+    pub fn with_sink(sink: impl FnMut(B256, &[u8]) + 'a) -> Self {
+        Self {
+            sink: Some(Box::new(sink)),
+            ..Self::default()
+        }
+    }
+
+    /// Record `hash -> data`; repeats are ignored.
+    pub fn insert(&mut self, hash: B256, data: &[u8]) {
+        if self.preimages.contains_key(&hash) {
+            return;
+        }
+        if let Some(sink) = &mut self.sink {
+            sink(hash, data);
+        }
+        self.preimages.insert(hash, Bytes::copy_from_slice(data));
+    }
+
+    /// Record the preimages of the secure trie keys `state` touches:
+    /// account addresses and storage slots.
+    pub fn insert_state_keys(&mut self, state: &EvmState) {
+        for (address, account) in state {
+            self.insert(keccak256(address), address.as_slice());
+            for slot in account.storage.keys() {
+                let key = B256::from(*slot);
+                self.insert(keccak256(key), key.as_slice());
+            }
+        }
+    }
+
+    pub fn preimages(&self) -> &BTreeMap<B256, Bytes> {
+        &self.preimages
+    }
+
+    pub fn into_preimages(self) -> BTreeMap<B256, Bytes> {
+        self.preimages
+    }
+}
+
+impl<CTX> Inspector<CTX> for PreimageRecorder<'_> {
+    fn step(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        self.pending = None;
+        if interp.bytecode.opcode() != KECCAK256 {
+            return;
+        }
+        let (Ok(offset), Ok(size)) = (interp.stack.peek(0), interp.stack.peek(1)) else {
+            return;
+        };
+        // Anything larger cannot be paid for and fails the instruction.
+        if let (Ok(offset), Ok(size)) = (usize::try_from(offset), usize::try_from(size)) {
+            self.pending = Some((if size == 0 { 0 } else { offset }, size));
+        }
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let Some((offset, size)) = self.pending.take() else {
+            return;
+        };
+        if offset
+            .checked_add(size)
+            .is_none_or(|end| end > interp.memory.size())
+        {
+            return;
+        }
+        let data = interp.memory.slice_len(offset, size).to_vec();
+        let hash = keccak256(&data);
+        // The stack top holds the hash unless the instruction failed.
+        if interp.stack.peek(0) == Ok(U256::from_be_bytes(hash.0)) {
+            self.insert(hash, &data);
+        }
+    }
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction and collect the preimages of every
+    /// KECCAK256 it computed plus the trie keys it touched.  New preimages are
+    /// also passed to `sink` when given.  Commits when `commit` is set.
+    pub fn execute_with_preimages(
+        &mut self,
+        commit: bool,
+        sink: Option<&mut PreimageSink<'_>>,
+    ) -> Result<(ExecutionResult, BTreeMap<B256, Bytes>)> {
+        let recorder = match sink {
+            Some(sink) => PreimageRecorder::with_sink(sink),
+            None => PreimageRecorder::new(),
+        };
+        let (ResultAndState { result, state }, mut recorder) = self.inspect_pending(recorder)?;
+        recorder.insert_state_keys(&state);
+        if commit {
+            self.commit_state(state);
+        }
+        Ok((result, recorder.into_preimages()))
+    }
+}
+
+impl RevmInstanceStateDB {
+    /// [`RevmInstance::execute_with_preimages`] against the Go StateDB, which
+    /// receives the changes when `commit` is set.
+    pub fn execute_with_preimages(
+        &mut self,
+        commit: bool,
+        sink: Option<&mut PreimageSink<'_>>,
+    ) -> Result<(ExecutionResult, BTreeMap<B256, Bytes>)> {
+        let recorder = match sink {
+            Some(sink) => PreimageRecorder::with_sink(sink),
+            None => PreimageRecorder::new(),
+        };
+        let (ResultAndState { result, state }, mut recorder) = self.inspect_pending(recorder)?;
+        recorder.insert_state_keys(&state);
+        if commit {
+            self.evm.ctx.journaled_state.database.commit(state);
+        }
+        Ok((result, recorder.into_preimages()))
+    }
+}
+
+/// Adapt a host callback into a preimage sink.
+///
+/// # Safety
+/// `callback` must be safe to call with `user_data` for as long as the sink
+/// is alive.
+pub unsafe fn callback_sink(
+    callback: PreimageCallback,
+    user_data: *mut c_void,
+) -> impl FnMut(B256, &[u8]) {
+    move |hash, data| unsafe { callback(user_data, hash.as_ptr(), data.as_ptr(), data.len()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::{keccak256, B256, U256};
+    use std::ffi::c_void;
+
+    /// `MSTORE(0, 0xabcd); KECCAK256(30, 2); SSTORE(0, hash); STOP`
+    const RUNTIME: [u8; 13] = [
+        0x61, 0xab, 0xcd, 0x5f, 0x52, 0x60, 0x02, 0x60, 0x1e, 0x20, 0x5f, 0x55, 0x00,
+    ];
+
+    unsafe extern "C" fn collect(
+        user_data: *mut c_void,
+        hash: *const u8,
+        data: *const u8,
+        len: usize,
+    ) {
+        let seen = &mut *(user_data as *mut Vec<(B256, Vec<u8>)>);
+        let hash = B256::from_slice(std::slice::from_raw_parts(hash, 32));
+        seen.push((hash, std::slice::from_raw_parts(data, len).to_vec()));
+    }
+
+    #[test]
+    fn records_keccak_preimages() {
+        let expected = keccak256([0xab, 0xcd]);
+
+        unsafe {
+            let instance = instance_with(&RUNTIME);
+            set_call(instance, CONTRACT, &[], 0);
+
+            let mut seen = Vec::new();
+            let result = revm_execute_stream_preimages(
+                instance,
+                false,
+                collect,
+                &mut seen as *mut _ as *mut c_void,
+            );
+            assert!(!result.is_null());
+            assert!((*result).success != 0);
+            revm_free_execution_result(result);
+            assert!(seen.contains(&(expected, vec![0xab, 0xcd])));
+            assert!(seen.contains(&(keccak256(CONTRACT), CONTRACT.to_vec())));
+            assert!(seen.contains(&(keccak256(B256::ZERO), vec![0; 32])));
+
+            let value = take_json(revm_execute_with_preimages(instance, true));
+            assert_eq!(value["success"], true);
+            assert_eq!(value["preimages"][expected.to_string()], "0xabcd");
+            assert_eq!(
+                value["preimages"].as_object().unwrap().len(),
+                seen.len(),
+                "streamed and collected preimages differ"
+            );
+
+            let db = &(*instance).evm.ctx.journaled_state.database;
+            let stored = db.cache.accounts[&CONTRACT].storage[&U256::ZERO];
+            assert_eq!(stored, U256::from_be_bytes(expected.0));
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn records_statedb_preimages() {
+        let expected = keccak256([0xab, 0xcd]);
+        let host = host_with(&RUNTIME);
+
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+            statedb_set_call(instance, CONTRACT, 0);
+            let mut seen = Vec::new();
+            let result = revm_statedb_execute_stream_preimages(
+                instance,
+                false,
+                collect,
+                &mut seen as *mut _ as *mut c_void,
+            );
+            assert!(!result.is_null());
+            assert!((*result).success != 0);
+            revm_free_execution_result(result);
+            assert!(seen.contains(&(expected, vec![0xab, 0xcd])));
+            assert!(seen.contains(&(keccak256(CALLER), CALLER.to_vec())));
+            assert_eq!(host.storage(CONTRACT, U256::ZERO), U256::ZERO);
+
+            let value = take_json(revm_statedb_execute_with_preimages(instance, true));
+            assert_eq!(value["preimages"][expected.to_string()], "0xabcd");
+            // Committed through the host.
+            assert_eq!(
+                host.storage(CONTRACT, U256::ZERO),
+                U256::from_be_bytes(expected.0)
+            );
+            revm_free_statedb_instance(instance);
+        }
+    }
+}
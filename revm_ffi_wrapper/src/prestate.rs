@@ -0,0 +1,296 @@
+//! geth's `prestateTracer`.
+//!
+//! The pre-state is taken from the database before the execution is
+//! committed, for every account and storage slot the execution loaded.  In
+//! the default mode the result maps each such account to its balance,
+//! nonce, code and the slots read; with `diffMode` it is a `{"post",
+//! "pre"}` pair holding only what changed, following geth's pruning rules.
+//! Accounts and slots warmed by an access list count as loaded, as they do
+//! for REVM's journal.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use revm::{
+    context_interface::result::ExecutionResult,
+    database_interface::DatabaseRef,
+    primitives::{Address, Bytes, B256, KECCAK_EMPTY, U256},
+    state::{Account, AccountInfo, EvmState},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::types::RevmInstance;
+
+/// `prestateTracer` options, as passed in geth's `tracerConfig`.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrestateTracerConfig {
+    pub diff_mode: bool,
+    pub disable_code: bool,
+    pub disable_storage: bool,
+}
+
+/// An account as geth's `prestateTracer` prints it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PrestateAccount {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    #[serde(skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<B256, B256>,
+}
+
+fn is_zero(nonce: &u64) -> bool {
+    *nonce == 0
+}
+
+/// The tracer result: the pre-state, or the pre/post difference.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum PrestateTrace {
+    Prestate(BTreeMap<Address, PrestateAccount>),
+    Diff {
+        post: BTreeMap<Address, PrestateAccount>,
+        pre: BTreeMap<Address, PrestateAccount>,
+    },
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction and report its pre-state (or, in
+    /// diff mode, the state difference) like geth's `prestateTracer`.
+    /// Commits when `commit` is true.
+    pub fn execute_prestate_trace(
+        &mut self,
+        config: PrestateTracerConfig,
+        commit: bool,
+    ) -> Result<(ExecutionResult, PrestateTrace)> {
+        let outcome = self.replay_pending()?;
+        let trace = prestate_trace(
+            &self.evm.ctx.journaled_state.database,
+            &outcome.state,
+            config,
+        )?;
+        if commit {
+            self.commit_state(outcome.state);
+        }
+        Ok((outcome.result, trace))
+    }
+}
+
+/// The `prestateTracer` result for the uncommitted `state` of an execution
+/// on `db`.
+pub fn prestate_trace<DB: DatabaseRef>(
+    db: &DB,
+    state: &EvmState,
+    config: PrestateTracerConfig,
+) -> Result<PrestateTrace, DB::Error> {
+    let mut pre = BTreeMap::new();
+    let mut post = BTreeMap::new();
+    for (&address, account) in state {
+        let info = db.basic_ref(address)?;
+        // Contracts created by the transaction had no pre-state; in diff mode
+        // their post-state is everything they hold.
+        let created = info.is_none() && account.is_created();
+        let info = info.unwrap_or_default();
+        let code = code_of(db, &info)?;
+        let mut before = PrestateAccount {
+            balance: Some(info.balance),
+            code: (!config.disable_code && !code.is_empty()).then_some(code),
+            nonce: info.nonce,
+            storage: BTreeMap::new(),
+        };
+        if !config.disable_storage {
+            before.storage = account
+                .storage
+                .iter()
+                .map(|(slot, value)| (B256::from(*slot), B256::from(value.original_value)))
+                .collect();
+        }
+        if !config.diff_mode || account.is_selfdestructed() {
+            // Selfdestructed accounts keep their pre-state and have no post.
+            if !created {
+                pre.insert(address, before);
+            }
+            continue;
+        }
+        if let Some(after) = diff(db, &info, account, &mut before, config)? {
+            if !created {
+                pre.insert(address, before);
+            }
+            post.insert(address, after);
+        }
+    }
+    Ok(if config.diff_mode {
+        PrestateTrace::Diff { post, pre }
+    } else {
+        PrestateTrace::Prestate(pre)
+    })
+}
+
+/// The code of `info`, looked up by hash when the account was loaded
+/// without it.
+pub(crate) fn code_of<DB: DatabaseRef>(db: &DB, info: &AccountInfo) -> Result<Bytes, DB::Error> {
+    Ok(match &info.code {
+        Some(code) => code.original_bytes(),
+        None if info.code_hash == KECCAK_EMPTY => Bytes::new(),
+        None => db.code_by_hash_ref(info.code_hash)?.original_bytes(),
+    })
+}
+
+/// The post-state of `account` where it differs from `info`, or `None` when
+/// nothing changed.  Unchanged and zero slots are pruned from `before`.
+fn diff<DB: DatabaseRef>(
+    db: &DB,
+    info: &AccountInfo,
+    account: &Account,
+    before: &mut PrestateAccount,
+    config: PrestateTracerConfig,
+) -> Result<Option<PrestateAccount>, DB::Error> {
+    let mut after = PrestateAccount::default();
+    let mut modified = false;
+    if account.info.balance != info.balance {
+        modified = true;
+        after.balance = Some(account.info.balance);
+    }
+    if account.info.nonce != info.nonce {
+        modified = true;
+        after.nonce = account.info.nonce;
+    }
+    if account.info.code_hash != info.code_hash {
+        modified = true;
+        after.code = (!config.disable_code)
+            .then(|| code_of(db, &account.info))
+            .transpose()?;
+    }
+    before.storage.retain(|slot, value| {
+        let slot_value = &account.storage[&U256::from_be_bytes(slot.0)];
+        let new_value = B256::from(slot_value.present_value);
+        if new_value == *value {
+            return false;
+        }
+        modified = true;
+        if !new_value.is_zero() {
+            after.storage.insert(*slot, new_value);
+        }
+        !value.is_zero()
+    });
+    Ok(modified.then_some(after))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::U256;
+    use std::ffi::CString;
+
+    /// `SSTORE(0, SLOAD(0) + 1); SLOAD(1); STOP`
+    const COUNTER: [u8; 11] = [
+        0x5f, 0x54, 0x60, 0x01, 0x01, 0x5f, 0x55, 0x60, 0x01, 0x54, 0x00,
+    ];
+
+    fn trace(instance: *mut RevmInstance, config: &str) -> serde_json::Value {
+        let config = CString::new(config).unwrap();
+        unsafe {
+            take_json(revm_execute_prestate_trace(
+                instance,
+                config.as_ptr(),
+                false,
+            ))
+        }
+    }
+
+    #[test]
+    fn reports_prestate_and_diff() {
+        let (caller_key, contract_key) = (format!("{CALLER:#x}"), format!("{CONTRACT:#x}"));
+        let slot = |n: u64| format!("{:#x}", revm::primitives::B256::from(U256::from(n)));
+
+        unsafe {
+            let instance = instance_with(&COUNTER);
+            let db = &mut (*instance).evm.ctx.journaled_state.database;
+            db.insert_account_storage(CONTRACT, U256::ZERO, U256::from(41))
+                .unwrap();
+            db.insert_account_storage(CONTRACT, U256::from(1), U256::from(7))
+                .unwrap();
+            set_call(instance, CONTRACT, &[], 0);
+
+            let pre = trace(instance, "{}");
+            assert_eq!(
+                pre[&caller_key],
+                serde_json::json!({ "balance": "0xde0b6b3a7640000" })
+            );
+            assert_eq!(
+                pre[&contract_key]["code"],
+                format!("0x{}", hex::encode(COUNTER))
+            );
+            assert_eq!(pre[&contract_key]["balance"], "0x0");
+            assert_eq!(pre[&contract_key]["nonce"], 1);
+            assert_eq!(pre[&contract_key]["storage"][slot(0)], slot(41));
+            assert_eq!(pre[&contract_key]["storage"][slot(1)], slot(7));
+
+            let diff = trace(instance, r#"{"diffMode":true}"#);
+            assert_eq!(diff["pre"][&caller_key], pre[&caller_key]);
+            assert_eq!(diff["post"][&caller_key], serde_json::json!({ "nonce": 1 }));
+            // Only the written slot survives, and the unchanged code is
+            // only in the pre-state.
+            assert_eq!(
+                diff["pre"][&contract_key]["storage"],
+                serde_json::json!({ slot(0): slot(41) })
+            );
+            assert_eq!(
+                diff["post"][&contract_key],
+                serde_json::json!({ "storage": { slot(0): slot(42) } })
+            );
+
+            // Nothing was committed.
+            assert_eq!(revm_get_nonce(instance, c_address(CALLER).as_ptr()), 0);
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn diff_includes_created_contracts() {
+        let created = format!("{:#x}", CALLER.create(0));
+        let from = c_address(CALLER);
+        let value = CString::new("0x10").unwrap();
+        let zero = CString::new("0x0").unwrap();
+        // `SSTORE(0, 5)`, then return the one-byte runtime code `0xfe`.
+        let init = hex::decode("60055f5560fe5f5360015ff3").unwrap();
+
+        unsafe {
+            let instance = instance_with_contracts(&[]);
+            assert_eq!(
+                revm_set_tx(
+                    instance,
+                    from.as_ptr(),
+                    std::ptr::null(),
+                    value.as_ptr(),
+                    init.as_ptr(),
+                    init.len() as u32,
+                    100_000,
+                    zero.as_ptr(),
+                    0,
+                ),
+                0
+            );
+
+            assert!(trace(instance, "{}").get(&created).is_none());
+            let diff = trace(instance, r#"{"diffMode":true}"#);
+            assert!(diff["pre"].get(&created).is_none());
+            let slot = |n: u64| format!("{:#x}", revm::primitives::B256::from(U256::from(n)));
+            assert_eq!(
+                diff["post"][&created],
+                serde_json::json!({
+                    "balance": "0x10",
+                    "code": "0xfe",
+                    "nonce": 1,
+                    "storage": { slot(0): slot(5) },
+                })
+            );
+            revm_free(instance);
+        }
+    }
+}
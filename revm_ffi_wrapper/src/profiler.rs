@@ -0,0 +1,412 @@
+//! Gas and wall-time profiling.
+//!
+//! A [`GasProfiler`] attached to an instance aggregates every executed
+//! opcode by opcode, by contract (the address whose code ran) and by call
+//! stack, accumulating over all executions until it is detached.  Calls and
+//! creates are charged only their own cost: the gas forwarded to the callee
+//! is counted where the callee spends it.  Gas a frame spent outside its
+//! opcodes (precompiles, code deposit) is charged to the frame itself.
+//! Intrinsic gas and refunds are not part of any opcode and are not shown.
+//!
+//! Call stacks are rendered in the folded format of `flamegraph.pl` and
+//! `inferno`: one `frame;frame;OPCODE weight` line per stack.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::Instant;
+
+use revm::{
+    bytecode::opcode::{self, OpCode},
+    context_interface::ContextTr,
+    inspector::Inspector,
+    interpreter::{
+        interpreter_types::{InputsTr, Jumps, LoopControl},
+        CallInputs, CallOutcome, CallScheme, CreateInputs, CreateOutcome, Interpreter,
+        InterpreterResult,
+    },
+    primitives::Address,
+};
+use serde::Serialize;
+
+/// Gas added on top of the forwarded gas for calls that transfer value.
+const CALL_STIPEND: u64 = 2300;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpcodeStats {
+    pub count: u64,
+    pub gas: u64,
+    pub time_ns: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractStats {
+    /// Frames that ran the contract's code.
+    pub calls: u64,
+    pub gas: u64,
+    pub time_ns: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StackStats {
+    pub gas: u64,
+    pub time_ns: u64,
+}
+
+/// What a folded stack line is weighted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileWeight {
+    Gas,
+    Time,
+}
+
+/// Aggregated profile of the executions seen so far.
+#[derive(Clone, Debug, Default)]
+pub struct GasProfile {
+    pub transactions: u64,
+    pub opcodes: BTreeMap<&'static str, OpcodeStats>,
+    pub contracts: BTreeMap<Address, ContractStats>,
+    /// Keyed by folded stack, innermost entry last.
+    pub stacks: BTreeMap<String, StackStats>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OpcodeRow<'a> {
+    opcode: &'a str,
+    #[serde(flatten)]
+    stats: OpcodeStats,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContractRow {
+    address: Address,
+    #[serde(flatten)]
+    stats: ContractStats,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProfileReport<'a> {
+    transactions: u64,
+    gas: u64,
+    time_ns: u64,
+    opcodes: Vec<OpcodeRow<'a>>,
+    contracts: Vec<ContractRow>,
+}
+
+impl GasProfile {
+    /// The profile as JSON tables, rows sorted by gas (highest first).
+    pub fn report(&self) -> serde_json::Value {
+        let mut opcodes: Vec<_> = self
+            .opcodes
+            .iter()
+            .map(|(&opcode, &stats)| OpcodeRow { opcode, stats })
+            .collect();
+        opcodes.sort_by_key(|row| std::cmp::Reverse(row.stats.gas));
+        let mut contracts: Vec<_> = self
+            .contracts
+            .iter()
+            .map(|(&address, &stats)| ContractRow { address, stats })
+            .collect();
+        contracts.sort_by_key(|row| std::cmp::Reverse(row.stats.gas));
+        let report = ProfileReport {
+            transactions: self.transactions,
+            gas: self.stacks.values().map(|stats| stats.gas).sum(),
+            time_ns: self.stacks.values().map(|stats| stats.time_ns).sum(),
+            opcodes,
+            contracts,
+        };
+        serde_json::to_value(report).unwrap_or_default()
+    }
+
+    /// Folded stacks, one `stack weight` line each; zero weights are left
+    /// out.
+    pub fn folded(&self, weight: ProfileWeight) -> String {
+        let mut out = String::new();
+        for (stack, stats) in &self.stacks {
+            let value = match weight {
+                ProfileWeight::Gas => stats.gas,
+                ProfileWeight::Time => stats.time_ns,
+            };
+            if value > 0 {
+                let _ = writeln!(out, "{stack} {value}");
+            }
+        }
+        out
+    }
+
+    fn charge(&mut self, address: Address, stack: String, gas: u64, time_ns: u64) {
+        let contract = self.contracts.entry(address).or_default();
+        contract.gas += gas;
+        contract.time_ns += time_ns;
+        let stack = self.stacks.entry(stack).or_default();
+        stack.gas += gas;
+        stack.time_ns += time_ns;
+    }
+}
+
+struct ProfileFrame {
+    address: Address,
+    stack: String,
+    /// Free gas the frame got on top of what its caller paid.
+    stipend: u64,
+    /// Gas of the frame's own opcodes and its callees, as the frame paid it.
+    accounted: u64,
+}
+
+struct PendingStep {
+    op: u8,
+    gas: u64,
+    start: Instant,
+}
+
+/// Inspector aggregating a [`GasProfile`].
+#[derive(Default)]
+pub struct GasProfiler {
+    profile: GasProfile,
+    frames: Vec<ProfileFrame>,
+    pending: Option<PendingStep>,
+    /// The opcode last executed in the innermost frame.
+    last_op: Option<u8>,
+}
+
+impl GasProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn profile(&self) -> &GasProfile {
+        &self.profile
+    }
+
+    fn enter(&mut self, label: Option<String>, address: Address, forwarded: u64, stipend: u64) {
+        // The call opcode's cost includes the forwarded gas, which belongs
+        // to the callee.
+        if let (Some(parent), Some(op)) = (self.frames.last_mut(), self.last_op.take()) {
+            let name = op_name(op);
+            let stats = self.profile.opcodes.entry(name).or_default();
+            stats.gas = stats.gas.saturating_sub(forwarded);
+            let contract = self.profile.contracts.entry(parent.address).or_default();
+            contract.gas = contract.gas.saturating_sub(forwarded);
+            let stack = self
+                .profile
+                .stacks
+                .entry(format!("{};{name}", parent.stack))
+                .or_default();
+            stack.gas = stack.gas.saturating_sub(forwarded);
+            parent.accounted = parent.accounted.saturating_sub(forwarded);
+        }
+        let stack = match (self.frames.last(), label) {
+            (Some(parent), Some(label)) => format!("{};{label}", parent.stack),
+            (None, Some(label)) => label,
+            // Creates are labelled once their address is known.
+            (_, None) => String::new(),
+        };
+        self.frames.push(ProfileFrame {
+            address,
+            stack,
+            stipend,
+            accounted: 0,
+        });
+        if address != Address::ZERO {
+            self.profile.contracts.entry(address).or_default().calls += 1;
+        }
+    }
+
+    fn exit(&mut self, result: &InterpreterResult) {
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        let spent = result.gas.spent();
+        let unaccounted = spent.saturating_sub(frame.accounted);
+        if unaccounted > 0 && !frame.stack.is_empty() {
+            self.profile
+                .charge(frame.address, frame.stack.clone(), unaccounted, 0);
+        }
+        match self.frames.last_mut() {
+            Some(parent) => parent.accounted += spent.saturating_sub(frame.stipend),
+            None => self.profile.transactions += 1,
+        }
+        self.last_op = None;
+    }
+}
+
+fn op_name(op: u8) -> &'static str {
+    OpCode::new(op).map_or("UNKNOWN", OpCode::as_str)
+}
+
+fn call_label(address: Address, input: &[u8]) -> String {
+    match input.get(..4) {
+        Some(selector) => format!("{address:#x}:0x{}", hex::encode(selector)),
+        None => format!("{address:#x}"),
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for GasProfiler {
+    fn initialize_interp(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let parent = self
+            .frames
+            .len()
+            .checked_sub(2)
+            .map(|index| self.frames[index].stack.clone());
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        if frame.stack.is_empty() {
+            let address = interp.input.target_address();
+            let label = format!("create:{address:#x}");
+            frame.stack = match parent {
+                Some(parent) => format!("{parent};{label}"),
+                None => label,
+            };
+            frame.address = address;
+            self.profile.contracts.entry(address).or_default().calls += 1;
+        }
+    }
+
+    fn step(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        self.pending = Some(PendingStep {
+            op: interp.bytecode.opcode(),
+            gas: interp.control.gas().remaining(),
+            start: Instant::now(),
+        });
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let Some(step) = self.pending.take() else {
+            return;
+        };
+        let time_ns = step.start.elapsed().as_nanos() as u64;
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        let gas = step.gas.saturating_sub(interp.control.gas().remaining());
+        let name = op_name(step.op);
+        let stats = self.profile.opcodes.entry(name).or_default();
+        stats.count += 1;
+        stats.gas += gas;
+        stats.time_ns += time_ns;
+        frame.accounted += gas;
+        let (address, stack) = (frame.address, format!("{};{name}", frame.stack));
+        self.profile.charge(address, stack, gas, time_ns);
+        self.last_op = Some(step.op);
+    }
+
+    fn call(&mut self, context: &mut CTX, inputs: &mut CallInputs) -> Option<CallOutcome> {
+        let stipend = match inputs.scheme {
+            CallScheme::Call | CallScheme::CallCode if inputs.transfers_value() => {
+                CALL_STIPEND
+            }
+            _ => 0,
+        };
+        let input = inputs.input.bytes(context);
+        let label = call_label(inputs.bytecode_address, &input);
+        let forwarded = inputs.gas_limit.saturating_sub(stipend);
+        if !matches!(
+            self.last_op,
+            Some(opcode::CALL | opcode::CALLCODE | opcode::DELEGATECALL | opcode::STATICCALL)
+        ) {
+            self.last_op = None;
+        }
+        self.enter(Some(label), inputs.bytecode_address, forwarded, stipend);
+        None
+    }
+
+    fn call_end(&mut self, _context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.exit(&outcome.result);
+    }
+
+    fn create(&mut self, _context: &mut CTX, inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        if !matches!(self.last_op, Some(opcode::CREATE | opcode::CREATE2)) {
+            self.last_op = None;
+        }
+        self.enter(None, Address::ZERO, inputs.gas_limit, 0);
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.exit(&outcome.result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::Address;
+    use std::ffi::CStr;
+
+    /// `SSTORE(0, 1); CALL(gas, 0xbb.., 0, 0, 0, 0, 0); POP; STOP`
+    const OUTER: [u8; 34] = [
+        0x60, 0x01, 0x5f, 0x55, 0x5f, 0x5f, 0x5f, 0x5f, 0x5f, 0x73, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+        0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb, 0xbb,
+        0x5a, 0xf1, 0x50, 0x00,
+    ];
+
+    /// `SSTORE(0, 2); STOP`
+    const INNER: [u8; 5] = [0x60, 0x02, 0x5f, 0x55, 0x00];
+
+    #[test]
+    fn profiles_gas_by_opcode_contract_and_stack() {
+        let outer = CONTRACT;
+        let inner = Address::repeat_byte(0xbb);
+
+        unsafe {
+            let instance = instance_with_contracts(&[(outer, &OUTER), (inner, &INNER)]);
+            revm_start_profiling(instance);
+            let result = call(instance, outer);
+            assert_eq!((*result).success, 1);
+            let gas_used = (*result).gas_used as u64;
+            revm_free_execution_result(result);
+
+            let report = take_json(revm_profile_report(instance));
+            let folded = revm_profile_folded(instance, false);
+            assert!(!folded.is_null());
+            let text = CStr::from_ptr(folded).to_str().unwrap().to_string();
+            revm_free_string(folded);
+            revm_stop_profiling(instance);
+            assert!(revm_profile_report(instance).is_null());
+            revm_free(instance);
+
+            // Everything but the intrinsic gas is attributed exactly once.
+            assert_eq!(report["transactions"], 1);
+            assert_eq!(report["gas"], gas_used - 21_000);
+            let sstore = &report["opcodes"][0];
+            assert_eq!(sstore["opcode"], "SSTORE");
+            assert_eq!(sstore["count"], 2);
+            assert_eq!(sstore["gas"], 2 * 22_100);
+            let call = report["opcodes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|row| row["opcode"] == "CALL")
+                .unwrap();
+            // Cold account access only; the forwarded gas is the callee's.
+            assert_eq!(call["gas"], 2_600);
+            assert_eq!(report["contracts"].as_array().unwrap().len(), 2);
+            assert_eq!(report["contracts"][0]["calls"], 1);
+
+            let line = |stack: String| {
+                text.lines()
+                    .find_map(|line| line.strip_prefix(&format!("{stack} ")))
+                    .map(|weight| weight.parse::<u64>().unwrap())
+            };
+            assert_eq!(line(format!("{outer:#x};SSTORE")), Some(22_100));
+            assert_eq!(line(format!("{outer:#x};{inner:#x};SSTORE")), Some(22_100));
+            assert_eq!(line(format!("{outer:#x};CALL")), Some(2_600));
+            let total: u64 = text
+                .lines()
+                .map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap())
+                .sum();
+            assert_eq!(total, gas_used - 21_000);
+        }
+    }
+}
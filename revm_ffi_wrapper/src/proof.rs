@@ -0,0 +1,180 @@
+//! `eth_getProof`-style Merkle proofs for in-memory `RevmInstance`s.
+//!
+//! The JSON produced here has the same shape as geth's `eth_getProof`
+//! response, so existing light-client and bridge verifiers can consume it
+//! directly.  Proof elements are the RLP-encoded trie nodes from the root
+//! down to the leaf (or to where the path ends for absent keys).
+
+use revm::{
+    database_interface::DatabaseRef,
+    primitives::{Address, Bytes, KECCAK_EMPTY, U256},
+};
+use serde::Serialize;
+
+use crate::types::RevmInstance;
+use crate::utils::{address_to_hex, bytes_to_hex, u256_to_hex};
+
+/// Storage slot proof as returned by `eth_getProof`.
+#[derive(Clone, Debug, Serialize)]
+pub struct StorageProof {
+    pub key: String,
+    pub value: String,
+    pub proof: Vec<String>,
+}
+
+/// Account proof as returned by `eth_getProof`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProof {
+    pub address: String,
+    pub account_proof: Vec<String>,
+    pub balance: String,
+    pub code_hash: String,
+    pub nonce: String,
+    pub storage_hash: String,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+fn proof_to_hex(proof: Vec<Bytes>) -> Vec<String> {
+    proof.iter().map(|node| bytes_to_hex(node)).collect()
+}
+
+impl RevmInstance {
+    /// Build the account proof for `address` and storage proofs for `slots`.
+    ///
+    /// `keys` are echoed back verbatim in the `key` fields, as geth does.
+    pub fn get_proof(&mut self, address: Address, slots: &[U256], keys: &[String]) -> AccountProof {
+        let prune_empty = self.prune_empty_accounts();
+        let storage_hash = self.storage_root(address);
+        self.state_trie();
+        let db = &self.evm.ctx.journaled_state.database;
+        let trie = self.state_trie.as_mut().expect("built above");
+        let (account_proof, storage_proofs) = trie.proof(db, prune_empty, address, slots);
+
+        let Ok(info) = db.basic_ref(address);
+        let info = info.unwrap_or_default();
+        let code_hash = if info.code_hash.is_zero() { KECCAK_EMPTY } else { info.code_hash };
+
+        let storage_proof = slots
+            .iter()
+            .zip(keys)
+            .zip(storage_proofs)
+            .map(|((slot, key), proof)| {
+                let Ok(value) = db.storage_ref(address, *slot);
+                StorageProof {
+                    key: key.clone(),
+                    value: u256_to_hex(value),
+                    proof: proof_to_hex(proof),
+                }
+            })
+            .collect();
+
+        AccountProof {
+            address: address_to_hex(address),
+            account_proof: proof_to_hex(account_proof),
+            balance: u256_to_hex(info.balance),
+            code_hash: format!("{:#x}", code_hash),
+            nonce: format!("0x{:x}", info.nonce),
+            storage_hash: format!("{:#x}", storage_hash),
+            storage_proof,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state_root::{encode_account, encode_storage_value, storage_key};
+    use crate::*;
+    use alloy_trie::{proof::verify_proof, Nibbles};
+    use revm::primitives::{keccak256, Address, Bytes, B256, U256};
+    use revm::state::AccountInfo;
+    use std::ffi::{CStr, CString};
+
+    fn decode_proof(values: &serde_json::Value) -> Vec<Bytes> {
+        values
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node.as_str().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn proofs_verify_against_state_root() {
+        let contract = Address::repeat_byte(0x33);
+        let contract_c = CString::new(format!("{contract:#x}")).unwrap();
+
+        unsafe {
+            let instance = revm_new();
+            for i in 1..50u64 {
+                let addr = CString::new(format!("{:#x}", Address::with_last_byte(i as u8))).unwrap();
+                let balance = CString::new(format!("{:#x}", i * 1000)).unwrap();
+                revm_set_balance(instance, addr.as_ptr(), balance.as_ptr());
+            }
+            revm_set_balance(instance, contract_c.as_ptr(), CString::new("0x7").unwrap().as_ptr());
+            for slot in 0..20u64 {
+                let slot_c = CString::new(format!("{slot:#x}")).unwrap();
+                let value_c = CString::new(format!("{:#x}", slot + 100)).unwrap();
+                revm_set_storage(instance, contract_c.as_ptr(), slot_c.as_ptr(), value_c.as_ptr());
+            }
+
+            let root_ptr = revm_state_root(instance);
+            let root: B256 = CStr::from_ptr(root_ptr).to_str().unwrap().parse().unwrap();
+            revm_free_string(root_ptr);
+
+            let present = CString::new("0x5").unwrap();
+            let absent = CString::new("0x0000000000000000000000000000000000000000000000000000000000000063").unwrap();
+            let keys = [present.as_ptr(), absent.as_ptr()];
+            let json_ptr = revm_get_proof(instance, contract_c.as_ptr(), keys.as_ptr(), 2);
+            assert!(!json_ptr.is_null());
+            let json: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(json_ptr).to_str().unwrap()).unwrap();
+            revm_free_string(json_ptr);
+
+            let storage_hash: B256 = json["storageHash"].as_str().unwrap().parse().unwrap();
+            let info = AccountInfo { balance: U256::from(7), ..Default::default() };
+            verify_proof(
+                root,
+                Nibbles::unpack(keccak256(contract)),
+                Some(encode_account(&info, storage_hash)),
+                &decode_proof(&json["accountProof"]),
+            )
+            .expect("account proof verifies");
+            assert_eq!(json["balance"], "0x7");
+            assert_eq!(json["nonce"], "0x0");
+
+            let storage = json["storageProof"].as_array().unwrap();
+            assert_eq!(storage[0]["key"], "0x5");
+            assert_eq!(storage[0]["value"], "0x69");
+            verify_proof(
+                storage_hash,
+                Nibbles::unpack(storage_key(U256::from(5))),
+                Some(encode_storage_value(U256::from(0x69))),
+                &decode_proof(&storage[0]["proof"]),
+            )
+            .expect("storage proof verifies");
+
+            assert_eq!(storage[1]["value"], "0x0");
+            verify_proof(
+                storage_hash,
+                Nibbles::unpack(storage_key(U256::from(0x63))),
+                None,
+                &decode_proof(&storage[1]["proof"]),
+            )
+            .expect("exclusion proof verifies");
+
+            // Absent account: exclusion proof against the same root.
+            let nobody = Address::repeat_byte(0xee);
+            let nobody_c = CString::new(format!("{nobody:#x}")).unwrap();
+            let json_ptr = revm_get_proof(instance, nobody_c.as_ptr(), std::ptr::null(), 0);
+            let json: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(json_ptr).to_str().unwrap()).unwrap();
+            revm_free_string(json_ptr);
+            verify_proof(root, Nibbles::unpack(keccak256(nobody)), None, &decode_proof(&json["accountProof"]))
+                .expect("account exclusion proof verifies");
+            assert_eq!(json["storageHash"], format!("{:#x}", EMPTY_ROOT_HASH));
+
+            revm_free(instance);
+        }
+    }
+}
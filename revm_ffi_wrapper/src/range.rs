@@ -0,0 +1,275 @@
+//! Paged enumeration of accounts and storage for in-memory `RevmInstance`s.
+//!
+//! Both iterators walk keys in trie order (by `keccak256` of the address or
+//! slot), like geth's `debug_accountRange` and `debug_storageRangeAt`.  A page
+//! starts at the given hashed key (inclusive) and reports the hashed key of
+//! the first entry of the following page, if any, as the cursor to resume
+//! from.  A page size of 0 means geth's default of 256, which also caps
+//! account pages.
+//!
+//! Account pages are read from the hashed-key index kept alongside the state
+//! trie, so a page costs its own size.  Storage pages re-sort the account's
+//! slots on every call, so iterating a large storage fully is quadratic in
+//! the number of pages.
+
+use std::collections::BTreeMap;
+
+use revm::{
+    database_interface::DatabaseRef,
+    primitives::{Address, B256, U256},
+};
+use serde::Serialize;
+
+use crate::fork::flatten_account;
+use crate::state_root::storage_key;
+use crate::types::RevmInstance;
+use crate::utils::{address_to_hex, bytes_to_hex};
+
+/// One account in an account range, shaped like geth's `DumpAccount`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeAccount {
+    /// Balance as a decimal string, as in geth's state dump.
+    pub balance: String,
+    pub nonce: u64,
+    pub root: String,
+    pub code_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage: Option<BTreeMap<String, String>>,
+    pub address: String,
+    pub key: String,
+}
+
+/// A page of accounts.
+#[derive(Clone, Debug, Serialize)]
+pub struct AccountRange {
+    pub root: String,
+    pub accounts: BTreeMap<String, RangeAccount>,
+    /// Hashed key to pass as `start` for the next page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+/// A storage entry keyed by hashed slot, as in `debug_storageRangeAt`.
+#[derive(Clone, Debug, Serialize)]
+pub struct StorageEntry {
+    pub key: String,
+    pub value: String,
+}
+
+/// A page of an account's storage.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageRange {
+    pub storage: BTreeMap<String, StorageEntry>,
+    /// Hashed key to pass as `start` for the next page, `null` at the end.
+    pub next_key: Option<String>,
+}
+
+/// geth's `AccountRangeMaxResults`: the largest account page and the default
+/// page size.
+pub(crate) const MAX_RANGE_RESULTS: usize = 256;
+
+fn word_to_hex(value: U256) -> String {
+    format!("{:#x}", B256::from(value))
+}
+
+/// Take up to `max` entries (256 if 0) starting at `start`, returning the
+/// next cursor.
+fn page<V>(map: &BTreeMap<B256, V>, start: B256, max: usize) -> (Vec<(&B256, &V)>, Option<B256>) {
+    let max = if max == 0 { MAX_RANGE_RESULTS } else { max };
+    let mut iter = map.range(start..);
+    let entries: Vec<_> = iter.by_ref().take(max).collect();
+    let next = iter.next().map(|(key, _)| *key);
+    (entries, next)
+}
+
+impl RevmInstance {
+    /// List up to `max_results` accounts starting at hashed key `start`.
+    pub fn account_range(
+        &mut self,
+        start: B256,
+        max_results: usize,
+        include_code: bool,
+        include_storage: bool,
+    ) -> AccountRange {
+        let root = self.state_root();
+        let prune_empty = self.prune_empty_accounts();
+        let db = &self.evm.ctx.journaled_state.database;
+        let trie = self.state_trie.as_mut().expect("built by state_root");
+
+        let (entries, next) = page(trie.account_keys(), start, max_results.min(MAX_RANGE_RESULTS));
+        let entries: Vec<(B256, Address)> =
+            entries.into_iter().map(|(key, address)| (*key, *address)).collect();
+
+        let accounts = entries
+            .iter()
+            .map(|(key, address)| {
+                let acc = flatten_account(db, *address).unwrap_or_default();
+                let storage_root = trie.storage_root(db, prune_empty, *address);
+                let code = include_code.then(|| {
+                    let Ok(code) = db.code_by_hash_ref(acc.info.code_hash);
+                    bytes_to_hex(code.original_byte_slice())
+                });
+                let storage = include_storage.then(|| {
+                    acc.storage
+                        .iter()
+                        .map(|(slot, value)| (word_to_hex(*slot), word_to_hex(*value)))
+                        .collect()
+                });
+                let entry = RangeAccount {
+                    balance: acc.info.balance.to_string(),
+                    nonce: acc.info.nonce,
+                    root: format!("{:#x}", storage_root),
+                    code_hash: format!("{:#x}", acc.info.code_hash),
+                    code,
+                    storage,
+                    address: address_to_hex(*address),
+                    key: format!("{:#x}", key),
+                };
+                (address_to_hex(*address), entry)
+            })
+            .collect();
+
+        AccountRange {
+            root: format!("{:#x}", root),
+            accounts,
+            next: next.map(|key| format!("{:#x}", key)),
+        }
+    }
+
+    /// List up to `max_results` storage slots of `address` starting at hashed
+    /// key `start`.
+    pub fn storage_range(&self, address: Address, start: B256, max_results: usize) -> StorageRange {
+        let db = &self.evm.ctx.journaled_state.database;
+        let slots: BTreeMap<B256, (U256, U256)> = flatten_account(db, address)
+            .map(|acc| acc.storage)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(slot, value)| (storage_key(slot), (slot, value)))
+            .collect();
+        let (entries, next) = page(&slots, start, max_results);
+
+        StorageRange {
+            storage: entries
+                .into_iter()
+                .map(|(hashed, (slot, value))| {
+                    let entry = StorageEntry { key: word_to_hex(*slot), value: word_to_hex(*value) };
+                    (format!("{:#x}", hashed), entry)
+                })
+                .collect(),
+            next_key: next.map(|key| format!("{:#x}", key)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use std::collections::BTreeSet;
+    use std::ffi::CString;
+
+    #[test]
+    fn pages_cover_all_accounts_once() {
+        unsafe {
+            let instance = revm_new();
+            for i in 1..=25u8 {
+                let addr = CString::new(format!("0x{:040x}", i)).unwrap();
+                let balance = CString::new(format!("{:#x}", i)).unwrap();
+                revm_set_balance(instance, addr.as_ptr(), balance.as_ptr());
+            }
+
+            let mut seen = BTreeSet::new();
+            let mut cursor: Option<CString> = None;
+            let mut pages = 0;
+            loop {
+                let start = cursor.as_ref().map_or(std::ptr::null(), |c| c.as_ptr());
+                let page = take_json(revm_account_range(instance, start, 10, false, false));
+                pages += 1;
+                let mut keys = Vec::new();
+                for (address, account) in page["accounts"].as_object().unwrap() {
+                    assert!(seen.insert(address.clone()), "account listed twice");
+                    keys.push(account["key"].as_str().unwrap().to_string());
+                }
+                match page["next"].as_str() {
+                    Some(next) => {
+                        assert!(keys.iter().all(|k| k.as_str() < next));
+                        cursor = Some(CString::new(next).unwrap());
+                    }
+                    None => break,
+                }
+            }
+            assert_eq!(pages, 3);
+            assert_eq!(seen.len(), 25);
+
+            // A page size of 0 falls back to geth's default instead of
+            // returning an empty page that points at itself.
+            let page = take_json(revm_account_range(
+                instance,
+                std::ptr::null(),
+                0,
+                false,
+                false,
+            ));
+            assert_eq!(page["accounts"].as_object().unwrap().len(), 25);
+            assert!(page.get("next").is_none());
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn storage_range_returns_preimages() {
+        let contract = CString::new("0x3333333333333333333333333333333333333333").unwrap();
+        unsafe {
+            let instance = revm_new();
+            revm_set_balance(instance, contract.as_ptr(), CString::new("0x1").unwrap().as_ptr());
+            for slot in 0..5u64 {
+                let slot_c = CString::new(format!("{slot:#x}")).unwrap();
+                let value_c = CString::new(format!("{:#x}", slot + 1)).unwrap();
+                revm_set_storage(instance, contract.as_ptr(), slot_c.as_ptr(), value_c.as_ptr());
+            }
+
+            let first = take_json(revm_storage_range(
+                instance,
+                contract.as_ptr(),
+                std::ptr::null(),
+                3,
+            ));
+            assert_eq!(first["storage"].as_object().unwrap().len(), 3);
+            let next = CString::new(first["nextKey"].as_str().unwrap()).unwrap();
+
+            let second = take_json(revm_storage_range(
+                instance,
+                contract.as_ptr(),
+                next.as_ptr(),
+                3,
+            ));
+            let storage = second["storage"].as_object().unwrap();
+            assert_eq!(storage.len(), 2);
+            assert!(second["nextKey"].is_null());
+
+            let mut slots: Vec<String> = first["storage"]
+                .as_object()
+                .unwrap()
+                .values()
+                .chain(storage.values())
+                .map(|entry| entry["key"].as_str().unwrap().to_string())
+                .collect();
+            slots.sort();
+            let expected: Vec<String> = (0..5u64).map(|s| format!("0x{:064x}", s)).collect();
+            assert_eq!(slots, expected);
+
+            let all = take_json(revm_storage_range(
+                instance,
+                contract.as_ptr(),
+                std::ptr::null(),
+                0,
+            ));
+            assert_eq!(all["storage"].as_object().unwrap().len(), 5);
+            revm_free(instance);
+        }
+    }
+}
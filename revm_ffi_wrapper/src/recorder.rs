@@ -0,0 +1,719 @@
+//! Record-and-replay of the traffic between REVM and the Go StateDB.
+//!
+//! While a recorder is attached to a `GoDatabase`, every `re_state_*`
+//! request and its response, every write, and the config, block and tx env
+//! of each execution are appended to a file, one JSON object per line.  A
+//! [`Recording`] loaded from that file re-executes every transaction against
+//! the recorded responses alone, so a trace captured on a production node
+//! reproduces under `cargo test` with no Go process present:
+//!
+//! ```no_run
+//! let outcomes = revm_ffi::Recording::load("bug-report.jsonl")?.replay()?;
+//! assert!(outcomes.iter().all(|o| o.matches()), "{outcomes:#?}");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use revm::{
+    bytecode::Bytecode,
+    context::{BlockEnv, CfgEnv, TxEnv},
+    context_interface::result::ExecutionResult,
+    database_interface::{Database, DatabaseRef},
+    handler::ExecuteEvm,
+    primitives::{Address, Bytes, HashMap, B256, KECCAK_EMPTY, U256},
+    state::{AccountInfo, EvmState},
+    Context, Journal, MainBuilder,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::go_db::GoDBError;
+
+/// One line of a recording.  Responses keep the error string when the Go
+/// side failed, so failures replay too.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecordEntry {
+    Config {
+        cfg: CfgEnv,
+    },
+    /// Start of an execution; the entries up to the next `execute` belong
+    /// to it.
+    Execute {
+        block: BlockEnv,
+        tx: Box<TxEnv>,
+    },
+    Basic {
+        address: Address,
+        response: Result<Option<AccountInfo>, String>,
+    },
+    Storage {
+        address: Address,
+        slot: U256,
+        response: Result<U256, String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Code {
+        code_hash: B256,
+        response: Result<Bytes, String>,
+    },
+    BlockHash {
+        number: u64,
+        response: Result<B256, String>,
+    },
+    /// Outcome of the execution, or the error that prevented it.
+    Result {
+        response: Result<ExecutionResult, String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SetBasic {
+        address: Address,
+        balance: U256,
+        nonce: u64,
+        code_hash: B256,
+    },
+    SetStorage {
+        address: Address,
+        slot: U256,
+        value: U256,
+    },
+}
+
+/// Buffered output of a [`Recorder`].
+#[derive(Debug)]
+struct Output {
+    file: BufWriter<File>,
+    /// First failed write; nothing is written after it.
+    error: Option<std::io::Error>,
+}
+
+/// Appends [`RecordEntry`] lines to a file.  Clones share the file.  Entries
+/// are buffered and reach the file on [`Recorder::finish`] or when the last
+/// clone is dropped.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    out: Arc<Mutex<Output>>,
+}
+
+impl Recorder {
+    /// Create (or truncate) the recording at `path`.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let out = Output {
+            file: BufWriter::new(File::create(path)?),
+            error: None,
+        };
+        Ok(Self {
+            out: Arc::new(Mutex::new(out)),
+        })
+    }
+
+    /// Append one entry.  Recording never fails the execution being
+    /// recorded: a failed write stops the recording and is returned by
+    /// [`Recorder::finish`].
+    pub fn record(&self, entry: &RecordEntry) {
+        let mut out = self.out.lock().unwrap();
+        if out.error.is_some() {
+            return;
+        }
+        let written = serde_json::to_writer(&mut out.file, entry)
+            .map_err(std::io::Error::from)
+            .and_then(|()| out.file.write_all(b"\n"));
+        if let Err(e) = written {
+            out.error = Some(e);
+        }
+    }
+
+    /// Flush the buffered entries, or return the first write that failed.
+    pub fn finish(&self) -> std::io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        match out.error.take() {
+            Some(e) => Err(e),
+            None => out.file.flush(),
+        }
+    }
+
+    /// Record the writes `GoDatabase::commit` forwards for `changes`.
+    pub fn record_commit(&self, changes: &EvmState) {
+        for entry in commit_entries(changes) {
+            self.record(&entry);
+        }
+    }
+}
+
+/// The `set_basic`/`set_storage` calls a commit of `changes` makes.
+fn commit_entries(changes: &EvmState) -> Vec<RecordEntry> {
+    let mut entries = Vec::new();
+    for (address, account) in changes {
+        entries.push(RecordEntry::SetBasic {
+            address: *address,
+            balance: account.info.balance,
+            nonce: account.info.nonce,
+            code_hash: account.info.code_hash,
+        });
+        for (slot, value) in account.changed_storage_slots() {
+            entries.push(RecordEntry::SetStorage {
+                address: *address,
+                slot: *slot,
+                value: value.present_value(),
+            });
+        }
+    }
+    entries
+}
+
+/// Account and storage writes of one execution, order-independent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateWrites {
+    pub accounts: BTreeMap<Address, (U256, u64, B256)>,
+    pub storage: BTreeMap<(Address, U256), U256>,
+}
+
+impl StateWrites {
+    fn push(&mut self, entry: &RecordEntry) {
+        match *entry {
+            RecordEntry::SetBasic {
+                address,
+                balance,
+                nonce,
+                code_hash,
+            } => {
+                self.accounts.insert(address, (balance, nonce, code_hash));
+            }
+            RecordEntry::SetStorage {
+                address,
+                slot,
+                value,
+            } => {
+                self.storage.insert((address, slot), value);
+            }
+            _ => {}
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.accounts.is_empty() && self.storage.is_empty()
+    }
+}
+
+/// Recorded state served to a replayed execution.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayDatabase {
+    basic: HashMap<Address, Result<Option<AccountInfo>, String>>,
+    storage: HashMap<(Address, U256), Result<U256, String>>,
+    code: HashMap<B256, Result<Bytes, String>>,
+    block_hashes: HashMap<u64, Result<B256, String>>,
+}
+
+impl ReplayDatabase {
+    /// Fold a response or write into the state.
+    fn apply(&mut self, entry: &RecordEntry) {
+        match entry {
+            RecordEntry::Basic { address, response } => {
+                self.basic.insert(*address, response.clone());
+            }
+            RecordEntry::Storage {
+                address,
+                slot,
+                response,
+            } => {
+                self.storage.insert((*address, *slot), response.clone());
+            }
+            RecordEntry::Code {
+                code_hash,
+                response,
+            } => {
+                self.code.insert(*code_hash, response.clone());
+            }
+            RecordEntry::BlockHash { number, response } => {
+                self.block_hashes.insert(*number, response.clone());
+            }
+            RecordEntry::SetBasic {
+                address,
+                balance,
+                nonce,
+                code_hash,
+            } => {
+                let info = AccountInfo {
+                    balance: *balance,
+                    nonce: *nonce,
+                    code_hash: *code_hash,
+                    code: None,
+                };
+                self.basic.insert(*address, Ok(Some(info)));
+            }
+            RecordEntry::SetStorage {
+                address,
+                slot,
+                value,
+            } => {
+                self.storage.insert((*address, *slot), Ok(*value));
+            }
+            RecordEntry::Config { .. }
+            | RecordEntry::Execute { .. }
+            | RecordEntry::Result { .. } => {}
+        }
+    }
+}
+
+fn missing(what: String) -> GoDBError {
+    GoDBError(format!("{what} was not recorded"))
+}
+
+impl DatabaseRef for ReplayDatabase {
+    type Error = GoDBError;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        match self.basic.get(&address) {
+            Some(response) => response.clone().map_err(GoDBError),
+            None => Err(missing(format!("account {address}"))),
+        }
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        if code_hash == KECCAK_EMPTY {
+            return Ok(Bytecode::new());
+        }
+        match self.code.get(&code_hash) {
+            Some(Ok(code)) if code.is_empty() => Ok(Bytecode::new()),
+            Some(Ok(code)) => Ok(Bytecode::new_raw(code.clone())),
+            Some(Err(e)) => Err(GoDBError(e.clone())),
+            None => Err(missing(format!("code {code_hash}"))),
+        }
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        match self.storage.get(&(address, index)) {
+            Some(response) => response.clone().map_err(GoDBError),
+            None => Err(missing(format!("slot {index:#x} of {address}"))),
+        }
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        match self.block_hashes.get(&number) {
+            Some(response) => response.clone().map_err(GoDBError),
+            None => Err(missing(format!("hash of block {number}"))),
+        }
+    }
+}
+
+impl Database for ReplayDatabase {
+    type Error = GoDBError;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.basic_ref(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.code_by_hash_ref(code_hash)
+    }
+
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.storage_ref(address, index)
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<B256, Self::Error> {
+        self.block_hash_ref(number)
+    }
+}
+
+type ReplayContext = Context<BlockEnv, TxEnv, CfgEnv, ReplayDatabase, Journal<ReplayDatabase>, ()>;
+
+/// A replayed execution next to what was recorded for it.
+#[derive(Clone, Debug)]
+pub struct ReplayOutcome {
+    /// Position of the execution in the recording, from 0.
+    pub index: usize,
+    pub expected: Option<Result<ExecutionResult, String>>,
+    pub actual: Result<ExecutionResult, String>,
+    pub expected_writes: StateWrites,
+    pub actual_writes: StateWrites,
+}
+
+impl ReplayOutcome {
+    /// True when the result matches and, if the execution was committed,
+    /// so do the writes.
+    pub fn matches(&self) -> bool {
+        self.expected.as_ref().is_none_or(|e| *e == self.actual)
+            && (self.expected_writes.is_empty() || self.expected_writes == self.actual_writes)
+    }
+}
+
+/// A recording loaded back from disk.
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub entries: Vec<RecordEntry>,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let mut entries = Vec::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = serde_json::from_str(&line)
+                .with_context(|| format!("{}:{}", path.display(), i + 1))?;
+            entries.push(entry);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Re-execute every recorded transaction against the recorded state.
+    ///
+    /// An execution sees the responses recorded after its `execute` entry,
+    /// falling back to earlier responses updated by earlier writes.
+    pub fn replay(&self) -> Result<Vec<ReplayOutcome>> {
+        let mut known = ReplayDatabase::default();
+        let mut cfg = None;
+        let mut outcomes = Vec::new();
+
+        let mut i = 0;
+        while i < self.entries.len() {
+            let (block, tx) = match &self.entries[i] {
+                RecordEntry::Config { cfg: c } => {
+                    cfg = Some(c.clone());
+                    i += 1;
+                    continue;
+                }
+                RecordEntry::Execute { block, tx } => (block, tx),
+                other => {
+                    known.apply(other);
+                    i += 1;
+                    continue;
+                }
+            };
+            let Some(cfg) = cfg.clone() else {
+                bail!("execution {} has no config entry before it", outcomes.len());
+            };
+            let end = self.entries[i + 1..]
+                .iter()
+                .position(|e| matches!(e, RecordEntry::Execute { .. } | RecordEntry::Config { .. }))
+                .map_or(self.entries.len(), |p| i + 1 + p);
+            let segment = &self.entries[i + 1..end];
+
+            let mut expected = None;
+            let mut expected_writes = StateWrites::default();
+            for entry in segment {
+                match entry {
+                    RecordEntry::Result { response } => expected = Some(response.clone()),
+                    RecordEntry::SetBasic { .. } | RecordEntry::SetStorage { .. } => {
+                        expected_writes.push(entry)
+                    }
+                    _ => {}
+                }
+            }
+            // Apply reads in reverse so the first response the execution saw
+            // for a key wins; its own writes come after it finished.
+            let mut db = known.clone();
+            for entry in segment.iter().rev() {
+                if !matches!(
+                    entry,
+                    RecordEntry::SetBasic { .. } | RecordEntry::SetStorage { .. }
+                ) {
+                    db.apply(entry);
+                }
+            }
+
+            let mut evm = ReplayContext::new(db, cfg.spec)
+                .with_cfg(cfg)
+                .with_block(block.clone())
+                .with_tx((**tx).clone())
+                .build_mainnet();
+            let (actual, actual_writes) = match evm.replay() {
+                Ok(result) => {
+                    let mut writes = StateWrites::default();
+                    commit_entries(&result.state)
+                        .iter()
+                        .for_each(|e| writes.push(e));
+                    (Ok(result.result), writes)
+                }
+                Err(e) => (Err(e.to_string()), StateWrites::default()),
+            };
+            outcomes.push(ReplayOutcome {
+                index: outcomes.len(),
+                expected,
+                actual,
+                expected_writes,
+                actual_writes,
+            });
+
+            segment.iter().for_each(|e| known.apply(e));
+            i = end;
+        }
+
+        if outcomes.is_empty() && !self.entries.is_empty() {
+            return Err(anyhow!("recording contains no executions"));
+        }
+        Ok(outcomes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::go_db::GoDatabase;
+    use crate::test_support::*;
+    use crate::*;
+    use revm::database_interface::DatabaseCommit;
+    use revm::primitives::{hardfork::SpecId, keccak256, TxKind};
+    use revm::state::{Account, EvmStorageSlot};
+    use std::ffi::CString;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "revm-recording-{name}-{}.jsonl",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn records_go_traffic() {
+        let path = temp_path("traffic");
+        let address = Address::repeat_byte(0x11);
+        let host = MockHost::new();
+        host.insert_account(
+            address,
+            AccountInfo {
+                nonce: 42,
+                code_hash: B256::ZERO,
+                ..Default::default()
+            },
+        );
+        host.set_storage(address, U256::from(3), U256::from_be_bytes([1u8; 32]));
+        host.set_code(
+            B256::repeat_byte(0xcc),
+            Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]),
+        );
+        host.set_block_hash(7, B256::repeat_byte(2));
+        let mut db = GoDatabase::new(host.handle());
+        db.set_recorder(Some(Recorder::create(&path).unwrap()));
+
+        db.basic_ref(address).unwrap();
+        db.storage_ref(address, U256::from(3)).unwrap();
+        db.code_by_hash_ref(B256::repeat_byte(0xcc)).unwrap();
+        db.block_hash_ref(7).unwrap();
+        let mut account = Account::default();
+        account.info.nonce = 5;
+        account.storage.insert(
+            U256::from(3),
+            EvmStorageSlot::new_changed(U256::ZERO, U256::from(9)),
+        );
+        db.commit([(address, account)].into_iter().collect());
+        db.set_recorder(None);
+
+        let recording = Recording::load(&path).unwrap();
+        let info = AccountInfo {
+            nonce: 42,
+            code_hash: B256::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(
+            recording.entries,
+            vec![
+                RecordEntry::Basic {
+                    address,
+                    response: Ok(Some(info)),
+                },
+                RecordEntry::Storage {
+                    address,
+                    slot: U256::from(3),
+                    response: Ok(U256::from_be_bytes([1u8; 32])),
+                },
+                RecordEntry::Code {
+                    code_hash: B256::repeat_byte(0xcc),
+                    response: Ok(Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef])),
+                },
+                RecordEntry::BlockHash {
+                    number: 7,
+                    response: Ok(B256::repeat_byte(2)),
+                },
+                RecordEntry::SetBasic {
+                    address,
+                    balance: U256::ZERO,
+                    nonce: 5,
+                    code_hash: KECCAK_EMPTY,
+                },
+                RecordEntry::SetStorage {
+                    address,
+                    slot: U256::from(3),
+                    value: U256::from(9),
+                },
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replays_recorded_executions() {
+        // `SSTORE(0, 0x2a); STOP`
+        let runtime = Bytes::from_static(&[0x60, 0x2a, 0x60, 0x00, 0x55, 0x00]);
+        let caller = Address::repeat_byte(0xaa);
+        let contract = Address::repeat_byte(0xcc);
+        let execute = |nonce| RecordEntry::Execute {
+            block: BlockEnv::default(),
+            tx: Box::new(TxEnv {
+                caller,
+                kind: TxKind::Call(contract),
+                gas_limit: 100_000,
+                gas_price: 0,
+                nonce,
+                chain_id: Some(56),
+                ..Default::default()
+            }),
+        };
+        let mut cfg = CfgEnv::new_with_spec(SpecId::CANCUN);
+        cfg.chain_id = 56;
+
+        let mut entries = vec![
+            RecordEntry::Config { cfg },
+            execute(0),
+            RecordEntry::Basic {
+                address: caller,
+                response: Ok(Some(AccountInfo::from_balance(U256::from(10u64.pow(18))))),
+            },
+            RecordEntry::Basic {
+                address: contract,
+                response: Ok(Some(AccountInfo {
+                    code_hash: keccak256(&runtime),
+                    code: None,
+                    ..Default::default()
+                })),
+            },
+            RecordEntry::Code {
+                code_hash: keccak256(&runtime),
+                response: Ok(runtime.clone()),
+            },
+            RecordEntry::Storage {
+                address: contract,
+                slot: U256::ZERO,
+                response: Ok(U256::ZERO),
+            },
+            RecordEntry::Basic {
+                address: Address::ZERO,
+                response: Ok(None),
+            },
+        ];
+        let first = Recording {
+            entries: entries.clone(),
+        }
+        .replay()
+        .unwrap()
+        .remove(0);
+        let result = first.actual.clone().unwrap();
+        assert!(result.is_success());
+        assert_eq!(result.gas_used(), 21_000 + 3 + 3 + 22_100);
+        assert_eq!(
+            first.actual_writes.storage,
+            [((contract, U256::ZERO), U256::from(0x2a))].into()
+        );
+
+        // What GoDatabase would have recorded for a committed execution,
+        // followed by a second one served from the writes alone.
+        entries.push(RecordEntry::Result {
+            response: Ok(result),
+        });
+        entries.push(RecordEntry::SetBasic {
+            address: caller,
+            balance: U256::from(10u64.pow(18)),
+            nonce: 1,
+            code_hash: KECCAK_EMPTY,
+        });
+        entries.push(RecordEntry::SetBasic {
+            address: contract,
+            balance: U256::ZERO,
+            nonce: 0,
+            code_hash: keccak256(&runtime),
+        });
+        entries.push(RecordEntry::SetBasic {
+            address: Address::ZERO,
+            balance: U256::ZERO,
+            nonce: 0,
+            code_hash: KECCAK_EMPTY,
+        });
+        entries.push(RecordEntry::SetStorage {
+            address: contract,
+            slot: U256::ZERO,
+            value: U256::from(0x2a),
+        });
+        entries.push(execute(1));
+
+        let path = temp_path("replay");
+        let recorder = Recorder::create(&path).unwrap();
+        entries.iter().for_each(|e| recorder.record(e));
+        recorder.finish().unwrap();
+        let outcomes = Recording::load(&path).unwrap().replay().unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[0].matches(), "{:?}", outcomes[0]);
+        let second = outcomes[1].actual.as_ref().unwrap();
+        assert_eq!(second.gas_used(), 21_000 + 3 + 3 + 2_100 + 100);
+
+        // A divergent write is caught.
+        let RecordEntry::SetStorage { value, .. } = &mut entries[11] else {
+            panic!("expected the storage write");
+        };
+        *value = U256::from(0x2b);
+        let outcomes = Recording { entries }.replay().unwrap();
+        assert!(!outcomes[0].matches());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn records_statedb_instance() {
+        let path = temp_path("statedb");
+        let path_c = CString::new(path.to_str().unwrap()).unwrap();
+        let (from, to) = (c_address(CALLER), c_address(CONTRACT));
+
+        // The caller carries code, so the call is rejected.
+        let host = MockHost::new();
+        host.insert_account(CALLER, contract(&[0x00]));
+
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+            assert_eq!(revm_statedb_start_recording(instance, path_c.as_ptr()), 0);
+            let result = revm_call_contract_statedb(
+                instance,
+                from.as_ptr(),
+                to.as_ptr(),
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+                100_000,
+            );
+            assert!(result.is_null());
+            assert_eq!(revm_statedb_stop_recording(instance), 0);
+            revm_free_statedb_instance(instance);
+        }
+
+        let recording = Recording::load(&path).unwrap();
+        assert!(matches!(recording.entries[0], RecordEntry::Config { .. }));
+        let outcomes = recording.replay().unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert!(outcomes[0].expected.as_ref().unwrap().is_err());
+        assert!(outcomes[0].matches(), "{:?}", outcomes[0]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reports_write_errors_on_stop() {
+        let path = CString::new("/dev/full").unwrap();
+        let host = MockHost::new();
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+            assert_eq!(revm_statedb_start_recording(instance, path.as_ptr()), 0);
+            assert_eq!(revm_statedb_stop_recording(instance), -1);
+            let error = (*instance).last_error.clone().unwrap();
+            assert!(error.starts_with("recording failed"), "{error}");
+            assert_eq!(revm_statedb_stop_recording(instance), 0);
+            revm_free_statedb_instance(instance);
+        }
+    }
+}
@@ -0,0 +1,1064 @@
+//! Offline replay of canonical blocks from a chaindata directory.
+//!
+//! Each block is rebuilt from its stored header and body, executed through
+//! REVM and checked against the header (gas used, receipts root, logs bloom
+//! and, when the backend can compute it, the state root) and the stored
+//! receipts.  Replay stops at the first block that does not match; the report
+//! names the first transaction whose receipt differs.
+//!
+//! With [`ReplayConfig::parlia`] set, blocks follow BSC's Parlia rules:
+//! transaction fees are paid to the system address instead of the coinbase,
+//! and system transactions (zero-priced calls from the coinbase to a system
+//! contract) run as plain calls that charge no intrinsic gas or fee, after the
+//! reward they carry is moved from the system address to the coinbase.  State
+//! changes Parlia makes without a transaction, such as the system contract
+//! upgrades at hard fork blocks, are not applied, so replay diverges there.
+//!
+//! Each block's hash is registered with [`ReplayBackend::set_block_hash`]
+//! once the block is closed.  For in-memory instances from Prague on this
+//! also writes it to the EIP-2935 history contract, which is what the next
+//! block's pre-block system call does.  The EIP-4788 beacon root call is not
+//! applied.
+//!
+//! [`DiskBackend`] can only start from a state the database still holds:
+//! the flat snapshot's root or the single root the path-based trie is
+//! persisted at, normally that of a recent block.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use alloy_consensus::transaction::SignerRecoverable;
+use alloy_consensus::{
+    BlockHeader, Eip2718EncodableReceipt, EthereumReceipt, Header, Transaction, TxEnvelope,
+    TxReceipt, Typed2718,
+};
+use anyhow::{anyhow, bail, Context as _, Result};
+use revm::{
+    bytecode::Bytecode,
+    context::{BlockEnv, CfgEnv, TxEnv},
+    context_interface::{
+        either::Either,
+        result::{EVMError, ExecutionResult, ResultAndState},
+        Block as _, Transaction as _,
+    },
+    database::CacheDB,
+    handler::{EthFrame, ExecuteEvm, Handler, MainnetEvm, MainnetHandler, SYSTEM_ADDRESS},
+    primitives::{
+        address, alloy_primitives::Bloom, eip4844::GAS_PER_BLOB, hardfork::SpecId, keccak256,
+        Address, Log, B256, KECCAK_EMPTY, U256,
+    },
+    state::AccountInfo,
+    Context, Database, DatabaseCommit, DatabaseRef, Journal, MainBuilder,
+};
+
+use crate::block_hashes::BLOCK_HASH_WINDOW;
+use crate::chaindata::{ChainData, ChainDataDB};
+use crate::fork::ForkedDB;
+use crate::trie::Trie;
+use crate::types::RevmInstance;
+
+/// Wei per gwei, the unit of withdrawal amounts.
+const GWEI: u64 = 1_000_000_000;
+
+/// BSC system contracts.  A call to one of them from the block's coinbase at
+/// zero gas price is a Parlia system transaction.
+const SYSTEM_CONTRACTS: [Address; 17] = [
+    address!("0x0000000000000000000000000000000000001000"),
+    address!("0x0000000000000000000000000000000000001001"),
+    address!("0x0000000000000000000000000000000000001002"),
+    address!("0x0000000000000000000000000000000000001003"),
+    address!("0x0000000000000000000000000000000000001004"),
+    address!("0x0000000000000000000000000000000000001005"),
+    address!("0x0000000000000000000000000000000000001006"),
+    address!("0x0000000000000000000000000000000000001007"),
+    address!("0x0000000000000000000000000000000000001008"),
+    address!("0x0000000000000000000000000000000000002000"),
+    address!("0x0000000000000000000000000000000000002001"),
+    address!("0x0000000000000000000000000000000000002002"),
+    address!("0x0000000000000000000000000000000000002003"),
+    address!("0x0000000000000000000000000000000000002004"),
+    address!("0x0000000000000000000000000000000000002005"),
+    address!("0x0000000000000000000000000000000000002006"),
+    address!("0x0000000000000000000000000000000000003000"),
+];
+
+/// Chain parameters the stored blocks do not carry.
+#[derive(Clone, Copy, Debug)]
+pub struct ReplayConfig {
+    pub chain_id: u64,
+    pub spec: SpecId,
+    /// Apply Parlia's fee routing and system transactions (BSC).
+    pub parlia: bool,
+}
+
+impl ReplayConfig {
+    fn cfg_env(&self) -> CfgEnv {
+        let mut cfg = CfgEnv::new_with_spec(self.spec);
+        cfg.chain_id = self.chain_id;
+        cfg
+    }
+}
+
+/// State that blocks are replayed into.
+pub trait ReplayBackend {
+    /// Execute and commit one transaction.
+    fn transact(&mut self, block: &BlockEnv, tx: TxEnv) -> Result<ExecutionResult>;
+
+    /// Execute and commit a Parlia system transaction: bump the caller's
+    /// nonce, then run a plain call with no intrinsic gas, fee or refund.
+    fn system_transact(&mut self, block: &BlockEnv, tx: TxEnv) -> Result<ExecutionResult>;
+
+    /// Credit `amount` wei to `address` outside of any transaction.
+    fn increase_balance(&mut self, address: Address, amount: U256) -> Result<()>;
+
+    /// Debit `amount` wei from `address` outside of any transaction.
+    fn decrease_balance(&mut self, address: Address, amount: U256) -> Result<()>;
+
+    /// Make `hash` available to BLOCKHASH as the hash of block `number`.
+    fn set_block_hash(&mut self, _number: u64, _hash: B256) {}
+
+    /// Root of the current state, if the backend can compute one.
+    fn state_root(&mut self) -> Option<B256>;
+}
+
+impl ReplayBackend for RevmInstance {
+    fn transact(&mut self, block: &BlockEnv, tx: TxEnv) -> Result<ExecutionResult> {
+        self.evm.ctx.block = block.clone();
+        self.evm.ctx.tx = tx;
+        let result = self.evm.replay().map_err(|e| anyhow!("{e:?}"))?;
+        self.commit_state(result.state);
+        Ok(result.result)
+    }
+
+    fn system_transact(&mut self, block: &BlockEnv, tx: TxEnv) -> Result<ExecutionResult> {
+        update_account(
+            &mut self.evm.ctx.journaled_state.database,
+            tx.caller,
+            |info| {
+                info.nonce += 1;
+                Ok(())
+            },
+        )?;
+        self.touch_account(tx.caller);
+        let result = system_call(&mut self.evm, block, tx)?;
+        self.commit_state(result.state);
+        Ok(result.result)
+    }
+
+    fn increase_balance(&mut self, address: Address, amount: U256) -> Result<()> {
+        update_account(
+            &mut self.evm.ctx.journaled_state.database,
+            address,
+            |info| {
+                info.balance += amount;
+                Ok(())
+            },
+        )?;
+        self.touch_account(address);
+        Ok(())
+    }
+
+    fn decrease_balance(&mut self, address: Address, amount: U256) -> Result<()> {
+        update_account(
+            &mut self.evm.ctx.journaled_state.database,
+            address,
+            |info| debit(info, address, amount),
+        )?;
+        self.touch_account(address);
+        Ok(())
+    }
+
+    fn set_block_hash(&mut self, number: u64, hash: B256) {
+        RevmInstance::set_block_hash(self, number, hash);
+    }
+
+    fn state_root(&mut self) -> Option<B256> {
+        Some(RevmInstance::state_root(self))
+    }
+}
+
+type ReplayEvm<DB> = MainnetEvm<Context<BlockEnv, TxEnv, CfgEnv, DB, Journal<DB>, ()>>;
+
+/// Apply `f` to the stored account fields of `address`, creating the account
+/// if needed.
+fn update_account<DB>(
+    db: &mut CacheDB<DB>,
+    address: Address,
+    f: impl FnOnce(&mut AccountInfo) -> Result<()>,
+) -> Result<()>
+where
+    DB: DatabaseRef,
+    DB::Error: std::error::Error + Send + Sync + 'static,
+{
+    let mut info = db.basic_ref(address)?.unwrap_or_default();
+    f(&mut info)?;
+    db.insert_account_info(address, info);
+    Ok(())
+}
+
+fn debit(info: &mut AccountInfo, address: Address, amount: U256) -> Result<()> {
+    info.balance = info
+        .balance
+        .checked_sub(amount)
+        .ok_or_else(|| anyhow!("{address} cannot pay {amount} wei"))?;
+    Ok(())
+}
+
+/// Run `tx` the way Parlia applies a system transaction: a call given the
+/// whole gas limit, skipping validation, fee payment and refunds.
+fn system_call<DB: Database>(
+    evm: &mut ReplayEvm<DB>,
+    block: &BlockEnv,
+    tx: TxEnv,
+) -> Result<ResultAndState>
+where
+    DB::Error: std::fmt::Debug,
+{
+    evm.ctx.block = block.clone();
+    evm.ctx.tx = tx;
+    MainnetHandler::<_, EVMError<DB::Error>, EthFrame<_, _, _>>::default()
+        .run_system_call(evm)
+        .map_err(|e| anyhow!("{e:?}"))
+}
+
+/// Replays on top of state persisted in the chaindata directory.  Writes stay
+/// in memory; no state root is computed.
+pub struct DiskBackend {
+    evm: ReplayEvm<CacheDB<ChainDataDB>>,
+}
+
+impl DiskBackend {
+    /// Start from the persisted state at `root`, which must be the root of
+    /// the flat snapshot or of the path-based trie.
+    pub fn new(data: Arc<ChainData>, root: B256, config: ReplayConfig) -> Result<Self> {
+        let db = ChainDataDB::new(data, Some(root))
+            .context("replay from disk must start at the block whose state is persisted")?;
+        let evm = Context::new(CacheDB::new(db), config.spec)
+            .with_cfg(config.cfg_env())
+            .build_mainnet();
+        Ok(Self { evm })
+    }
+}
+
+impl ReplayBackend for DiskBackend {
+    fn transact(&mut self, block: &BlockEnv, tx: TxEnv) -> Result<ExecutionResult> {
+        self.evm.ctx.block = block.clone();
+        self.evm.ctx.tx = tx;
+        let result = self.evm.replay().map_err(|e| anyhow!("{e:?}"))?;
+        self.evm.ctx.journaled_state.database.commit(result.state);
+        Ok(result.result)
+    }
+
+    fn system_transact(&mut self, block: &BlockEnv, tx: TxEnv) -> Result<ExecutionResult> {
+        update_account(
+            &mut self.evm.ctx.journaled_state.database,
+            tx.caller,
+            |info| {
+                info.nonce += 1;
+                Ok(())
+            },
+        )?;
+        let result = system_call(&mut self.evm, block, tx)?;
+        self.evm.ctx.journaled_state.database.commit(result.state);
+        Ok(result.result)
+    }
+
+    fn increase_balance(&mut self, address: Address, amount: U256) -> Result<()> {
+        update_account(
+            &mut self.evm.ctx.journaled_state.database,
+            address,
+            |info| {
+                info.balance += amount;
+                Ok(())
+            },
+        )
+    }
+
+    fn decrease_balance(&mut self, address: Address, amount: U256) -> Result<()> {
+        update_account(
+            &mut self.evm.ctx.journaled_state.database,
+            address,
+            |info| debit(info, address, amount),
+        )
+    }
+
+    fn state_root(&mut self) -> Option<B256> {
+        None
+    }
+}
+
+/// Build an in-memory instance holding the `alloc` of a geth genesis file.
+pub fn genesis_instance(path: impl AsRef<Path>, config: ReplayConfig) -> Result<RevmInstance> {
+    let path = path.as_ref();
+    let genesis: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)
+        .with_context(|| format!("parsing {}", path.display()))?;
+    let alloc = genesis["alloc"]
+        .as_object()
+        .ok_or_else(|| anyhow!("genesis has no alloc"))?;
+
+    let mut db = CacheDB::new(ForkedDB::default());
+    for (address, account) in alloc {
+        let address: Address = with_0x(address).parse()?;
+        let code = match account["code"].as_str() {
+            Some(code) => hex::decode(code.trim_start_matches("0x"))?,
+            None => Vec::new(),
+        };
+        let code_hash = if code.is_empty() {
+            KECCAK_EMPTY
+        } else {
+            keccak256(&code)
+        };
+        let info = AccountInfo::new(
+            parse_quantity(&account["balance"])?,
+            parse_quantity(&account["nonce"])?
+                .try_into()
+                .map_err(|_| anyhow!("nonce of {address} does not fit in u64"))?,
+            code_hash,
+            Bytecode::new_raw(code.into()),
+        );
+        db.insert_account_info(address, info);
+        if let Some(storage) = account["storage"].as_object() {
+            for (slot, value) in storage {
+                let value = value.as_str().ok_or_else(|| anyhow!("bad storage value"))?;
+                let slot = U256::from_str_radix(slot.trim_start_matches("0x"), 16)?;
+                let value = U256::from_str_radix(value.trim_start_matches("0x"), 16)?;
+                let Ok(()) = db.insert_account_storage(address, slot, value);
+            }
+        }
+    }
+
+    let evm = Context::new(db, config.spec)
+        .with_cfg(config.cfg_env())
+        .build_mainnet();
+    Ok(RevmInstance {
+        evm,
+        last_error: None,
+        snapshots: Default::default(),
+        next_snapshot_id: 1,
+        state_trie: None,
+        inspectors: Default::default(),
+        block_witness: None,
+    })
+}
+
+fn with_0x(s: &str) -> String {
+    if s.starts_with("0x") {
+        s.to_string()
+    } else {
+        format!("0x{s}")
+    }
+}
+
+/// A genesis quantity: absent, a `0x` hex string or a decimal string.
+fn parse_quantity(value: &serde_json::Value) -> Result<U256> {
+    match value.as_str() {
+        None => Ok(U256::ZERO),
+        Some(s) => match s.strip_prefix("0x") {
+            Some(hex) => Ok(U256::from_str_radix(hex, 16)?),
+            None => Ok(U256::from_str_radix(s, 10)?),
+        },
+    }
+}
+
+/// A value that differs from the stored chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Mismatch {
+    fn new(field: impl Into<String>, expected: impl ToString, actual: impl ToString) -> Self {
+        Self {
+            field: field.into(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }
+    }
+}
+
+/// The first transaction whose outcome differs from its stored receipt.
+#[derive(Clone, Debug)]
+pub struct TxDivergence {
+    pub index: usize,
+    pub hash: B256,
+    pub sender: Address,
+    pub to: Option<Address>,
+    /// REVM's result, or the error that prevented execution.
+    pub outcome: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Result of replaying one block.
+#[derive(Clone, Debug)]
+pub struct BlockReport {
+    pub number: u64,
+    pub hash: B256,
+    pub transactions: usize,
+    pub gas_used: u64,
+    /// Header fields that do not match.
+    pub mismatches: Vec<Mismatch>,
+    pub divergent_tx: Option<TxDivergence>,
+}
+
+impl BlockReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty() && self.divergent_tx.is_none()
+    }
+}
+
+fn block_env(header: &Header, spec: SpecId) -> BlockEnv {
+    let mut env = BlockEnv {
+        number: header.number,
+        beneficiary: header.beneficiary,
+        timestamp: header.timestamp,
+        gas_limit: header.gas_limit,
+        basefee: header.base_fee_per_gas.unwrap_or_default(),
+        difficulty: header.difficulty,
+        prevrandao: Some(header.mix_hash),
+        blob_excess_gas_and_price: None,
+    };
+    if let Some(excess) = header.excess_blob_gas {
+        env.set_blob_excess_gas_and_price(excess, spec.is_enabled_in(SpecId::PRAGUE));
+    }
+    env
+}
+
+fn tx_env(tx: &TxEnvelope, caller: Address) -> TxEnv {
+    TxEnv {
+        tx_type: tx.ty(),
+        caller,
+        gas_limit: tx.gas_limit(),
+        gas_price: tx.max_fee_per_gas(),
+        kind: tx.kind(),
+        value: tx.value(),
+        data: tx.input().clone(),
+        nonce: tx.nonce(),
+        chain_id: tx.chain_id(),
+        access_list: tx.access_list().cloned().unwrap_or_default(),
+        gas_priority_fee: tx.max_priority_fee_per_gas(),
+        blob_hashes: tx
+            .blob_versioned_hashes()
+            .map(<[B256]>::to_vec)
+            .unwrap_or_default(),
+        max_fee_per_blob_gas: tx.max_fee_per_blob_gas().unwrap_or_default(),
+        authorization_list: tx
+            .authorization_list()
+            .map(|list| list.iter().cloned().map(Either::Left).collect())
+            .unwrap_or_default(),
+    }
+}
+
+fn is_system_tx(tx: &TxEnvelope, sender: Address, header: &Header) -> bool {
+    sender == header.beneficiary
+        && tx.max_fee_per_gas() == 0
+        && tx.to().is_some_and(|to| SYSTEM_CONTRACTS.contains(&to))
+}
+
+/// Execute an ordinary transaction.  Under Parlia the fee it paid the
+/// coinbase goes to the system address instead, blob fees included.
+fn apply_tx<B: ReplayBackend>(
+    backend: &mut B,
+    block: &BlockEnv,
+    tx: &TxEnvelope,
+    sender: Address,
+    config: ReplayConfig,
+) -> Result<ExecutionResult> {
+    let tx = tx_env(tx, sender);
+    let gas_price = tx.effective_gas_price(u128::from(block.basefee));
+    let blob_fee = U256::from(tx.blob_hashes.len() as u64 * GAS_PER_BLOB)
+        * U256::from(block.blob_gasprice().unwrap_or_default());
+
+    let result = backend.transact(block, tx)?;
+    if config.parlia {
+        // Validation rejects prices below the base fee unless the base fee
+        // check is disabled, in which case no tip is paid.
+        let tip = if config.spec.is_enabled_in(SpecId::LONDON) {
+            gas_price.saturating_sub(u128::from(block.basefee))
+        } else {
+            gas_price
+        };
+        let fee = U256::from(tip) * U256::from(result.gas_used());
+        backend.decrease_balance(block.beneficiary, fee)?;
+        backend.increase_balance(SYSTEM_ADDRESS, fee + blob_fee)?;
+    }
+    Ok(result)
+}
+
+/// Execute a Parlia system transaction.  Any value it carries is the block
+/// reward being distributed, which Parlia first moves from the system
+/// address to the coinbase.
+fn apply_system_tx<B: ReplayBackend>(
+    backend: &mut B,
+    block: &BlockEnv,
+    tx: &TxEnvelope,
+    sender: Address,
+) -> Result<ExecutionResult> {
+    if !tx.value().is_zero() {
+        backend.decrease_balance(SYSTEM_ADDRESS, tx.value())?;
+        backend.increase_balance(sender, tx.value())?;
+    }
+    backend.system_transact(block, tx_env(tx, sender))
+}
+
+/// Root of the receipts trie, keyed by RLP-encoded transaction index.
+pub fn receipts_root(receipts: &[EthereumReceipt]) -> B256 {
+    let mut trie = Trie::new();
+    for (index, receipt) in receipts.iter().enumerate() {
+        let mut value = Vec::new();
+        receipt.eip2718_encode_with_bloom(&receipt.bloom(), &mut value);
+        trie.insert(&alloy_rlp::encode(index), value);
+    }
+    trie.root_hash()
+}
+
+fn compare_logs(expected: &[Log], actual: &[Log], mismatches: &mut Vec<Mismatch>) {
+    if expected.len() != actual.len() {
+        mismatches.push(Mismatch::new("logs", expected.len(), actual.len()));
+        return;
+    }
+    for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+        if expected.address != actual.address {
+            mismatches.push(Mismatch::new(
+                format!("logs[{i}].address"),
+                expected.address,
+                actual.address,
+            ));
+        }
+        if expected.topics() != actual.topics() {
+            mismatches.push(Mismatch::new(
+                format!("logs[{i}].topics"),
+                format!("{:?}", expected.topics()),
+                format!("{:?}", actual.topics()),
+            ));
+        }
+        if expected.data.data != actual.data.data {
+            mismatches.push(Mismatch::new(
+                format!("logs[{i}].data"),
+                &expected.data.data,
+                &actual.data.data,
+            ));
+        }
+    }
+}
+
+/// Replay block `number` on top of `backend`, which must hold the state of
+/// its parent.
+pub fn replay_block<B: ReplayBackend>(
+    chain: &ChainData,
+    backend: &mut B,
+    number: u64,
+    config: ReplayConfig,
+) -> Result<BlockReport> {
+    let block = chain
+        .block(number)?
+        .ok_or_else(|| anyhow!("block {number} not found"))?;
+    let stored = chain.receipts(number)?;
+    let header = &block.header;
+    let env = block_env(header, config.spec);
+
+    let mut report = BlockReport {
+        number,
+        hash: header.hash_slow(),
+        transactions: block.body.transactions.len(),
+        gas_used: 0,
+        mismatches: Vec::new(),
+        divergent_tx: None,
+    };
+    let mut receipts = Vec::with_capacity(block.body.transactions.len());
+
+    for (index, tx) in block.body.transactions.iter().enumerate() {
+        let mut divergence = |outcome: String, mismatches: Vec<Mismatch>, sender: Address| {
+            if report.divergent_tx.is_none() {
+                report.divergent_tx = Some(TxDivergence {
+                    index,
+                    hash: *tx.tx_hash(),
+                    sender,
+                    to: tx.to(),
+                    outcome,
+                    mismatches,
+                });
+            }
+        };
+
+        let sender = match tx.recover_signer() {
+            Ok(sender) => sender,
+            Err(err) => {
+                divergence(
+                    format!("sender recovery failed: {err}"),
+                    Vec::new(),
+                    Address::ZERO,
+                );
+                break;
+            }
+        };
+        let system = config.parlia && is_system_tx(tx, sender, header);
+        let outcome = if system {
+            apply_system_tx(backend, &env, tx, sender)
+        } else {
+            apply_tx(backend, &env, tx, sender, config)
+        };
+        let result = match outcome {
+            Ok(result) => result,
+            Err(err) => {
+                divergence(format!("invalid transaction: {err}"), Vec::new(), sender);
+                break;
+            }
+        };
+
+        report.gas_used += if system {
+            // Parlia charges everything the call consumed, refunds included.
+            match &result {
+                ExecutionResult::Success {
+                    gas_used,
+                    gas_refunded,
+                    ..
+                } => gas_used + gas_refunded,
+                _ => result.gas_used(),
+            }
+        } else {
+            result.gas_used()
+        };
+        let receipt = EthereumReceipt {
+            tx_type: tx.tx_type(),
+            // Parlia records every system transaction as successful.
+            success: system || result.is_success(),
+            cumulative_gas_used: report.gas_used,
+            logs: result.logs().to_vec(),
+        };
+
+        if let Some(expected) = stored.as_ref().and_then(|r| r.get(index)) {
+            let mut mismatches = Vec::new();
+            let expected_success = expected.status.coerce_status();
+            if expected_success != receipt.success {
+                mismatches.push(Mismatch::new("status", expected_success, receipt.success));
+            }
+            if expected.cumulative_gas_used != receipt.cumulative_gas_used {
+                mismatches.push(Mismatch::new(
+                    "cumulative_gas_used",
+                    expected.cumulative_gas_used,
+                    receipt.cumulative_gas_used,
+                ));
+            }
+            compare_logs(&expected.logs, &receipt.logs, &mut mismatches);
+            if !mismatches.is_empty() {
+                divergence(format!("{result:?}"), mismatches, sender);
+            }
+        }
+        receipts.push(receipt);
+    }
+
+    if let Some(withdrawals) = &block.body.withdrawals {
+        for withdrawal in withdrawals.iter() {
+            let amount = U256::from(withdrawal.amount) * U256::from(GWEI);
+            backend.increase_balance(withdrawal.address, amount)?;
+        }
+    }
+
+    if report.divergent_tx.is_some() && receipts.len() < block.body.transactions.len() {
+        // Execution stopped early; header totals are meaningless.
+        return Ok(report);
+    }
+
+    let mut bloom = Bloom::default();
+    receipts.iter().for_each(|r| bloom.accrue_bloom(&r.bloom()));
+    let checks = [
+        (
+            "gas_used",
+            header.gas_used.to_string(),
+            report.gas_used.to_string(),
+        ),
+        (
+            "receipts_root",
+            header.receipts_root.to_string(),
+            receipts_root(&receipts).to_string(),
+        ),
+        (
+            "logs_bloom",
+            header.logs_bloom.to_string(),
+            bloom.to_string(),
+        ),
+    ];
+    for (field, expected, actual) in checks {
+        if expected != actual {
+            report
+                .mismatches
+                .push(Mismatch::new(field, expected, actual));
+        }
+    }
+    if let Some(root) = backend.state_root() {
+        if root != header.state_root() {
+            report
+                .mismatches
+                .push(Mismatch::new("state_root", header.state_root, root));
+        }
+    }
+    Ok(report)
+}
+
+/// Replay `from..=to`, calling `on_block` after each block and stopping at
+/// the first one that diverges.  Returns the report of the last block run.
+pub fn replay_range<B: ReplayBackend>(
+    chain: &ChainData,
+    backend: &mut B,
+    from: u64,
+    to: u64,
+    config: ReplayConfig,
+    mut on_block: impl FnMut(&BlockReport),
+) -> Result<Option<BlockReport>> {
+    if from == 0 {
+        bail!("block 0 has no transactions to replay");
+    }
+    for number in from.saturating_sub(BLOCK_HASH_WINDOW)..from {
+        if let Some(hash) = chain.canonical_hash(number)? {
+            backend.set_block_hash(number, hash);
+        }
+    }
+
+    let mut last = None;
+    for number in from..=to {
+        let report = replay_block(chain, backend, number, config)?;
+        backend.set_block_hash(number, report.hash);
+        on_block(&report);
+        let ok = report.is_ok();
+        last = Some(report);
+        if !ok {
+            break;
+        }
+    }
+    Ok(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chaindata::test_db::{create, encode_receipts};
+    use crate::state_root::encode_account;
+    use crate::StoredReceipt;
+    use crate::EMPTY_ROOT_HASH;
+    use alloy_consensus::crypto::secp256k1::{recover_signer, sign_message};
+    use alloy_consensus::{
+        proofs::calculate_receipt_root, BlockBody, Eip658Value, Receipt, ReceiptEnvelope,
+        SignableTransaction, TxLegacy,
+    };
+    use revm::primitives::{TxKind, KECCAK_EMPTY};
+
+    const GWEI_PRICE: u128 = 1_000_000_000;
+
+    struct Chain {
+        genesis: std::path::PathBuf,
+        header: Header,
+        body: BlockBody<TxEnvelope>,
+        receipts: Vec<StoredReceipt>,
+    }
+
+    /// Genesis funding one key, and block 1 sending 1 wei from it.
+    fn transfer_chain(config: ReplayConfig) -> Chain {
+        let secret = B256::repeat_byte(0x11);
+        let probe = sign_message(secret, B256::ZERO).unwrap();
+        let sender = recover_signer(&probe, B256::ZERO).unwrap();
+        let recipient = Address::with_last_byte(0xbb);
+        let coinbase = Address::with_last_byte(0xcc);
+        let funds = U256::from(10u128.pow(18));
+
+        let genesis =
+            std::env::temp_dir().join(format!("revm-replay-genesis-{}.json", std::process::id()));
+        let alloc = serde_json::json!({
+            "config": { "chainId": config.chain_id },
+            "alloc": { sender.to_string(): { "balance": funds.to_string() } },
+        });
+        std::fs::write(&genesis, alloc.to_string()).unwrap();
+
+        let tx = TxLegacy {
+            chain_id: Some(config.chain_id),
+            nonce: 0,
+            gas_price: GWEI_PRICE,
+            gas_limit: 21_000,
+            to: TxKind::Call(recipient),
+            value: U256::from(1),
+            ..Default::default()
+        };
+        let signature = sign_message(secret, tx.signature_hash()).unwrap();
+        let tx: TxEnvelope = tx.into_signed(signature).into();
+
+        // Expected roots computed independently of the replay code.
+        let receipt = Receipt {
+            status: Eip658Value::Eip658(true),
+            cumulative_gas_used: 21_000,
+            logs: vec![],
+        };
+        let receipts_root =
+            calculate_receipt_root(&[ReceiptEnvelope::Legacy(receipt.clone().with_bloom())]);
+        let fee = U256::from(21_000 * GWEI_PRICE);
+        let mut state = Trie::new();
+        for (address, nonce, balance) in [
+            (sender, 1, funds - fee - U256::from(1)),
+            (recipient, 0, U256::from(1)),
+            (coinbase, 0, fee),
+        ] {
+            let info = AccountInfo::new(balance, nonce, KECCAK_EMPTY, Bytecode::default());
+            state.insert(
+                keccak256(address).as_slice(),
+                encode_account(&info, EMPTY_ROOT_HASH),
+            );
+        }
+
+        let header = Header {
+            number: 1,
+            beneficiary: coinbase,
+            gas_limit: 30_000_000,
+            gas_used: 21_000,
+            timestamp: 1,
+            state_root: state.root_hash(),
+            receipts_root,
+            // Cancun headers carry every optional field up to the blob gas.
+            base_fee_per_gas: Some(0),
+            withdrawals_root: Some(EMPTY_ROOT_HASH),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(0),
+            parent_beacon_block_root: Some(B256::ZERO),
+            ..Default::default()
+        };
+        let body = BlockBody {
+            transactions: vec![tx],
+            ..Default::default()
+        };
+        Chain {
+            genesis,
+            header,
+            body,
+            receipts: vec![receipt],
+        }
+    }
+
+    fn write(name: &str, chain: &Chain) -> ChainData {
+        let hash = chain.header.hash_slow();
+        let key = |prefix: &[u8], suffix: &[u8]| [prefix, &1u64.to_be_bytes()[..], suffix].concat();
+        let dir = create(
+            name,
+            &[vec![
+                (1, key(b"h", b"n"), hash.to_vec()),
+                (1, key(b"h", &hash[..]), alloy_rlp::encode(&chain.header)),
+                (1, key(b"b", &hash[..]), alloy_rlp::encode(&chain.body)),
+                (1, key(b"r", &hash[..]), encode_receipts(&chain.receipts)),
+            ]],
+        );
+        let data = ChainData::open(&dir).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        data
+    }
+
+    fn replay(name: &str, chain: &Chain, config: ReplayConfig) -> BlockReport {
+        let data = write(name, chain);
+        let mut instance = genesis_instance(&chain.genesis, config).unwrap();
+        replay_range(&data, &mut instance, 1, 1, config, |_| {})
+            .unwrap()
+            .unwrap()
+    }
+
+    /// Block 1 of a Parlia chain: a transfer, whose fee goes to the system
+    /// address, then the coinbase depositing that fee with the validator
+    /// contract, which stores the value it receives in slot 0.
+    fn parlia_chain(config: ReplayConfig) -> Chain {
+        let key = |byte| {
+            let secret = B256::repeat_byte(byte);
+            let probe = sign_message(secret, B256::ZERO).unwrap();
+            (secret, recover_signer(&probe, B256::ZERO).unwrap())
+        };
+        let (user_key, user) = key(0x11);
+        let (validator_key, coinbase) = key(0x22);
+        let validators = SYSTEM_CONTRACTS[0];
+        let recipient = Address::with_last_byte(0xbb);
+        let code = hex::decode("3460005500").unwrap();
+        let funds = U256::from(10u128.pow(18));
+        let fee = U256::from(21_000 * GWEI_PRICE);
+
+        let genesis = std::env::temp_dir().join(format!(
+            "revm-replay-parlia-genesis-{}.json",
+            std::process::id()
+        ));
+        let alloc = serde_json::json!({
+            "alloc": {
+                user.to_string(): { "balance": funds.to_string() },
+                validators.to_string(): { "code": hex::encode(&code) },
+            },
+        });
+        std::fs::write(&genesis, alloc.to_string()).unwrap();
+
+        let transfer = TxLegacy {
+            chain_id: Some(config.chain_id),
+            gas_price: GWEI_PRICE,
+            gas_limit: 21_000,
+            to: TxKind::Call(recipient),
+            value: U256::from(1),
+            ..Default::default()
+        };
+        let deposit = TxLegacy {
+            chain_id: Some(config.chain_id),
+            gas_price: 0,
+            gas_limit: u64::MAX / 2,
+            to: TxKind::Call(validators),
+            value: fee,
+            ..Default::default()
+        };
+        let transfer = transfer
+            .clone()
+            .into_signed(sign_message(user_key, transfer.signature_hash()).unwrap());
+        let deposit = deposit
+            .clone()
+            .into_signed(sign_message(validator_key, deposit.signature_hash()).unwrap());
+
+        // CALLVALUE, PUSH1 and a cold SSTORE of a fresh slot, no intrinsic gas.
+        let receipts = vec![
+            Receipt {
+                status: Eip658Value::Eip658(true),
+                cumulative_gas_used: 21_000,
+                logs: vec![],
+            },
+            Receipt {
+                status: Eip658Value::Eip658(true),
+                cumulative_gas_used: 21_000 + 22_105,
+                logs: vec![],
+            },
+        ];
+        let receipts_root = calculate_receipt_root(
+            &receipts
+                .iter()
+                .map(|r| ReceiptEnvelope::Legacy(r.clone().with_bloom()))
+                .collect::<Vec<_>>(),
+        );
+        let mut storage = Trie::new();
+        storage.insert(keccak256(B256::ZERO).as_slice(), alloy_rlp::encode(fee));
+        let mut state = Trie::new();
+        // The system address ends up empty and is pruned.
+        for (address, nonce, balance, code_hash, storage_root) in [
+            (
+                user,
+                1,
+                funds - fee - U256::from(1),
+                KECCAK_EMPTY,
+                EMPTY_ROOT_HASH,
+            ),
+            (recipient, 0, U256::from(1), KECCAK_EMPTY, EMPTY_ROOT_HASH),
+            (coinbase, 1, U256::ZERO, KECCAK_EMPTY, EMPTY_ROOT_HASH),
+            (validators, 0, fee, keccak256(&code), storage.root_hash()),
+        ] {
+            let info = AccountInfo::new(balance, nonce, code_hash, Bytecode::default());
+            state.insert(
+                keccak256(address).as_slice(),
+                encode_account(&info, storage_root),
+            );
+        }
+
+        let header = Header {
+            number: 1,
+            beneficiary: coinbase,
+            gas_limit: 30_000_000,
+            gas_used: 21_000 + 22_105,
+            timestamp: 1,
+            state_root: state.root_hash(),
+            receipts_root,
+            base_fee_per_gas: Some(0),
+            withdrawals_root: Some(EMPTY_ROOT_HASH),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(0),
+            parent_beacon_block_root: Some(B256::ZERO),
+            ..Default::default()
+        };
+        let body = BlockBody {
+            transactions: vec![transfer.into(), deposit.into()],
+            ..Default::default()
+        };
+        Chain {
+            genesis,
+            header,
+            body,
+            receipts,
+        }
+    }
+
+    #[test]
+    fn replays_parlia_system_transactions() {
+        let config = ReplayConfig {
+            chain_id: 56,
+            spec: SpecId::CANCUN,
+            parlia: true,
+        };
+        let chain = parlia_chain(config);
+        let report = replay("replay-parlia", &chain, config);
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!((report.gas_used, report.transactions), (43_105, 2));
+
+        // Run as an ordinary transaction, the deposit pays intrinsic gas.
+        let plain = ReplayConfig {
+            parlia: false,
+            ..config
+        };
+        let report = replay("replay-parlia-off", &chain, plain);
+        assert_eq!(report.divergent_tx.unwrap().index, 1);
+        std::fs::remove_file(chain.genesis).unwrap();
+    }
+
+    #[test]
+    fn replays_block_against_stored_header() {
+        let config = ReplayConfig {
+            chain_id: 56,
+            spec: SpecId::CANCUN,
+            parlia: false,
+        };
+        let chain = transfer_chain(config);
+        let report = replay("replay-ok", &chain, config);
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!((report.gas_used, report.transactions), (21_000, 1));
+
+        let mut tampered = transfer_chain(config);
+        tampered.header.gas_used = 20_000;
+        let report = replay("replay-gas", &tampered, config);
+        assert_eq!(
+            report.mismatches,
+            vec![Mismatch::new("gas_used", 20_000, 21_000)]
+        );
+        assert!(report.divergent_tx.is_none());
+
+        let mut tampered = transfer_chain(config);
+        tampered.receipts[0].status = Eip658Value::Eip658(false);
+        let report = replay("replay-status", &tampered, config);
+        let tx = report.divergent_tx.unwrap();
+        assert_eq!(tx.index, 0);
+        assert_eq!(tx.hash, *chain.body.transactions[0].tx_hash());
+        assert_eq!(tx.mismatches, vec![Mismatch::new("status", false, true)]);
+
+        // A gas price below the base fee is reported, not charged.
+        let mut tampered = transfer_chain(config);
+        tampered.header.base_fee_per_gas = Some(2 * GWEI_PRICE as u64);
+        let parlia = ReplayConfig {
+            parlia: true,
+            ..config
+        };
+        let report = replay("replay-basefee", &tampered, parlia);
+        let tx = report.divergent_tx.unwrap();
+        assert!(tx.outcome.starts_with("invalid transaction"), "{tx:?}");
+        std::fs::remove_file(chain.genesis).unwrap();
+    }
+
+    #[test]
+    fn rejects_genesis_nonce_overflow() {
+        let genesis = std::env::temp_dir().join(format!(
+            "revm-replay-nonce-genesis-{}.json",
+            std::process::id()
+        ));
+        let alloc = serde_json::json!({
+            "alloc": { Address::ZERO.to_string(): { "nonce": "0x10000000000000000" } },
+        });
+        std::fs::write(&genesis, alloc.to_string()).unwrap();
+        let config = ReplayConfig {
+            chain_id: 56,
+            spec: SpecId::CANCUN,
+            parlia: false,
+        };
+        let err = genesis_instance(&genesis, config).err().unwrap();
+        assert!(err.to_string().contains("does not fit in u64"), "{err}");
+        std::fs::remove_file(genesis).unwrap();
+    }
+}
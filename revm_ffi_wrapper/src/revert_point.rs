@@ -0,0 +1,303 @@
+//! Diagnostics for failed executions without a full trace.
+//!
+//! [`RevertDiagnostics`] keeps only the last instruction of each open frame:
+//! its pc, opcode, remaining gas and the top of the stack before it ran.
+//! When a frame reverts or halts, that instruction is the failing one, and
+//! it is recorded together with a snippet of the frame's memory.  The
+//! memory is only copied when a frame ends, so a step costs a handful of
+//! word copies rather than the full stack and memory of an EIP-3155 trace.
+//!
+//! A failed execution is reported as the chain of failing instructions,
+//! innermost first: a frame failing right after a call it made failed (as
+//! when a revert bubbles up) is reported after that call's chain.  Only
+//! instructions that can forward the call's return data may run in between:
+//! stack, arithmetic, jumps, memory and the return data buffer.  Anything
+//! else, such as a storage access, means the frame handled the failure.
+
+use revm::{
+    bytecode::opcode::{self, OpCode},
+    context_interface::ContextTr,
+    inspector::Inspector,
+    interpreter::{
+        interpreter_types::{InputsTr, Jumps, LoopControl, MemoryTr},
+        CallInputs, CallOutcome, CreateInputs, CreateOutcome, InstructionResult, Interpreter,
+        InterpreterResult,
+    },
+    primitives::{Address, Bytes, U256},
+};
+use serde::Serialize;
+
+use crate::eip3155::geth_error;
+
+/// Stack items captured per instruction, from the top.
+const STACK_TOP: usize = 8;
+/// Bytes of memory captured from the start of the failing frame's memory.
+const MEMORY_SNIPPET: usize = 256;
+
+/// The instruction a frame failed at.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevertPoint {
+    /// Account whose storage the frame uses
+    pub address: Address,
+    /// Depth of the frame, 1 for the transaction's top frame
+    pub depth: usize,
+    pub pc: usize,
+    pub opcode: u8,
+    pub op: &'static str,
+    /// Gas left before the instruction
+    pub gas_remaining: u64,
+    /// Up to 8 stack items before the instruction, top first
+    pub stack_top: Vec<U256>,
+    /// The first 256 bytes of memory when the frame ended
+    pub memory: Bytes,
+    pub memory_size: usize,
+    /// geth's error message for the frame's result
+    pub error: &'static str,
+}
+
+/// Whether `opcode` can be part of forwarding a failed call's return data.
+fn forwards_return_data(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        opcode::ADD..=opcode::SAR
+            | opcode::POP..=opcode::MSTORE8
+            | opcode::JUMP..=opcode::JUMPDEST
+            | opcode::MCOPY..=opcode::SWAP16
+            | opcode::RETURNDATASIZE
+            | opcode::RETURNDATACOPY
+            | opcode::RETURN
+            | opcode::REVERT
+            | opcode::INVALID
+    )
+}
+
+/// The last instruction of an open frame.
+#[derive(Default)]
+struct Frame {
+    address: Address,
+    steps: u64,
+    pc: usize,
+    opcode: u8,
+    gas_remaining: u64,
+    stack_top: Vec<U256>,
+    memory: Bytes,
+    memory_size: usize,
+    /// Failure chain of the last call this frame made, if it failed
+    failed_call: Option<Vec<RevertPoint>>,
+}
+
+/// Inspector recording where the last execution failed.
+#[derive(Default)]
+pub struct RevertDiagnostics {
+    frames: Vec<Frame>,
+    last: Vec<RevertPoint>,
+}
+
+impl RevertDiagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Failing instructions of the last execution, innermost first; empty
+    /// if it succeeded or failed before running any instruction.
+    pub fn revert_points(&self) -> &[RevertPoint] {
+        &self.last
+    }
+
+    /// Forget the last execution, before running the next one.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.last.clear();
+    }
+
+    fn enter(&mut self) {
+        self.frames.push(Frame::default());
+    }
+
+    fn exit(&mut self, result: &InterpreterResult) {
+        let depth = self.frames.len();
+        let Some(frame) = self.frames.pop() else {
+            return;
+        };
+        let failed = match geth_error(result.result) {
+            Some(error) => {
+                let mut chain = frame.failed_call.unwrap_or_default();
+                if frame.steps > 0 {
+                    chain.push(RevertPoint {
+                        address: frame.address,
+                        depth,
+                        pc: frame.pc,
+                        opcode: frame.opcode,
+                        op: OpCode::new(frame.opcode).map_or("UNKNOWN", OpCode::as_str),
+                        gas_remaining: frame.gas_remaining,
+                        stack_top: frame.stack_top,
+                        memory: frame.memory,
+                        memory_size: frame.memory_size,
+                        error,
+                    });
+                }
+                Some(chain)
+            }
+            None => None,
+        };
+        match self.frames.last_mut() {
+            Some(parent) => parent.failed_call = failed,
+            None => self.last = failed.unwrap_or_default(),
+        }
+    }
+}
+
+impl<CTX: ContextTr> Inspector<CTX> for RevertDiagnostics {
+    fn step(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        if frame.steps == 0 {
+            frame.address = interp.input.target_address();
+        }
+        frame.steps += 1;
+        frame.pc = interp.bytecode.pc();
+        frame.opcode = interp.bytecode.opcode();
+        if !forwards_return_data(frame.opcode) {
+            frame.failed_call = None;
+        }
+        frame.gas_remaining = interp.control.gas().remaining();
+        frame.stack_top.clear();
+        frame
+            .stack_top
+            .extend(interp.stack.data().iter().rev().take(STACK_TOP));
+    }
+
+    fn step_end(&mut self, interp: &mut Interpreter, _context: &mut CTX) {
+        if matches!(
+            interp.control.instruction_result(),
+            InstructionResult::Continue | InstructionResult::CallOrCreate
+        ) {
+            return;
+        }
+        // The frame is ending; its memory is about to be dropped.
+        let Some(frame) = self.frames.last_mut() else {
+            return;
+        };
+        frame.memory_size = interp.memory.size();
+        let len = frame.memory_size.min(MEMORY_SNIPPET);
+        frame.memory = if len == 0 {
+            Bytes::new()
+        } else {
+            Bytes::copy_from_slice(&interp.memory.slice(0..len))
+        };
+    }
+
+    fn call(&mut self, _context: &mut CTX, _inputs: &mut CallInputs) -> Option<CallOutcome> {
+        self.enter();
+        None
+    }
+
+    fn call_end(&mut self, _context: &mut CTX, _inputs: &CallInputs, outcome: &mut CallOutcome) {
+        self.exit(&outcome.result);
+    }
+
+    fn create(&mut self, _context: &mut CTX, _inputs: &mut CreateInputs) -> Option<CreateOutcome> {
+        self.enter();
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut CTX,
+        _inputs: &CreateInputs,
+        outcome: &mut CreateOutcome,
+    ) {
+        self.exit(&outcome.result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::Address;
+
+    #[test]
+    fn records_revert_points() {
+        let outer = CONTRACT;
+        let inner = Address::repeat_byte(0xdd);
+        let invalid = Address::repeat_byte(0xee);
+        // POP(CALL(GAS, inner, 0, 0, 0, 0, 0)); bubble up the returndata
+        let mut code = vec![0x5f, 0x5f, 0x5f, 0x5f, 0x5f, 0x73];
+        code.extend_from_slice(inner.as_slice());
+        code.extend_from_slice(&[0x5a, 0xf1, 0x50, 0x3d, 0x5f, 0x5f, 0x3e, 0x3d, 0x5f, 0xfd]);
+        // MSTORE(0, 42); REVERT(0, 32)
+        let inner_code = [0x60, 0x2a, 0x5f, 0x52, 0x60, 0x20, 0x5f, 0xfd];
+        // PUSH1 7; INVALID
+        let invalid_code = [0x60, 0x07, 0xfe];
+        // POP(CALL(GAS, inner, 0, 0, 0, 0, 0)); POP(SLOAD(0)); REVERT(0, 0)
+        let handler = Address::repeat_byte(0xbb);
+        let mut handler_code = vec![0x5f, 0x5f, 0x5f, 0x5f, 0x5f, 0x73];
+        handler_code.extend_from_slice(inner.as_slice());
+        handler_code.extend_from_slice(&[0x5a, 0xf1, 0x50, 0x5f, 0x54, 0x50, 0x5f, 0x5f, 0xfd]);
+
+        unsafe {
+            let instance = instance_with_contracts(&[
+                (outer, &code),
+                (inner, &inner_code),
+                (invalid, &invalid_code),
+                (handler, &handler_code),
+            ]);
+            let revert_points = || take_json(revm_revert_points(instance));
+            let call = |to: Address| revm_free_execution_result(call(instance, to));
+
+            assert!(revm_revert_points(instance).is_null());
+            assert_eq!(
+                (*instance).last_error.as_deref(),
+                Some("revert diagnostics are not enabled")
+            );
+            revm_enable_revert_diagnostics(instance);
+
+            // The revert bubbles up: the inner point comes first.
+            call(outer);
+            let points = revert_points();
+            let word = format!("0x{}", "00".repeat(31) + "2a");
+            assert_eq!(points.as_array().unwrap().len(), 2);
+            assert_eq!(points[0]["address"], format!("{inner:#x}"));
+            assert_eq!(points[0]["depth"], 2);
+            assert_eq!(points[0]["pc"], 7);
+            assert_eq!(points[0]["op"], "REVERT");
+            assert_eq!(points[0]["stackTop"], serde_json::json!(["0x0", "0x20"]));
+            assert_eq!(points[0]["memory"], word);
+            assert_eq!(points[0]["memorySize"], 32);
+            assert_eq!(points[0]["error"], "execution reverted");
+            assert_eq!(points[1]["address"], format!("{outer:#x}"));
+            assert_eq!(points[1]["depth"], 1);
+            assert_eq!(points[1]["pc"], 35);
+            assert_eq!(points[1]["stackTop"], serde_json::json!(["0x0", "0x20"]));
+            assert_eq!(points[1]["memory"], word);
+
+            // Reading storage after the failed call handles the failure, so
+            // only the frame's own revert is reported.
+            call(handler);
+            let points = revert_points();
+            assert_eq!(points.as_array().unwrap().len(), 1);
+            assert_eq!(points[0]["address"], format!("{handler:#x}"));
+            assert_eq!(points[0]["op"], "REVERT");
+
+            call(invalid);
+            let points = revert_points();
+            assert_eq!(points.as_array().unwrap().len(), 1);
+            assert_eq!(points[0]["pc"], 2);
+            assert_eq!(points[0]["opcode"], 0xfe);
+            assert_eq!(points[0]["stackTop"], serde_json::json!(["0x7"]));
+            assert_eq!(points[0]["memory"], "0x");
+            assert_eq!(points[0]["error"], "invalid opcode");
+
+            // A successful execution clears the previous points.
+            call(CALLER);
+            assert_eq!(revert_points(), serde_json::json!([]));
+
+            revm_disable_revert_diagnostics(instance);
+            assert!(revm_revert_points(instance).is_null());
+            revm_free(instance);
+        }
+    }
+}
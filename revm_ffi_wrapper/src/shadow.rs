@@ -0,0 +1,471 @@
+//! Shadow execution: run a transaction through REVM and compare the outcome
+//! with the receipt and state diff the Go EVM produced for the same
+//! transaction.
+//!
+//! The Go side describes its result as JSON:
+//!
+//! ```json
+//! {
+//!   "receipt": { "status": "0x1", "gasUsed": "0x5208",
+//!                "logs": [{ "address": "0x…", "topics": ["0x…"], "data": "0x…" }] },
+//!   "stateDiff": { "0x…": { "balance": "0x…", "nonce": "0x1",
+//!                           "storage": { "0x…slot": "0x…value" } } }
+//! }
+//! ```
+//!
+//! `stateDiff` holds post-transaction values of the accounts the Go EVM
+//! modified.  An account or field it leaves out is taken to be unchanged, so
+//! a change REVM makes that Go did not report is a mismatch too.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use revm::{
+    context_interface::result::{ExecutionResult, ResultAndState},
+    database_interface::{DatabaseCommit, DatabaseRef},
+    primitives::{Address, Log, B256, U256},
+    state::EvmState,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::types::RevmInstance;
+use crate::RevmInstanceStateDB;
+use crate::utils::{address_to_hex, bytes_to_hex, hex_to_address, hex_to_u256, u256_to_hex};
+
+/// Receipt and state diff reported by the other engine.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowExpectation {
+    #[serde(default)]
+    pub receipt: Option<ExpectedReceipt>,
+    #[serde(default)]
+    pub state_diff: BTreeMap<String, ExpectedAccount>,
+}
+
+/// Receipt fields compared by shadow execution.  Quantities may be hex
+/// strings, decimal strings or JSON numbers.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpectedReceipt {
+    pub status: Option<serde_json::Value>,
+    pub gas_used: Option<serde_json::Value>,
+    pub logs: Option<Vec<ExpectedLog>>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ExpectedLog {
+    pub address: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub data: String,
+}
+
+/// Post-transaction values of one account.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ExpectedAccount {
+    pub balance: Option<String>,
+    pub nonce: Option<serde_json::Value>,
+    #[serde(default)]
+    pub storage: BTreeMap<String, String>,
+}
+
+/// One value on which the engines disagree.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ShadowMismatch {
+    /// `status`, `gasUsed`, `logs`, `logs[i].address|topics|data`,
+    /// `balance`, `nonce` or `storage`.
+    pub field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot: Option<String>,
+    /// Value reported by the Go EVM.
+    pub expected: String,
+    /// Value produced by REVM.
+    pub actual: String,
+}
+
+/// Outcome of a shadow comparison.
+#[derive(Clone, Debug, Serialize)]
+pub struct ShadowReport {
+    pub matches: bool,
+    pub mismatches: Vec<ShadowMismatch>,
+}
+
+/// Parse a quantity given as a JSON number, a `0x` hex string or a decimal
+/// string.
+fn quantity(value: &serde_json::Value) -> Result<U256> {
+    match value {
+        serde_json::Value::Number(n) => n
+            .as_u64()
+            .map(U256::from)
+            .ok_or_else(|| anyhow!("invalid quantity {n}")),
+        serde_json::Value::String(s) if s.starts_with("0x") => hex_to_u256(s),
+        serde_json::Value::String(s) => {
+            U256::from_str_radix(s, 10).map_err(|e| anyhow!("invalid quantity {s}: {e}"))
+        }
+        other => Err(anyhow!("invalid quantity {other}")),
+    }
+}
+
+fn slot_to_hex(slot: U256) -> String {
+    B256::from(slot).to_string()
+}
+
+#[derive(Default)]
+struct Comparison {
+    mismatches: Vec<ShadowMismatch>,
+}
+
+impl Comparison {
+    fn check<T: PartialEq + ToString>(&mut self, field: &str, expected: T, actual: T) {
+        self.check_at(field, None, None, expected, actual);
+    }
+
+    fn check_at<T: PartialEq + ToString>(
+        &mut self,
+        field: &str,
+        address: Option<Address>,
+        slot: Option<U256>,
+        expected: T,
+        actual: T,
+    ) {
+        if expected != actual {
+            self.mismatches.push(ShadowMismatch {
+                field: field.to_string(),
+                address: address.map(address_to_hex),
+                slot: slot.map(slot_to_hex),
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+
+    fn logs(&mut self, expected: &[ExpectedLog], actual: &[Log]) -> Result<()> {
+        if expected.len() != actual.len() {
+            self.check("logs", expected.len(), actual.len());
+            return Ok(());
+        }
+        for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+            self.check(
+                &format!("logs[{i}].address"),
+                hex_to_address(&expected.address)?,
+                actual.address,
+            );
+            let topics = expected
+                .topics
+                .iter()
+                .map(|t| hex_to_u256(t).map(B256::from))
+                .collect::<Result<Vec<_>>>()?;
+            self.check(
+                &format!("logs[{i}].topics"),
+                format!("{topics:?}"),
+                format!("{:?}", actual.topics()),
+            );
+            let data = hex::decode(expected.data.trim_start_matches("0x"))?;
+            self.check(
+                &format!("logs[{i}].data"),
+                bytes_to_hex(&data),
+                bytes_to_hex(&actual.data.data),
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Compare an execution result and its (uncommitted) state changes with
+/// what the Go EVM reported.  `pre` must still hold the pre-transaction
+/// state.
+pub fn compare_execution<DB: DatabaseRef>(
+    pre: &DB,
+    result: &ExecutionResult,
+    state: &EvmState,
+    expected: &ShadowExpectation,
+) -> Result<ShadowReport> {
+    let mut cmp = Comparison::default();
+
+    if let Some(receipt) = &expected.receipt {
+        if let Some(status) = &receipt.status {
+            cmp.check("status", !quantity(status)?.is_zero(), result.is_success());
+        }
+        if let Some(gas_used) = &receipt.gas_used {
+            cmp.check(
+                "gasUsed",
+                quantity(gas_used)?,
+                U256::from(result.gas_used()),
+            );
+        }
+        if let Some(logs) = &receipt.logs {
+            cmp.logs(logs, result.logs())?;
+        }
+    }
+
+    let mut diff = BTreeMap::new();
+    for (address, account) in &expected.state_diff {
+        diff.insert(hex_to_address(address)?, account);
+    }
+    let mut addresses: Vec<Address> = diff.keys().copied().collect();
+    addresses.extend(
+        state
+            .iter()
+            .filter(|(_, a)| a.is_touched())
+            .map(|(addr, _)| *addr),
+    );
+    addresses.sort();
+    addresses.dedup();
+
+    let db_err = |e: DB::Error| anyhow!("database error: {e}");
+    for address in addresses {
+        let before = pre.basic_ref(address).map_err(db_err)?.unwrap_or_default();
+        let account = state.get(&address).filter(|a| a.is_touched());
+        let after = account.map_or(&before, |a| &a.info);
+        let reported = diff.get(&address);
+
+        let balance = match reported.and_then(|r| r.balance.as_deref()) {
+            Some(balance) => hex_to_u256(balance)?,
+            None => before.balance,
+        };
+        cmp.check_at(
+            "balance",
+            Some(address),
+            None,
+            u256_to_hex(balance),
+            u256_to_hex(after.balance),
+        );
+
+        let nonce = match reported.and_then(|r| r.nonce.as_ref()) {
+            Some(nonce) => quantity(nonce)?,
+            None => U256::from(before.nonce),
+        };
+        cmp.check_at("nonce", Some(address), None, nonce, U256::from(after.nonce));
+
+        let mut slots = BTreeMap::new();
+        if let Some(account) = account {
+            for (slot, value) in account.storage.iter().filter(|(_, v)| v.is_changed()) {
+                slots.insert(*slot, (value.original_value, Some(value.present_value)));
+            }
+        }
+        for (slot, value) in reported.map(|r| &r.storage).into_iter().flatten() {
+            let slot = hex_to_u256(slot)?;
+            let value = hex_to_u256(value)?;
+            let actual = slots.get(&slot).and_then(|(_, present)| *present);
+            slots.insert(slot, (value, actual));
+        }
+        for (slot, (expected, actual)) in slots {
+            let actual = match actual {
+                Some(actual) => actual,
+                None => match account.and_then(|a| a.storage.get(&slot)) {
+                    Some(value) => value.present_value,
+                    None if account.is_some_and(|a| a.is_selfdestructed()) => U256::ZERO,
+                    None => pre.storage_ref(address, slot).map_err(db_err)?,
+                },
+            };
+            cmp.check_at(
+                "storage",
+                Some(address),
+                Some(slot),
+                u256_to_hex(expected),
+                u256_to_hex(actual),
+            );
+        }
+    }
+
+    Ok(ShadowReport {
+        matches: cmp.mismatches.is_empty(),
+        mismatches: cmp.mismatches,
+    })
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction and compare it with `expected`.
+    ///
+    /// The REVM state changes are committed only when `commit` is set, so a
+    /// shadow instance can either follow the chain or stay put.
+    pub fn execute_shadow(
+        &mut self,
+        expected: &ShadowExpectation,
+        commit: bool,
+    ) -> Result<ShadowReport> {
+        let ResultAndState { result, state } = self
+            .replay_pending()
+            .map_err(|e| anyhow!("Execution failed: {e:?}"))?;
+        let report = compare_execution(
+            &self.evm.ctx.journaled_state.database,
+            &result,
+            &state,
+            expected,
+        )?;
+        if commit {
+            self.commit_state(state);
+        }
+        Ok(report)
+    }
+}
+
+impl RevmInstanceStateDB {
+    /// [`RevmInstance::execute_shadow`] against the Go StateDB, which
+    /// receives the changes when `commit` is set.
+    pub fn execute_shadow(
+        &mut self,
+        expected: &ShadowExpectation,
+        commit: bool,
+    ) -> Result<ShadowReport> {
+        let ResultAndState { result, state } = self
+            .replay_pending()
+            .map_err(|e| anyhow!("Execution failed: {e:?}"))?;
+        let report = compare_execution(
+            &self.evm.ctx.journaled_state.database,
+            &result,
+            &state,
+            expected,
+        )?;
+        if commit {
+            self.evm.ctx.journaled_state.database.commit(state);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::primitives::U256;
+    use std::ffi::CString;
+
+    /// `SSTORE(0, 0x2a); LOG0(0, 0); STOP`
+    const RUNTIME: [u8; 11] = [
+        0x60, 0x2a, 0x60, 0x00, 0x55, 0x60, 0x00, 0x60, 0x00, 0xa0, 0x00,
+    ];
+
+    unsafe fn shadow(
+        instance: *mut RevmInstance,
+        nonce: u32,
+        expected: &serde_json::Value,
+    ) -> serde_json::Value {
+        set_call(instance, CONTRACT, &[], nonce);
+        let json = CString::new(expected.to_string()).unwrap();
+        take_json(revm_execute_shadow(instance, json.as_ptr(), true))
+    }
+
+    #[test]
+    fn reports_structured_diff() {
+        let caller = format!("{CALLER:#x}");
+        let contract_hex = format!("{CONTRACT:#x}");
+        let slot0 = format!("{:#x}", revm::primitives::B256::ZERO);
+
+        unsafe {
+            let instance = instance_with(&RUNTIME);
+            let report = shadow(
+                instance,
+                0,
+                &serde_json::json!({
+                    "receipt": {
+                        "status": 1,
+                        "gasUsed": "0x5208",
+                        "logs": [{ "address": contract_hex, "topics": [], "data": "0x" }],
+                    },
+                    "stateDiff": {
+                        caller.clone(): { "nonce": 1 },
+                        contract_hex.clone(): { "storage": { slot0.clone(): "0x2b" } },
+                        "0x00000000000000000000000000000000000000ee": { "balance": "0x5" },
+                    },
+                }),
+            );
+            assert_eq!(report["matches"], false);
+            let mismatches = report["mismatches"].as_array().unwrap();
+            let fields: Vec<_> = mismatches
+                .iter()
+                .map(|m| m["field"].as_str().unwrap())
+                .collect();
+            assert_eq!(fields, ["gasUsed", "balance", "storage"]);
+            assert_eq!(mismatches[0]["expected"], "21000");
+            let gas_used = mismatches[0]["actual"].as_str().unwrap().to_string();
+            assert_eq!(
+                mismatches[1]["address"],
+                "0x00000000000000000000000000000000000000ee"
+            );
+            assert_eq!(mismatches[1]["actual"], "0x0");
+            assert_eq!(mismatches[2]["address"], contract_hex.as_str());
+            assert_eq!(mismatches[2]["slot"], slot0.as_str());
+            assert_eq!(
+                (&mismatches[2]["expected"], &mismatches[2]["actual"]),
+                (&"0x2b".into(), &"0x2a".into())
+            );
+            revm_free(instance);
+
+            // The same transaction, described correctly.
+            let instance = instance_with(&RUNTIME);
+            let report = shadow(
+                instance,
+                0,
+                &serde_json::json!({
+                    "receipt": { "status": "0x1", "gasUsed": gas_used, "logs": [{ "address": contract_hex }] },
+                    "stateDiff": {
+                        caller.clone(): { "nonce": "0x1" },
+                        contract_hex.clone(): { "storage": { slot0.clone(): "0x2a" } },
+                    },
+                }),
+            );
+            assert_eq!(report["matches"], true, "{report}");
+
+            // Committed, so replaying leaves slot 0 unchanged and Go's diff
+            // no longer needs to mention it.
+            let report = shadow(
+                instance,
+                1,
+                &serde_json::json!({
+                    "stateDiff": { caller.clone(): { "nonce": 2 } },
+                }),
+            );
+            assert_eq!(report["matches"], true, "{report}");
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn shadows_statedb_execution() {
+        let (caller, contract) = (CALLER, CONTRACT);
+        let (caller_hex, contract_hex) = (format!("{caller:#x}"), format!("{contract:#x}"));
+        let slot0 = format!("{:#x}", revm::primitives::B256::ZERO);
+        let host = host_with(&RUNTIME);
+
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+            let shadow = |nonce: u32, expected: serde_json::Value| {
+                statedb_set_call(instance, contract, nonce);
+                let json = CString::new(expected.to_string()).unwrap();
+                take_json(revm_statedb_execute_shadow(instance, json.as_ptr(), true))
+            };
+
+            let report = shadow(
+                0,
+                serde_json::json!({
+                    "receipt": { "status": "0x1", "logs": [{ "address": contract_hex }] },
+                    "stateDiff": {
+                        caller_hex.clone(): { "nonce": 1 },
+                        contract_hex.clone(): { "storage": { slot0.clone(): "0x2a" } },
+                    },
+                }),
+            );
+            assert_eq!(report["matches"], true, "{report}");
+            // The changes reached the host.
+            assert_eq!(host.account(caller).unwrap().nonce, 1);
+            assert_eq!(host.storage(contract, U256::ZERO), U256::from(0x2a));
+
+            let report = shadow(
+                1,
+                serde_json::json!({
+                    "stateDiff": {
+                        caller_hex.clone(): { "nonce": 2 },
+                        contract_hex.clone(): { "storage": { slot0.clone(): "0x2b" } },
+                    },
+                }),
+            );
+            assert_eq!(report["mismatches"][0]["field"], "storage");
+            assert_eq!(report["mismatches"][0]["actual"], "0x2a");
+            revm_free_statedb_instance(instance);
+        }
+    }
+}
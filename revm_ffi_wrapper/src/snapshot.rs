@@ -0,0 +1,106 @@
+//! Checkpoint / revert support for in-memory `RevmInstance`s.
+//!
+//! A checkpoint is a full copy of the `CacheDB` overlay (accounts, storage,
+//! contracts and block hashes, plus the shared fork layers beneath it)
+//! together with the block environment.  Ids are
+//! handed out in increasing order; reverting to an id drops that checkpoint
+//! and all newer ones, like anvil's `evm_snapshot` / `evm_revert`.
+
+use anyhow::{anyhow, Result};
+use revm::{context::BlockEnv, database::CacheDB};
+
+use crate::fork::ForkedDB;
+use crate::state_root::StateTrie;
+use crate::types::RevmInstance;
+
+/// State captured by `revm_snapshot`.
+#[derive(Clone, Debug)]
+pub struct InstanceSnapshot {
+    db: CacheDB<ForkedDB>,
+    block: BlockEnv,
+    state_trie: Option<StateTrie>,
+}
+
+impl RevmInstance {
+    /// Capture the current state and return the new checkpoint id.
+    pub fn snapshot(&mut self) -> u64 {
+        let id = self.next_snapshot_id;
+        self.next_snapshot_id += 1;
+
+        let snapshot = InstanceSnapshot {
+            db: self.evm.ctx.journaled_state.database.clone(),
+            block: self.evm.ctx.block.clone(),
+            state_trie: self.state_trie.clone(),
+        };
+        self.snapshots.insert(id, snapshot);
+        id
+    }
+
+    /// Restore checkpoint `id`, discarding it and every later checkpoint.
+    pub fn revert_to(&mut self, id: u64) -> Result<()> {
+        let snapshot = self
+            .snapshots
+            .remove(&id)
+            .ok_or_else(|| anyhow!("Unknown snapshot id {}", id))?;
+        self.snapshots.split_off(&id);
+
+        self.evm.ctx.journaled_state.database = snapshot.db;
+        self.evm.ctx.block = snapshot.block;
+        self.state_trie = snapshot.state_trie;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+    use std::ffi::CString;
+
+    unsafe fn balance_of(instance: *mut RevmInstance, addr: &CString) -> String {
+        let ptr = revm_get_balance(instance, addr.as_ptr());
+        let balance = std::ffi::CStr::from_ptr(ptr).to_str().unwrap().to_string();
+        revm_free_string(ptr);
+        balance
+    }
+
+    #[test]
+    fn nested_snapshots_restore_state() {
+        let alice = CString::new("0x1000000000000000000000000000000000000001").unwrap();
+        let one = CString::new("0x1").unwrap();
+        let two = CString::new("0x2").unwrap();
+        let three = CString::new("0x3").unwrap();
+
+        unsafe {
+            let instance = revm_new();
+            assert!(!instance.is_null());
+
+            assert_eq!(revm_set_balance(instance, alice.as_ptr(), one.as_ptr()), 0);
+            let first = revm_snapshot(instance);
+
+            assert_eq!(revm_set_balance(instance, alice.as_ptr(), two.as_ptr()), 0);
+            assert_eq!(revm_set_nonce(instance, alice.as_ptr(), 5), 0);
+            (*instance).evm.ctx.block.number = 100;
+            let second = revm_snapshot(instance);
+            assert!(second > first);
+
+            assert_eq!(revm_set_balance(instance, alice.as_ptr(), three.as_ptr()), 0);
+            (*instance).evm.ctx.block.number = 200;
+
+            assert_eq!(revm_revert_to(instance, second), 0);
+            assert_eq!(balance_of(instance, &alice), "0x2");
+            assert_eq!(revm_get_nonce(instance, alice.as_ptr()), 5);
+            assert_eq!((*instance).evm.ctx.block.number, 100);
+
+            assert_eq!(revm_revert_to(instance, first), 0);
+            assert_eq!(balance_of(instance, &alice), "0x1");
+            assert_eq!(revm_get_nonce(instance, alice.as_ptr()), 0);
+            assert_eq!((*instance).evm.ctx.block.number, 0);
+
+            // Reverting consumes the checkpoint and all later ones.
+            assert_eq!(revm_revert_to(instance, second), -1);
+            assert_eq!(revm_revert_to(instance, first), -1);
+
+            revm_free(instance);
+        }
+    }
+}
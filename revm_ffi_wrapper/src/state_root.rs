@@ -0,0 +1,416 @@
+//! State root and storage roots for in-memory `RevmInstance`s.
+//!
+//! The account trie and one storage trie per account are built lazily on the
+//! first request and then kept up to date incrementally: every commit path of
+//! the instance records which accounts and slots it touched, and the next root
+//! request only re-reads and re-hashes those.  Accounts that are empty under
+//! EIP-161 are left out of the trie, matching geth after Spurious Dragon.
+
+use std::collections::BTreeMap;
+
+use alloy_rlp::{Encodable, Header};
+use revm::{
+    database::CacheDB,
+    database_interface::DatabaseRef,
+    primitives::{
+        hardfork::SpecId, keccak256, Address, Bytes, HashMap, HashSet, StorageKey, StorageValue,
+        B256,
+    },
+    state::{AccountInfo, EvmState},
+};
+
+use crate::fork::{flatten_account, flatten_accounts, ForkedDB};
+use crate::trie::{Trie, EMPTY_ROOT_HASH};
+use crate::types::RevmInstance;
+
+/// Pending changes for one account since the last root computation.
+#[derive(Clone, Debug, Default)]
+struct DirtyAccount {
+    slots: HashSet<StorageKey>,
+    storage_reset: bool,
+}
+
+/// Account trie plus per-account storage tries.
+#[derive(Clone, Debug, Default)]
+pub struct StateTrie {
+    accounts: Trie,
+    /// Addresses in the account trie by hashed key, for range iteration
+    account_keys: BTreeMap<B256, Address>,
+    storage: HashMap<Address, Trie>,
+    dirty: HashMap<Address, DirtyAccount>,
+}
+
+/// Trie key of a storage slot (`keccak256` of the 32-byte big-endian slot).
+pub(crate) fn storage_key(slot: StorageKey) -> B256 {
+    keccak256(slot.to_be_bytes::<32>())
+}
+
+/// RLP encoding of a storage value as stored in the trie.
+pub(crate) fn encode_storage_value(value: StorageValue) -> Vec<u8> {
+    if value.is_zero() {
+        return Vec::new();
+    }
+    let mut out = Vec::with_capacity(33);
+    value.encode(&mut out);
+    out
+}
+
+/// RLP encoding of an account leaf: `[nonce, balance, storageRoot, codeHash]`.
+pub(crate) fn encode_account(info: &AccountInfo, storage_root: B256) -> Vec<u8> {
+    let payload_length = info.nonce.length()
+        + info.balance.length()
+        + storage_root.length()
+        + info.code_hash.length();
+    let mut out = Vec::with_capacity(payload_length + 2);
+    Header { list: true, payload_length }.encode(&mut out);
+    info.nonce.encode(&mut out);
+    info.balance.encode(&mut out);
+    storage_root.encode(&mut out);
+    info.code_hash.encode(&mut out);
+    out
+}
+
+fn build_storage_trie(storage: &HashMap<StorageKey, StorageValue>) -> Trie {
+    let mut trie = Trie::new();
+    for (slot, value) in storage {
+        trie.insert(storage_key(*slot).as_slice(), encode_storage_value(*value));
+    }
+    trie
+}
+
+impl StateTrie {
+    /// Build the tries from scratch for everything visible through `db`.
+    pub fn build(db: &CacheDB<ForkedDB>, prune_empty: bool) -> Self {
+        let mut trie = StateTrie::default();
+        for (address, account) in flatten_accounts(db) {
+            let mut storage = build_storage_trie(&account.storage);
+            let storage_root = storage.root_hash();
+            if !storage.is_empty() {
+                trie.storage.insert(address, storage);
+            }
+            if prune_empty && account.info.is_empty() && storage_root == EMPTY_ROOT_HASH {
+                continue;
+            }
+            let key = keccak256(address);
+            trie.accounts.insert(key.as_slice(), encode_account(&account.info, storage_root));
+            trie.account_keys.insert(key, address);
+        }
+        trie
+    }
+
+    /// Record that the account fields of `address` may have changed.
+    pub fn mark_account(&mut self, address: Address) {
+        self.dirty.entry(address).or_default();
+    }
+
+    /// Record that a single storage slot of `address` may have changed.
+    pub fn mark_slot(&mut self, address: Address, slot: StorageKey) {
+        self.dirty.entry(address).or_default().slots.insert(slot);
+    }
+
+    /// Record everything a committed transaction changed.
+    pub fn mark_state(&mut self, state: &EvmState) {
+        for (address, account) in state {
+            if !account.is_touched() {
+                continue;
+            }
+            let dirty = self.dirty.entry(*address).or_default();
+            if account.is_selfdestructed() || account.is_created() {
+                dirty.storage_reset = true;
+            }
+            dirty
+                .slots
+                .extend(account.changed_storage_slots().map(|(slot, _)| *slot));
+        }
+    }
+
+    /// Fold pending changes into the tries.
+    fn apply(&mut self, db: &CacheDB<ForkedDB>, prune_empty: bool) {
+        for (address, dirty) in std::mem::take(&mut self.dirty) {
+            let account_key = keccak256(address);
+            let Ok(info) = db.basic_ref(address);
+            let Some(info) = info else {
+                self.accounts.remove(account_key.as_slice());
+                self.account_keys.remove(&account_key);
+                self.storage.remove(&address);
+                continue;
+            };
+
+            let storage = self.storage.entry(address).or_default();
+            if dirty.storage_reset {
+                let full = flatten_account(db, address).map(|acc| acc.storage).unwrap_or_default();
+                *storage = build_storage_trie(&full);
+            }
+            for slot in dirty.slots {
+                let Ok(value) = db.storage_ref(address, slot);
+                storage.insert(storage_key(slot).as_slice(), encode_storage_value(value));
+            }
+            let storage_root = storage.root_hash();
+            if storage.is_empty() {
+                self.storage.remove(&address);
+            }
+
+            if prune_empty && info.is_empty() && storage_root == EMPTY_ROOT_HASH {
+                self.accounts.remove(account_key.as_slice());
+                self.account_keys.remove(&account_key);
+            } else {
+                self.accounts
+                    .insert(account_key.as_slice(), encode_account(&info, storage_root));
+                self.account_keys.insert(account_key, address);
+            }
+        }
+    }
+
+    /// Proof nodes for the account leaf of `address` and for each of `slots`
+    /// in its storage trie.
+    pub fn proof(
+        &mut self,
+        db: &CacheDB<ForkedDB>,
+        prune_empty: bool,
+        address: Address,
+        slots: &[StorageKey],
+    ) -> (Vec<Bytes>, Vec<Vec<Bytes>>) {
+        self.apply(db, prune_empty);
+        let account_proof = self.accounts.proof(keccak256(address).as_slice());
+        let storage_proofs = match self.storage.get_mut(&address) {
+            Some(storage) => slots
+                .iter()
+                .map(|slot| storage.proof(storage_key(*slot).as_slice()))
+                .collect(),
+            None => vec![Vec::new(); slots.len()],
+        };
+        (account_proof, storage_proofs)
+    }
+
+    /// Current state root.
+    pub fn root(&mut self, db: &CacheDB<ForkedDB>, prune_empty: bool) -> B256 {
+        self.apply(db, prune_empty);
+        self.accounts.root_hash()
+    }
+
+    /// Addresses in the account trie by hashed key, as of the last root
+    /// computation.
+    pub(crate) fn account_keys(&self) -> &BTreeMap<B256, Address> {
+        &self.account_keys
+    }
+
+    /// Current storage root of `address` (the empty root if it has none).
+    pub fn storage_root(
+        &mut self,
+        db: &CacheDB<ForkedDB>,
+        prune_empty: bool,
+        address: Address,
+    ) -> B256 {
+        self.apply(db, prune_empty);
+        self.storage
+            .get_mut(&address)
+            .map(Trie::root_hash)
+            .unwrap_or(EMPTY_ROOT_HASH)
+    }
+}
+
+impl RevmInstance {
+    /// Whether empty accounts are dropped from the trie (EIP-161).
+    pub(crate) fn prune_empty_accounts(&self) -> bool {
+        self.evm.ctx.cfg.spec.is_enabled_in(SpecId::SPURIOUS_DRAGON)
+    }
+
+    /// The instance's state trie, built on first use.
+    pub(crate) fn state_trie(&mut self) -> &mut StateTrie {
+        let prune_empty = self.prune_empty_accounts();
+        let db = &self.evm.ctx.journaled_state.database;
+        self.state_trie
+            .get_or_insert_with(|| StateTrie::build(db, prune_empty))
+    }
+
+    /// Commit transaction output to the database, keeping the trie in sync.
+    pub fn commit_state(&mut self, state: EvmState) {
+        use revm::database_interface::DatabaseCommit;
+
+        if let Some(trie) = &mut self.state_trie {
+            trie.mark_state(&state);
+        }
+        self.evm.ctx.journaled_state.database.commit(state);
+    }
+
+    /// Note a direct write to the account fields of `address`.
+    pub(crate) fn touch_account(&mut self, address: Address) {
+        if let Some(trie) = &mut self.state_trie {
+            trie.mark_account(address);
+        }
+    }
+
+    /// Note a direct write to a storage slot.
+    pub(crate) fn touch_slot(&mut self, address: Address, slot: StorageKey) {
+        if let Some(trie) = &mut self.state_trie {
+            trie.mark_slot(address, slot);
+        }
+    }
+
+    /// Merkle-Patricia root of the current state.
+    pub fn state_root(&mut self) -> B256 {
+        let prune_empty = self.prune_empty_accounts();
+        self.state_trie();
+        let trie = self.state_trie.as_mut().expect("built above");
+        trie.root(&self.evm.ctx.journaled_state.database, prune_empty)
+    }
+
+    /// Storage root of `address`.
+    pub fn storage_root(&mut self, address: Address) -> B256 {
+        let prune_empty = self.prune_empty_accounts();
+        self.state_trie();
+        let trie = self.state_trie.as_mut().expect("built above");
+        trie.storage_root(&self.evm.ctx.journaled_state.database, prune_empty, address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use alloy_trie::{HashBuilder, Nibbles};
+    use revm::primitives::U256;
+    use std::collections::BTreeMap;
+    use std::ffi::{CStr, CString};
+
+    /// Reference root computed with alloy-trie's non-incremental builder.
+    fn reference_root(leaves: impl IntoIterator<Item = (B256, Vec<u8>)>) -> B256 {
+        let sorted: BTreeMap<B256, Vec<u8>> = leaves.into_iter().collect();
+        let mut builder = HashBuilder::default();
+        for (key, value) in sorted {
+            builder.add_leaf(Nibbles::unpack(key), &value);
+        }
+        builder.root()
+    }
+
+    type TestAccount = (Address, AccountInfo, Vec<(U256, U256)>);
+
+    fn reference_state_root(accounts: &[TestAccount]) -> B256 {
+        reference_root(accounts.iter().map(|(address, info, storage)| {
+            let storage_root = reference_root(
+                storage
+                    .iter()
+                    .filter(|(_, v)| !v.is_zero())
+                    .map(|(k, v)| (storage_key(*k), encode_storage_value(*v))),
+            );
+            (keccak256(address), encode_account(info, storage_root))
+        }))
+    }
+
+    unsafe fn root_of(instance: *mut RevmInstance) -> B256 {
+        let ptr = revm_state_root(instance);
+        let root = CStr::from_ptr(ptr).to_str().unwrap().parse().unwrap();
+        revm_free_string(ptr);
+        root
+    }
+
+    #[test]
+    fn empty_instance_has_empty_root() {
+        unsafe {
+            let instance = revm_new();
+            assert_eq!(root_of(instance), EMPTY_ROOT_HASH);
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn incremental_root_matches_full_rebuild() {
+        let alice = Address::repeat_byte(0x11);
+        let bob = Address::repeat_byte(0x22);
+        let alice_c = CString::new(format!("{alice:#x}")).unwrap();
+        let bob_c = CString::new(format!("{bob:#x}")).unwrap();
+
+        unsafe {
+            let instance = revm_new();
+            revm_set_balance(instance, alice_c.as_ptr(), CString::new("0x1000").unwrap().as_ptr());
+            revm_set_balance(instance, bob_c.as_ptr(), CString::new("0x5").unwrap().as_ptr());
+            revm_set_storage(
+                instance,
+                bob_c.as_ptr(),
+                CString::new("0x1").unwrap().as_ptr(),
+                CString::new("0x2a").unwrap().as_ptr(),
+            );
+            let first = root_of(instance);
+
+            let alice_info = AccountInfo { balance: U256::from(0x1000), ..Default::default() };
+            let bob_info = AccountInfo { balance: U256::from(5), ..Default::default() };
+            assert_eq!(
+                first,
+                reference_state_root(&[
+                    (alice, alice_info.clone(), vec![]),
+                    (bob, bob_info.clone(), vec![(U256::from(1), U256::from(0x2a))]),
+                ])
+            );
+
+            // Incremental updates: change a slot, add a slot, clear a slot.
+            revm_set_storage(
+                instance,
+                bob_c.as_ptr(),
+                CString::new("0x2").unwrap().as_ptr(),
+                CString::new("0x7").unwrap().as_ptr(),
+            );
+            revm_set_storage(
+                instance,
+                bob_c.as_ptr(),
+                CString::new("0x1").unwrap().as_ptr(),
+                CString::new("0x0").unwrap().as_ptr(),
+            );
+            revm_set_nonce(instance, alice_c.as_ptr(), 3);
+            let second = root_of(instance);
+
+            let alice_info = AccountInfo { nonce: 3, ..alice_info };
+            let expected = reference_state_root(&[
+                (alice, alice_info, vec![]),
+                (bob, bob_info, vec![(U256::from(2), U256::from(7))]),
+            ]);
+            assert_eq!(second, expected);
+
+            let fresh = StateTrie::build(&(*instance).evm.ctx.journaled_state.database, true)
+                .root(&(*instance).evm.ctx.journaled_state.database, true);
+            assert_eq!(fresh, expected);
+
+            let storage_root = (*instance).storage_root(bob);
+            assert_eq!(
+                storage_root,
+                reference_root([(storage_key(U256::from(2)), encode_storage_value(U256::from(7)))])
+            );
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn committed_transfer_updates_root() {
+        let alice = Address::repeat_byte(0x11);
+        let bob = Address::repeat_byte(0x22);
+        let alice_c = CString::new(format!("{alice:#x}")).unwrap();
+        let bob_c = CString::new(format!("{bob:#x}")).unwrap();
+
+        unsafe {
+            let instance = revm_new();
+            revm_set_balance(
+                instance,
+                alice_c.as_ptr(),
+                CString::new("0xde0b6b3a7640000").unwrap().as_ptr(),
+            );
+            let before = root_of(instance);
+
+            let result = revm_transfer(
+                instance,
+                alice_c.as_ptr(),
+                bob_c.as_ptr(),
+                CString::new("0x100").unwrap().as_ptr(),
+                21_000,
+            );
+            assert!(!result.is_null());
+            assert_eq!((*result).success, 1);
+            revm_free_execution_result(result);
+
+            let after = root_of(instance);
+            assert_ne!(before, after);
+            let rebuilt = StateTrie::build(&(*instance).evm.ctx.journaled_state.database, true)
+                .root(&(*instance).evm.ctx.journaled_state.database, true);
+            assert_eq!(after, rebuilt);
+            revm_free(instance);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Fixtures shared by the unit tests: a funded caller and contracts deployed
+//! from raw bytecode, on an in-memory instance or a [`MockHost`].
+
+use std::ffi::{c_char, CStr, CString};
+
+use revm::{
+    bytecode::Bytecode,
+    primitives::{Address, Bytes, U256},
+    state::AccountInfo,
+};
+
+use crate::*;
+
+/// Sender of the test transactions, funded with one ether.
+pub(crate) const CALLER: Address = Address::repeat_byte(0xaa);
+/// Where [`instance_with`] and [`host_with`] deploy their code.
+pub(crate) const CONTRACT: Address = Address::repeat_byte(0xcc);
+
+/// Account holding `code`.
+pub(crate) fn contract(code: &[u8]) -> AccountInfo {
+    AccountInfo::from_bytecode(Bytecode::new_raw(Bytes::copy_from_slice(code)))
+}
+
+fn funded() -> AccountInfo {
+    AccountInfo::from_balance(U256::from(10u64.pow(18)))
+}
+
+/// In-memory instance with [`CALLER`] funded and each `(address, code)`
+/// deployed.  Free with `revm_free`.
+pub(crate) fn instance_with_contracts(contracts: &[(Address, &[u8])]) -> *mut RevmInstance {
+    unsafe {
+        let instance = revm_new();
+        let db = &mut (*instance).evm.ctx.journaled_state.database;
+        db.insert_account_info(CALLER, funded());
+        for (address, code) in contracts {
+            db.insert_account_info(*address, contract(code));
+        }
+        instance
+    }
+}
+
+/// In-memory instance with [`CALLER`] funded and `code` at [`CONTRACT`].
+pub(crate) fn instance_with(code: &[u8]) -> *mut RevmInstance {
+    instance_with_contracts(&[(CONTRACT, code)])
+}
+
+/// [`MockHost`] with [`CALLER`] funded and `code` at [`CONTRACT`].
+pub(crate) fn host_with(code: &[u8]) -> MockHost {
+    let host = MockHost::new();
+    host.insert_account(CALLER, funded());
+    host.insert_account(CONTRACT, contract(code));
+    host
+}
+
+/// `address` in hex, as the FFI functions take it.
+pub(crate) fn c_address(address: Address) -> CString {
+    CString::new(format!("{address:#x}")).unwrap()
+}
+
+/// Call `to` from [`CALLER`] with 100_000 gas through `revm_call_contract`.
+/// Free the result with `revm_free_execution_result`.
+pub(crate) unsafe fn call(instance: *mut RevmInstance, to: Address) -> *mut ExecutionResultFFI {
+    let (from, to) = (c_address(CALLER), c_address(to));
+    revm_call_contract(
+        instance,
+        from.as_ptr(),
+        to.as_ptr(),
+        std::ptr::null(),
+        0,
+        std::ptr::null(),
+        100_000,
+    )
+}
+
+/// Make the pending transaction a call of `to` from [`CALLER`] with `input`,
+/// 100_000 gas and neither value nor gas price.
+pub(crate) unsafe fn set_call(instance: *mut RevmInstance, to: Address, input: &[u8], nonce: u32) {
+    let (from, to, zero) = (
+        c_address(CALLER),
+        c_address(to),
+        CString::new("0x0").unwrap(),
+    );
+    let rc = revm_set_tx(
+        instance,
+        from.as_ptr(),
+        to.as_ptr(),
+        zero.as_ptr(),
+        input.as_ptr(),
+        input.len() as u32,
+        100_000,
+        zero.as_ptr(),
+        nonce,
+    );
+    assert_eq!(rc, 0);
+}
+
+/// [`set_call`] for a StateDB instance, without input.
+pub(crate) unsafe fn statedb_set_call(instance: *mut RevmInstanceStateDB, to: Address, nonce: u32) {
+    let (from, to, zero) = (
+        c_address(CALLER),
+        c_address(to),
+        CString::new("0x0").unwrap(),
+    );
+    let rc = revm_statedb_set_tx(
+        instance,
+        from.as_ptr(),
+        to.as_ptr(),
+        zero.as_ptr(),
+        std::ptr::null(),
+        0,
+        100_000,
+        zero.as_ptr(),
+        nonce,
+    );
+    assert_eq!(rc, 0);
+}
+
+/// Parse a JSON string returned through FFI and free it.
+pub(crate) unsafe fn take_json(json: *mut c_char) -> serde_json::Value {
+    assert!(!json.is_null());
+    let value = serde_json::from_str(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+    revm_free_string(json);
+    value
+}
@@ -0,0 +1,520 @@
+//! In-memory Merkle-Patricia trie with cached node encodings.
+//!
+//! Every node remembers its RLP encoding (and hash) until it, or something
+//! below it, is modified.  After a batch of updates `root_hash` therefore only
+//! re-encodes the nodes on the modified paths, which keeps state-root
+//! computation cheap when a transaction touches a handful of accounts.
+//!
+//! Keys are raw bytes (callers hash them for the "secure" state and storage
+//! tries); values are stored exactly as given, i.e. already RLP-encoded.
+
+use alloy_rlp::{Encodable, Header};
+use revm::primitives::{keccak256, Bytes, B256};
+
+/// Root hash of an empty trie (`keccak256(rlp(""))`).
+pub const EMPTY_ROOT_HASH: B256 = B256::new([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+]);
+
+/// Encoding cached on a clean node.
+#[derive(Clone, Debug)]
+struct Encoded {
+    /// Full RLP encoding of the node.
+    rlp: Vec<u8>,
+    /// How a parent refers to the node: the RLP itself if shorter than 32
+    /// bytes, otherwise the RLP-encoded keccak of it.
+    reference: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf {
+        path: Vec<u8>,
+        value: Vec<u8>,
+        cache: Option<Encoded>,
+    },
+    Extension {
+        path: Vec<u8>,
+        child: Box<Node>,
+        cache: Option<Encoded>,
+    },
+    Branch {
+        children: Box<[Node; 16]>,
+        value: Option<Vec<u8>>,
+        cache: Option<Encoded>,
+    },
+}
+
+/// Merkle-Patricia trie over byte keys.
+#[derive(Clone, Debug, Default)]
+pub struct Trie {
+    root: Node,
+    len: usize,
+}
+
+impl Trie {
+    /// Create an empty trie.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of key/value pairs stored.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the trie holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Look up the value stored under `key`.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        let nibbles = to_nibbles(key);
+        let mut node = &self.root;
+        let mut path = nibbles.as_slice();
+        loop {
+            match node {
+                Node::Empty => return None,
+                Node::Leaf { path: leaf, value, .. } => {
+                    return (leaf.as_slice() == path).then_some(value.as_slice());
+                }
+                Node::Extension { path: ext, child, .. } => {
+                    path = path.strip_prefix(ext.as_slice())?;
+                    node = child;
+                }
+                Node::Branch { children, value, .. } => match path.split_first() {
+                    None => return value.as_deref(),
+                    Some((nibble, rest)) => {
+                        node = &children[*nibble as usize];
+                        path = rest;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Insert or replace `key`.  An empty `value` removes the key, as in geth.
+    pub fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+        if value.is_empty() {
+            self.remove(key);
+            return;
+        }
+        let nibbles = to_nibbles(key);
+        if insert(&mut self.root, &nibbles, value) {
+            self.len += 1;
+        }
+    }
+
+    /// Remove `key`, returning whether it was present.
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        let nibbles = to_nibbles(key);
+        let removed = remove(&mut self.root, &nibbles);
+        if removed {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    /// Root hash, re-encoding only nodes changed since the last call.
+    pub fn root_hash(&mut self) -> B256 {
+        match &self.root {
+            Node::Empty => EMPTY_ROOT_HASH,
+            _ => keccak256(&encode(&mut self.root).rlp),
+        }
+    }
+
+    /// RLP-encoded nodes on the path to `key`, root first, in the form used
+    /// by `eth_getProof`.  Nodes embedded in their parent are not listed
+    /// separately.  For an absent key the proof ends where the path diverges.
+    pub fn proof(&mut self, key: &[u8]) -> Vec<Bytes> {
+        // Make sure every node on the path has a cached encoding.
+        self.root_hash();
+
+        let nibbles = to_nibbles(key);
+        let mut proof = Vec::new();
+        let mut node = &self.root;
+        let mut path = nibbles.as_slice();
+        let mut is_root = true;
+        loop {
+            let encoded = match node {
+                Node::Empty => break,
+                Node::Leaf { cache, .. }
+                | Node::Extension { cache, .. }
+                | Node::Branch { cache, .. } => cache.as_ref().expect("encoded above"),
+            };
+            if is_root || encoded.rlp.len() >= 32 {
+                proof.push(Bytes::copy_from_slice(&encoded.rlp));
+            }
+            is_root = false;
+
+            match node {
+                Node::Extension { path: ext, child, .. } => match path.strip_prefix(ext.as_slice()) {
+                    Some(rest) => {
+                        path = rest;
+                        node = child;
+                    }
+                    None => break,
+                },
+                Node::Branch { children, .. } => match path.split_first() {
+                    Some((nibble, rest)) => {
+                        path = rest;
+                        node = &children[*nibble as usize];
+                    }
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+        proof
+    }
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn empty_children() -> Box<[Node; 16]> {
+    Box::new(std::array::from_fn(|_| Node::Empty))
+}
+
+fn leaf(path: &[u8], value: Vec<u8>) -> Node {
+    Node::Leaf { path: path.to_vec(), value, cache: None }
+}
+
+/// Place `node` under a new branch slot, consuming `path[0]` as the index.
+fn set_branch_child(children: &mut [Node; 16], path: &[u8], node: Node) {
+    children[path[0] as usize] = node;
+}
+
+/// Returns `true` if a new key was added (as opposed to replaced).
+fn insert(node: &mut Node, path: &[u8], value: Vec<u8>) -> bool {
+    match std::mem::take(node) {
+        Node::Empty => {
+            *node = leaf(path, value);
+            true
+        }
+        Node::Leaf { path: leaf_path, value: leaf_value, .. } => {
+            let common = common_prefix(&leaf_path, path);
+            if common == leaf_path.len() && common == path.len() {
+                *node = leaf(path, value);
+                return false;
+            }
+
+            let mut children = empty_children();
+            let mut branch_value = None;
+            if common == leaf_path.len() {
+                branch_value = Some(leaf_value);
+            } else {
+                let rest = &leaf_path[common..];
+                set_branch_child(&mut children, rest, leaf(&rest[1..], leaf_value));
+            }
+            if common == path.len() {
+                branch_value = Some(value);
+            } else {
+                let rest = &path[common..];
+                set_branch_child(&mut children, rest, leaf(&rest[1..], value));
+            }
+
+            *node = wrap_in_extension(&path[..common], Node::Branch { children, value: branch_value, cache: None });
+            true
+        }
+        Node::Extension { path: ext_path, mut child, .. } => {
+            let common = common_prefix(&ext_path, path);
+            if common == ext_path.len() {
+                let added = insert(&mut child, &path[common..], value);
+                *node = Node::Extension { path: ext_path, child, cache: None };
+                return added;
+            }
+
+            let mut children = empty_children();
+            let ext_rest = &ext_path[common..];
+            let old = if ext_rest.len() == 1 {
+                *child
+            } else {
+                Node::Extension { path: ext_rest[1..].to_vec(), child, cache: None }
+            };
+            set_branch_child(&mut children, ext_rest, old);
+
+            let mut branch_value = None;
+            if common == path.len() {
+                branch_value = Some(value);
+            } else {
+                let rest = &path[common..];
+                set_branch_child(&mut children, rest, leaf(&rest[1..], value));
+            }
+
+            *node = wrap_in_extension(&path[..common], Node::Branch { children, value: branch_value, cache: None });
+            true
+        }
+        Node::Branch { mut children, value: branch_value, .. } => {
+            let added = match path.split_first() {
+                None => {
+                    let added = branch_value.is_none();
+                    *node = Node::Branch { children, value: Some(value), cache: None };
+                    return added;
+                }
+                Some((nibble, rest)) => insert(&mut children[*nibble as usize], rest, value),
+            };
+            *node = Node::Branch { children, value: branch_value, cache: None };
+            added
+        }
+    }
+}
+
+fn wrap_in_extension(path: &[u8], node: Node) -> Node {
+    if path.is_empty() {
+        node
+    } else {
+        Node::Extension { path: path.to_vec(), child: Box::new(node), cache: None }
+    }
+}
+
+/// Prepend `prefix` to the path of `node`, collapsing a removed branch level.
+fn prefix_node(prefix: &[u8], node: Node) -> Node {
+    match node {
+        Node::Leaf { path, value, .. } => Node::Leaf { path: [prefix, &path].concat(), value, cache: None },
+        Node::Extension { path, child, .. } => {
+            Node::Extension { path: [prefix, &path].concat(), child, cache: None }
+        }
+        Node::Empty => Node::Empty,
+        branch @ Node::Branch { .. } => wrap_in_extension(prefix, branch),
+    }
+}
+
+/// Returns `true` if the key was present.  Leaves the subtree untouched
+/// (caches included) if it was not.
+fn remove(node: &mut Node, path: &[u8]) -> bool {
+    match node {
+        Node::Empty => false,
+        Node::Leaf { path: leaf_path, .. } => {
+            if leaf_path.as_slice() != path {
+                return false;
+            }
+            *node = Node::Empty;
+            true
+        }
+        Node::Extension { path: ext_path, child, .. } => {
+            let Some(rest) = path.strip_prefix(ext_path.as_slice()) else {
+                return false;
+            };
+            if !remove(child, rest) {
+                return false;
+            }
+            let Node::Extension { path: ext_path, child, .. } = std::mem::take(node) else {
+                unreachable!()
+            };
+            *node = prefix_node(&ext_path, *child);
+            true
+        }
+        Node::Branch { children, value, cache } => {
+            let removed = match path.split_first() {
+                None => value.take().is_some(),
+                Some((nibble, rest)) => remove(&mut children[*nibble as usize], rest),
+            };
+            if !removed {
+                return false;
+            }
+            *cache = None;
+
+            let mut occupied = children
+                .iter()
+                .enumerate()
+                .filter(|(_, child)| !matches!(child, Node::Empty))
+                .map(|(i, _)| i);
+            let first = occupied.next();
+            let more = occupied.next().is_some();
+            match (first, more, value.is_some()) {
+                (None, _, true) => {
+                    let value = value.take().unwrap_or_default();
+                    *node = leaf(&[], value);
+                }
+                (Some(index), false, false) => {
+                    let child = std::mem::take(&mut children[index]);
+                    *node = prefix_node(&[index as u8], child);
+                }
+                (None, _, false) => *node = Node::Empty,
+                _ => {}
+            }
+            true
+        }
+    }
+}
+
+/// Hex-prefix encoding of a nibble path.
+fn compact(path: &[u8], is_leaf: bool) -> Vec<u8> {
+    let flag = if is_leaf { 2 } else { 0 };
+    let mut out = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        out.push(((flag + 1) << 4) | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    out.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    out
+}
+
+fn encode_list(payload: Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 4);
+    Header { list: true, payload_length: payload.len() }.encode(&mut out);
+    out.extend(payload);
+    out
+}
+
+fn child_reference(child: &mut Node, out: &mut Vec<u8>) {
+    match child {
+        Node::Empty => out.push(alloy_rlp::EMPTY_STRING_CODE),
+        _ => out.extend_from_slice(&encode(child).reference),
+    }
+}
+
+fn encode(node: &mut Node) -> &Encoded {
+    let rlp = match node {
+        Node::Empty => unreachable!("empty nodes are never encoded"),
+        Node::Leaf { cache: Some(_), .. }
+        | Node::Extension { cache: Some(_), .. }
+        | Node::Branch { cache: Some(_), .. } => None,
+        Node::Leaf { path, value, .. } => {
+            let mut payload = Vec::new();
+            compact(path, true).as_slice().encode(&mut payload);
+            value.as_slice().encode(&mut payload);
+            Some(encode_list(payload))
+        }
+        Node::Extension { path, child, .. } => {
+            let mut payload = Vec::new();
+            compact(path, false).as_slice().encode(&mut payload);
+            child_reference(child, &mut payload);
+            Some(encode_list(payload))
+        }
+        Node::Branch { children, value, .. } => {
+            let mut payload = Vec::new();
+            for child in children.iter_mut() {
+                child_reference(child, &mut payload);
+            }
+            match value {
+                Some(value) => value.as_slice().encode(&mut payload),
+                None => payload.push(alloy_rlp::EMPTY_STRING_CODE),
+            }
+            Some(encode_list(payload))
+        }
+    };
+
+    let cache = match node {
+        Node::Leaf { cache, .. } | Node::Extension { cache, .. } | Node::Branch { cache, .. } => cache,
+        Node::Empty => unreachable!(),
+    };
+    if let Some(rlp) = rlp {
+        let reference = if rlp.len() < 32 {
+            rlp.clone()
+        } else {
+            let mut reference = Vec::with_capacity(33);
+            keccak256(&rlp).encode(&mut reference);
+            reference
+        };
+        *cache = Some(Encoded { rlp, reference });
+    }
+    cache.as_ref().expect("just encoded")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn empty_root() {
+        assert_eq!(Trie::new().root_hash(), EMPTY_ROOT_HASH);
+        assert_eq!(EMPTY_ROOT_HASH, keccak256([alloy_rlp::EMPTY_STRING_CODE]));
+    }
+
+    #[test]
+    fn geth_insert_vector() {
+        // go-ethereum trie/trie_test.go TestInsert
+        let mut trie = Trie::new();
+        trie.insert(b"doe", b"reindeer".to_vec());
+        trie.insert(b"dog", b"puppy".to_vec());
+        trie.insert(b"dogglesworth", b"cat".to_vec());
+        let expected =
+            B256::from_str("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3").unwrap();
+        assert_eq!(trie.root_hash(), expected);
+        assert_eq!(trie.get(b"dog"), Some(&b"puppy"[..]));
+        assert_eq!(trie.len(), 3);
+    }
+
+    #[test]
+    fn removal_restores_previous_root() {
+        let mut trie = Trie::new();
+        trie.insert(b"doe", b"reindeer".to_vec());
+        trie.insert(b"dog", b"puppy".to_vec());
+        let before = trie.root_hash();
+
+        trie.insert(b"dogglesworth", b"cat".to_vec());
+        trie.insert(b"do", b"verb".to_vec());
+        assert_ne!(trie.root_hash(), before);
+
+        assert!(trie.remove(b"dogglesworth"));
+        assert!(trie.remove(b"do"));
+        assert!(!trie.remove(b"missing"));
+        assert_eq!(trie.root_hash(), before);
+
+        trie.insert(b"doe", Vec::new());
+        trie.insert(b"dog", Vec::new());
+        assert!(trie.is_empty());
+        assert_eq!(trie.root_hash(), EMPTY_ROOT_HASH);
+    }
+
+    #[test]
+    fn insertion_order_does_not_matter() {
+        let keys: Vec<B256> = (0u64..300).map(|i| keccak256(i.to_be_bytes())).collect();
+        let mut forward = Trie::new();
+        for (i, key) in keys.iter().enumerate() {
+            forward.insert(key.as_slice(), vec![(i % 250) as u8 + 1; i % 40 + 1]);
+            if i % 50 == 0 {
+                forward.root_hash();
+            }
+        }
+        let mut backward = Trie::new();
+        for (i, key) in keys.iter().enumerate().rev() {
+            backward.insert(key.as_slice(), vec![(i % 250) as u8 + 1; i % 40 + 1]);
+        }
+        assert_eq!(forward.root_hash(), backward.root_hash());
+    }
+
+    #[test]
+    fn matches_reference_builder_after_updates() {
+        use alloy_trie::{HashBuilder, Nibbles};
+        use std::collections::BTreeMap;
+
+        let mut trie = Trie::new();
+        let mut expected = BTreeMap::new();
+        for round in 0u64..5 {
+            for i in 0u64..400 {
+                let key = keccak256((i * 7 + round).to_be_bytes());
+                if (i + round) % 3 == 0 {
+                    trie.remove(key.as_slice());
+                    expected.remove(&key);
+                } else {
+                    let value = vec![(i % 200) as u8 + 1; (i % 50) as usize + 1];
+                    trie.insert(key.as_slice(), value.clone());
+                    expected.insert(key, value);
+                }
+            }
+
+            let mut builder = HashBuilder::default();
+            for (key, value) in &expected {
+                builder.add_leaf(Nibbles::unpack(key), value);
+            }
+            assert_eq!(trie.root_hash(), builder.root(), "round {round}");
+            assert_eq!(trie.len(), expected.len());
+        }
+    }
+}
@@ -1,17 +1,35 @@
 //! FFI-compatible types for REVM
 
+use std::collections::BTreeMap;
 use std::os::raw::{c_char, c_int, c_uint};
 use revm::{
     database::CacheDB,
-    database_interface::EmptyDB,
     handler::MainnetEvm,
 };
 
+use crate::fork::ForkedDB;
+use crate::inspect::InspectContext;
+
+/// EVM of an in-memory instance, over a write overlay on its fork layers
+pub type InstanceEvm = MainnetEvm<InspectContext<CacheDB<ForkedDB>>>;
+
 /// Main REVM instance structure
 #[repr(C)]
 pub struct RevmInstance {
-    pub evm: MainnetEvm<revm::Context<revm::context::BlockEnv, revm::context::TxEnv, revm::context::CfgEnv, CacheDB<EmptyDB>, revm::Journal<CacheDB<EmptyDB>>, ()>>,
+    pub evm: InstanceEvm,
     pub last_error: Option<String>,
+    /// Checkpoints taken with `revm_snapshot`, keyed by id
+    pub snapshots: BTreeMap<u64, crate::snapshot::InstanceSnapshot>,
+    /// Id handed out by the next `revm_snapshot` call
+    pub next_snapshot_id: u64,
+    /// Incrementally maintained state trie, built on the first root request
+    pub state_trie: Option<crate::state_root::StateTrie>,
+    /// Inspectors applied to every execution (EIP-3155 tracer, gas profiler,
+    /// host callbacks, streamed logs, revert points)
+    pub inspectors: crate::inspect::AttachedInspectors,
+    /// Witness of the executions committed through `revm_execute_with_witness`
+    /// since `revm_begin_block_witness`
+    pub block_witness: Option<crate::witness::Witness>,
 }
 
 /// FFI-compatible execution result
@@ -80,6 +98,18 @@ impl Default for RevmConfigFFI {
     }
 }
 
+/// Options for EIP-3155 tracing; all-false captures everything
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceOptionsFFI {
+    /// Omit the `memory` field
+    pub disable_memory: bool,
+    /// Report `stack` as null
+    pub disable_stack: bool,
+    /// Omit the `storage` field (geth never prints it)
+    pub disable_storage: bool,
+}
+
 /// Predefined chain configurations
 #[repr(C)]
 pub enum ChainPreset {
@@ -7,17 +7,16 @@ use std::slice;
 use anyhow::{anyhow, Result};
 use revm::{
     context_interface::{
-        result::{ExecutionResult, HaltReason, Output},
+        result::{ExecutionResult, HaltReason, Output, ResultAndState},
         context::ContextTr,
         journaled_state::JournalTr,
     },
     handler::{EvmTr, ExecuteCommitEvm},
     primitives::{Address, Bytes, TxKind, U256},
     database_interface::Database,
-    context::{CfgEnv, Context},
+    context::{CfgEnv, Context, TxEnv},
     database::{CacheDB, EmptyDB},
     state::AccountInfo,
-    ExecuteEvm,
     handler::MainnetEvm,
 };
 
@@ -148,7 +147,7 @@ pub fn convert_execution_result(result: ExecutionResult<HaltReason>) -> Executio
 
 /// Set transaction parameters
 pub unsafe fn set_transaction_params(
-    instance: &mut RevmInstance,
+    tx: &mut TxEnv,
     caller: *const c_char,
     to: *const c_char,
     value: *const c_char,
@@ -186,15 +185,13 @@ pub unsafe fn set_transaction_params(
         hex_to_u256(&c_str_to_string(gas_price)?)?.try_into().unwrap_or(1_000_000_000u128)
     };
 
-    instance.evm.ctx().modify_tx(|tx| {
-        tx.caller = caller_addr;
-        tx.kind = kind;
-        tx.value = value;
-        tx.data = data;
-        tx.gas_limit = gas_limit as u64;
-        tx.gas_price = gas_price;
-        tx.nonce = nonce as u64;
-    });
+    tx.caller = caller_addr;
+    tx.kind = kind;
+    tx.value = value;
+    tx.data = data;
+    tx.gas_limit = gas_limit as u64;
+    tx.gas_price = gas_price;
+    tx.nonce = nonce as u64;
 
     Ok(())
 }
@@ -234,7 +231,8 @@ pub unsafe fn deploy_contract_impl(
         tx.chain_id = Some(chain_id);
     });
 
-    let result = instance.evm.replay_commit()?;
+    let ResultAndState { result, state } = instance.replay_pending()?;
+    instance.commit_state(state);
     
     match result {
         ExecutionResult::Success { gas_used, output, .. } => {
@@ -293,6 +291,7 @@ pub unsafe fn set_balance_impl(
         code_hash: revm::primitives::KECCAK_EMPTY,
         code: Some(revm::bytecode::Bytecode::default()),
     });
+    instance.touch_account(addr);
     
     Ok(())
 }
@@ -323,6 +322,7 @@ pub unsafe fn set_storage_impl(
     
     let db = instance.evm.ctx().journal().db();
     db.insert_account_storage(addr, slot_u256, value_u256)?;
+    instance.touch_slot(addr, slot_u256);
     
     Ok(())
 }
@@ -353,6 +353,7 @@ pub unsafe fn set_nonce_impl(
     };
     
     db.insert_account_info(addr, account_info);
+    instance.touch_account(addr);
     Ok(())
 }
 
@@ -405,7 +406,8 @@ pub unsafe fn transfer_impl(
         tx.chain_id = Some(chain_id);
     });
 
-    let result = instance.evm.replay_commit()?;
+    let ResultAndState { result, state } = instance.replay_pending()?;
+    instance.commit_state(state);
     Ok(convert_execution_result(result))
 }
 
@@ -458,7 +460,8 @@ pub unsafe fn call_contract_impl(
         tx.chain_id = Some(chain_id);
     });
 
-    let result = instance.evm.replay_commit()?;
+    let ResultAndState { result, state } = instance.replay_pending()?;
+    instance.commit_state(state);
     Ok(convert_execution_result(result))
 }
 
@@ -505,6 +508,6 @@ pub unsafe fn view_call_contract_impl(
     });
 
     // Use replay() instead of replay_commit() for view calls
-    let result = instance.evm.replay()?;
+    let result = instance.replay_pending()?;
     Ok(convert_execution_result(result.result))
 } 
\ No newline at end of file
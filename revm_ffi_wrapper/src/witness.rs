@@ -0,0 +1,547 @@
+//! Execution witnesses and stateless execution.
+//!
+//! [`WitnessDB`] wraps any database and remembers the first value of every
+//! account, storage slot, bytecode and block hash read through it, which is
+//! the pre-state an execution depends on.  [`StatelessDB`] serves an
+//! execution from such a [`Witness`] alone and fails on any access outside
+//! it.
+//!
+//! A block witness is accumulated on the instance between
+//! `revm_begin_block_witness` and `revm_take_block_witness`: the witness of
+//! every execution committed through `revm_execute_with_witness` (or its
+//! StateDB counterpart) is [merged](Witness::merge) into it in order.
+//! Merging keeps the first value seen, and an execution reads every account
+//! and slot before writing it, so a key written by an earlier transaction
+//! already holds its pre-block value.  Uncommitted executions are left out,
+//! and so are commits through any other entry point (`revm_execute_commit`,
+//! `revm_call_contract`, tracers, replay): their reads are not recorded, so
+//! a block containing them cannot be re-executed from the block witness.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::{error::Error, fmt};
+
+use anyhow::{anyhow, Result};
+use revm::{
+    bytecode::Bytecode,
+    context::{BlockEnv, CfgEnv, TxEnv},
+    context_interface::result::{ExecutionResult, ResultAndState},
+    database_interface::{DBErrorMarker, Database, DatabaseCommit, DatabaseRef},
+    handler::ExecuteEvm,
+    primitives::{Address, Bytes, StorageKey, StorageValue, B256, KECCAK_EMPTY, U256},
+    state::AccountInfo,
+    Context, Journal, MainBuilder,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::types::RevmInstance;
+use crate::RevmInstanceStateDB;
+
+/// Account fields as read, without code (kept in [`Witness::codes`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WitnessAccount {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code_hash: B256,
+}
+
+/// Everything an execution read, with the values it saw.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Witness {
+    /// `None` for accounts that did not exist.
+    pub accounts: BTreeMap<Address, Option<WitnessAccount>>,
+    pub storage: BTreeMap<Address, BTreeMap<U256, U256>>,
+    pub codes: BTreeMap<B256, Bytes>,
+    pub block_hashes: BTreeMap<u64, B256>,
+}
+
+impl Witness {
+    /// Add the reads of `other` that this witness does not already hold, e.g.
+    /// to combine per-transaction witnesses taken against the same state.
+    pub fn merge(&mut self, other: Witness) {
+        for (address, account) in other.accounts {
+            self.accounts.entry(address).or_insert(account);
+        }
+        for (address, slots) in other.storage {
+            let storage = self.storage.entry(address).or_default();
+            for (slot, value) in slots {
+                storage.entry(slot).or_insert(value);
+            }
+        }
+        for (hash, code) in other.codes {
+            self.codes.entry(hash).or_insert(code);
+        }
+        for (number, hash) in other.block_hashes {
+            self.block_hashes.entry(number).or_insert(hash);
+        }
+    }
+
+    fn record_account(&mut self, address: Address, info: &Option<AccountInfo>) {
+        self.accounts.entry(address).or_insert_with(|| {
+            info.as_ref().map(|info| WitnessAccount {
+                balance: info.balance,
+                nonce: info.nonce,
+                code_hash: info.code_hash,
+            })
+        });
+        // Code delivered with the account is never requested by hash.
+        if let Some(AccountInfo {
+            code_hash,
+            code: Some(code),
+            ..
+        }) = info
+        {
+            self.record_code(*code_hash, code);
+        }
+    }
+
+    fn record_code(&mut self, code_hash: B256, code: &Bytecode) {
+        if code_hash != KECCAK_EMPTY && !code.is_empty() {
+            self.codes
+                .entry(code_hash)
+                .or_insert_with(|| code.original_bytes());
+        }
+    }
+}
+
+/// Database wrapper that collects a [`Witness`] of everything read.
+#[derive(Debug)]
+pub struct WitnessDB<DB> {
+    inner: DB,
+    witness: Mutex<Witness>,
+}
+
+impl<DB> WitnessDB<DB> {
+    pub fn new(inner: DB) -> Self {
+        Self {
+            inner,
+            witness: Mutex::new(Witness::default()),
+        }
+    }
+
+    /// The reads collected so far.
+    pub fn witness(&self) -> Witness {
+        self.witness.lock().unwrap().clone()
+    }
+
+    /// Unwrap into the inner database and the collected witness.
+    pub fn into_parts(self) -> (DB, Witness) {
+        (self.inner, self.witness.into_inner().unwrap())
+    }
+}
+
+impl<DB: DatabaseRef> DatabaseRef for WitnessDB<DB> {
+    type Error = DB::Error;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        let info = self.inner.basic_ref(address)?;
+        self.witness.lock().unwrap().record_account(address, &info);
+        Ok(info)
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        let code = self.inner.code_by_hash_ref(code_hash)?;
+        self.witness.lock().unwrap().record_code(code_hash, &code);
+        Ok(code)
+    }
+
+    fn storage_ref(
+        &self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        let value = self.inner.storage_ref(address, index)?;
+        self.witness
+            .lock()
+            .unwrap()
+            .storage
+            .entry(address)
+            .or_default()
+            .entry(index)
+            .or_insert(value);
+        Ok(value)
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        let hash = self.inner.block_hash_ref(number)?;
+        self.witness
+            .lock()
+            .unwrap()
+            .block_hashes
+            .entry(number)
+            .or_insert(hash);
+        Ok(hash)
+    }
+}
+
+impl<DB: DatabaseRef> Database for WitnessDB<DB> {
+    type Error = DB::Error;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.basic_ref(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.code_by_hash_ref(code_hash)
+    }
+
+    fn storage(
+        &mut self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        self.storage_ref(address, index)
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<B256, Self::Error> {
+        self.block_hash_ref(number)
+    }
+}
+
+/// Access outside the witness a [`StatelessDB`] was built from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WitnessError(pub String);
+
+impl fmt::Display for WitnessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for WitnessError {}
+
+impl DBErrorMarker for WitnessError {}
+
+/// Database holding nothing but a [`Witness`].
+#[derive(Clone, Debug, Default)]
+pub struct StatelessDB {
+    witness: Witness,
+}
+
+impl StatelessDB {
+    pub fn new(witness: Witness) -> Self {
+        Self { witness }
+    }
+
+    pub fn witness(&self) -> &Witness {
+        &self.witness
+    }
+}
+
+impl DatabaseRef for StatelessDB {
+    type Error = WitnessError;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        let account = self
+            .witness
+            .accounts
+            .get(&address)
+            .ok_or_else(|| WitnessError(format!("account {address} is not in the witness")))?;
+        Ok(account.map(|account| AccountInfo {
+            balance: account.balance,
+            nonce: account.nonce,
+            code_hash: account.code_hash,
+            code: None,
+        }))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        if code_hash == KECCAK_EMPTY {
+            return Ok(Bytecode::default());
+        }
+        self.witness
+            .codes
+            .get(&code_hash)
+            .map(|code| Bytecode::new_raw(code.clone()))
+            .ok_or_else(|| WitnessError(format!("code {code_hash} is not in the witness")))
+    }
+
+    fn storage_ref(
+        &self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        self.witness
+            .storage
+            .get(&address)
+            .and_then(|slots| slots.get(&index))
+            .copied()
+            .ok_or_else(|| {
+                WitnessError(format!(
+                    "slot {} of {address} is not in the witness",
+                    B256::from(index)
+                ))
+            })
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        self.witness
+            .block_hashes
+            .get(&number)
+            .copied()
+            .ok_or_else(|| WitnessError(format!("hash of block {number} is not in the witness")))
+    }
+}
+
+impl Database for StatelessDB {
+    type Error = WitnessError;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        self.basic_ref(address)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        self.code_by_hash_ref(code_hash)
+    }
+
+    fn storage(
+        &mut self,
+        address: Address,
+        index: StorageKey,
+    ) -> Result<StorageValue, Self::Error> {
+        self.storage_ref(address, index)
+    }
+
+    fn block_hash(&mut self, number: u64) -> Result<B256, Self::Error> {
+        self.block_hash_ref(number)
+    }
+}
+
+/// Run one transaction on a throwaway EVM over `db`.
+fn transact<DB: Database>(
+    db: DB,
+    cfg: &CfgEnv,
+    block: &BlockEnv,
+    tx: &TxEnv,
+) -> Result<ResultAndState> {
+    Context::<BlockEnv, TxEnv, CfgEnv, DB, Journal<DB>, ()>::new(db, cfg.spec)
+        .with_cfg(cfg.clone())
+        .with_block(block.clone())
+        .with_tx(tx.clone())
+        .build_mainnet()
+        .replay()
+        .map_err(|e| anyhow!("Execution failed: {e}"))
+}
+
+/// Run one transaction over `db`, returning the outcome together with the
+/// witness of what it read.
+fn transact_witnessed<DB: DatabaseRef>(
+    db: &DB,
+    cfg: &CfgEnv,
+    block: &BlockEnv,
+    tx: &TxEnv,
+) -> Result<(ResultAndState, Witness)> {
+    let mut db = WitnessDB::new(db);
+    let outcome = transact(&mut db, cfg, block, tx);
+    Ok((outcome?, db.into_parts().1))
+}
+
+/// Add the witness of a committed execution to the block witness, if one
+/// is being collected.
+fn accumulate(block_witness: &mut Option<Witness>, witness: &Witness) {
+    if let Some(block_witness) = block_witness {
+        block_witness.merge(witness.clone());
+    }
+}
+
+impl RevmInstance {
+    /// Execute the pending transaction and return its result together with
+    /// the witness of the state it read.  State changes are committed when
+    /// `commit` is set, and the witness then joins the block witness.
+    pub fn execute_with_witness(&mut self, commit: bool) -> Result<(ExecutionResult, Witness)> {
+        let ctx = &self.evm.ctx;
+        let (ResultAndState { result, state }, witness) =
+            transact_witnessed(&ctx.journaled_state.database, &ctx.cfg, &ctx.block, &ctx.tx)?;
+        if commit {
+            self.commit_state(state);
+            accumulate(&mut self.block_witness, &witness);
+        }
+        Ok((result, witness))
+    }
+
+    /// Execute the pending transaction against `witness` only, leaving the
+    /// instance's own state untouched.
+    pub fn execute_stateless(&self, witness: Witness) -> Result<ExecutionResult> {
+        let ctx = &self.evm.ctx;
+        transact(StatelessDB::new(witness), &ctx.cfg, &ctx.block, &ctx.tx).map(|r| r.result)
+    }
+}
+
+impl RevmInstanceStateDB {
+    /// [`RevmInstance::execute_with_witness`] against the Go StateDB, which
+    /// receives the changes when `commit` is set.
+    pub fn execute_with_witness(&mut self, commit: bool) -> Result<(ExecutionResult, Witness)> {
+        let recorder = crate::record_execution(&self.evm.ctx);
+        let ctx = &self.evm.ctx;
+        let outcome =
+            transact_witnessed(&ctx.journaled_state.database, &ctx.cfg, &ctx.block, &ctx.tx);
+        crate::record_result(
+            recorder,
+            &outcome.as_ref().map(|(outcome, _)| outcome.clone()),
+        );
+        let (ResultAndState { result, state }, witness) = outcome?;
+        if commit {
+            self.evm.ctx.journaled_state.database.commit(state);
+            accumulate(&mut self.block_witness, &witness);
+        }
+        Ok((result, witness))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::*;
+    use crate::*;
+    use revm::database_interface::DatabaseRef;
+    use revm::primitives::{keccak256, Address, Bytes, B256, U256};
+    use revm::state::AccountInfo;
+    use std::ffi::CString;
+
+    /// `SLOAD(1); BLOCKHASH(9); BALANCE(0xbb); STOP`
+    const RUNTIME: [u8; 13] = [
+        0x60, 0x01, 0x54, 0x50, 0x60, 0x09, 0x40, 0x50, 0x60, 0xbb, 0x31, 0x50, 0x00,
+    ];
+
+    /// `SSTORE(1, SLOAD(1) + 1); STOP`
+    const COUNTER: [u8; 10] = [0x60, 0x01, 0x54, 0x60, 0x01, 0x01, 0x60, 0x01, 0x55, 0x00];
+
+    #[test]
+    fn witness_drives_stateless_execution() {
+        let (caller, contract) = (CALLER, CONTRACT);
+        let other = Address::with_last_byte(0xbb);
+
+        unsafe {
+            let instance = instance_with(&RUNTIME);
+            let inst = &mut *instance;
+            let db = &mut inst.evm.ctx.journaled_state.database;
+            db.insert_account_storage(contract, U256::from(1), U256::from(7))
+                .unwrap();
+            db.insert_account_info(other, AccountInfo::from_balance(U256::from(5)));
+            inst.evm.ctx.block.number = 10;
+            inst.set_block_hash(9, B256::repeat_byte(9));
+            set_call(instance, contract, &[], 0);
+
+            let (result, witness) = inst.execute_with_witness(false).unwrap();
+            assert!(result.is_success());
+            assert_eq!(witness.accounts[&other].unwrap().balance, U256::from(5));
+            assert_eq!(witness.accounts[&caller].unwrap().nonce, 0);
+            assert_eq!(witness.storage[&contract][&U256::from(1)], U256::from(7));
+            assert_eq!(
+                witness.codes[&keccak256(RUNTIME)],
+                Bytes::from_static(&RUNTIME)
+            );
+            assert_eq!(witness.block_hashes[&9], B256::repeat_byte(9));
+            assert!(
+                witness.accounts.contains_key(&Address::ZERO),
+                "coinbase is read"
+            );
+
+            // The same transaction from the witness alone, through FFI.
+            let json = CString::new(serde_json::to_string(&witness).unwrap()).unwrap();
+            let stateless = revm_execute_stateless(instance, json.as_ptr());
+            assert!(!stateless.is_null());
+            assert_eq!(u64::from((*stateless).gas_used), result.gas_used());
+            revm_free_execution_result(stateless);
+
+            let mut partial = witness.clone();
+            partial.storage.clear();
+            let err = inst.execute_stateless(partial).unwrap_err().to_string();
+            assert!(
+                err.contains(&format!("slot {} of {contract}", B256::from(U256::from(1)))),
+                "{err}"
+            );
+
+            // Collected through FFI, with the changes committed.
+            let parsed = take_json(revm_execute_with_witness(instance, true));
+            let collected: Witness = serde_json::from_value(parsed["witness"].clone()).unwrap();
+            assert_eq!(collected, witness);
+            assert_eq!(parsed["gasUsed"], result.gas_used());
+            assert_eq!(
+                inst.evm
+                    .ctx
+                    .journaled_state
+                    .database
+                    .basic_ref(caller)
+                    .unwrap()
+                    .unwrap()
+                    .nonce,
+                1
+            );
+            revm_free(instance);
+        }
+    }
+
+    unsafe fn take_witness(json: *mut std::ffi::c_char) -> Witness {
+        serde_json::from_value(take_json(json)).unwrap()
+    }
+
+    #[test]
+    fn block_witness_keeps_pre_block_values() {
+        let (caller, contract) = (CALLER, CONTRACT);
+
+        unsafe {
+            let instance = instance_with(&COUNTER);
+            let inst = &mut *instance;
+            let db = &mut inst.evm.ctx.journaled_state.database;
+            db.insert_account_storage(contract, U256::from(1), U256::from(7))
+                .unwrap();
+
+            assert!(revm_take_block_witness(instance).is_null());
+            assert_eq!(revm_begin_block_witness(instance), 0);
+            // Not committed, so not part of the block.
+            set_call(instance, contract, &[], 0);
+            inst.execute_with_witness(false).unwrap();
+            for nonce in 0..2 {
+                set_call(instance, contract, &[], nonce);
+                let (result, _) = inst.execute_with_witness(true).unwrap();
+                assert!(result.is_success());
+            }
+            let block = take_witness(revm_take_block_witness(instance));
+            assert!(revm_take_block_witness(instance).is_null());
+
+            // Values from before the block, not after the first transaction.
+            assert_eq!(block.storage[&contract][&U256::from(1)], U256::from(7));
+            assert_eq!(block.accounts[&caller].unwrap().nonce, 0);
+            assert_eq!(
+                inst.evm
+                    .ctx
+                    .journaled_state
+                    .database
+                    .storage_ref(contract, U256::from(1)),
+                Ok(U256::from(9))
+            );
+            revm_free(instance);
+        }
+    }
+
+    #[test]
+    fn witnesses_statedb_blocks() {
+        let (caller, contract) = (CALLER, CONTRACT);
+        let host = host_with(&COUNTER);
+        host.set_storage(contract, U256::from(1), U256::from(7));
+
+        unsafe {
+            let instance = revm_new_with_statedb(host.handle(), &RevmConfigFFI::default());
+
+            assert_eq!(revm_statedb_begin_block_witness(instance), 0);
+            for nonce in 0..2 {
+                statedb_set_call(instance, contract, nonce);
+                let parsed = take_json(revm_statedb_execute_with_witness(instance, true));
+                assert_eq!(parsed["success"], true);
+            }
+            let block = take_witness(revm_statedb_take_block_witness(instance));
+            assert_eq!(block.storage[&contract][&U256::from(1)], U256::from(7));
+            assert_eq!(block.accounts[&caller].unwrap().nonce, 0);
+            assert_eq!(
+                block.codes[&keccak256(COUNTER)],
+                Bytes::from_static(&COUNTER)
+            );
+            // The changes reached the host.
+            assert_eq!(host.storage(contract, U256::from(1)), U256::from(9));
+            assert_eq!(host.account(caller).unwrap().nonce, 2);
+            revm_free_statedb_instance(instance);
+        }
+    }
+}